@@ -0,0 +1,530 @@
+// Cost basis and profit and loss of the trades history and ledgers, in a single currency
+use super::{registry::AssetRegistry, BalanceAsset, Errors, LedgerEntry, LedgerType, Side, Trade};
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, VecDeque};
+
+// Volumes below this are taken as used up, to ignore the error of the float differences
+const VOLUME_TOLERANCE: f64 = 1e-10;
+
+// Lots disposed of first
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CostMethod {
+    Fifo,
+    Lifo,
+    // The cost of all the lots of the asset is averaged
+    Average,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lot {
+    pub asset: String,
+    // Trade or ledger entry of the acquisition
+    pub refid: String,
+    pub acquired: DateTime<Utc>,
+    pub volume: f64,
+    // Cost of the volume, fees included
+    pub cost: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disposal {
+    pub asset: String,
+    // Trade or ledger entry of the disposal
+    pub refid: String,
+    // Refid and time of the lot disposed of. None with the average cost method, or when there were no lots
+    // left for the volume
+    pub lot_refid: Option<String>,
+    pub acquired: Option<DateTime<Utc>>,
+    pub disposed: DateTime<Utc>,
+    pub volume: f64,
+    // Cost basis of the volume, fees included
+    pub cost: f64,
+    // Amount received for the volume, fees deducted
+    pub proceeds: f64,
+}
+
+impl Disposal {
+    pub fn gain(&self) -> f64 {
+        self.proceeds - self.cost
+    }
+}
+
+// Profit and loss of an asset
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssetPnl {
+    pub asset: String,
+    // Volume and cost basis of the lots held
+    pub volume: f64,
+    pub cost: f64,
+    pub realized: f64,
+    // Value of the volume held minus its cost, none without a price
+    pub unrealized: Option<f64>,
+}
+
+pub struct Accounting {
+    method: CostMethod,
+    // Kraken's name of the currency of the costs and proceeds, e.g. ZUSD
+    currency: String,
+    // Held lots by asset, oldest first
+    lots: HashMap<String, VecDeque<Lot>>,
+    disposals: Vec<Disposal>,
+}
+
+impl Accounting {
+    pub fn new(method: CostMethod, currency: &str) -> Self {
+        Self {
+            method,
+            currency: currency.to_string(),
+            lots: HashMap::new(),
+            disposals: vec![],
+        }
+    }
+
+    // Goes through the trades and the ledger entries in time order. Ledger entries of trades are left out,
+    // since the trades give their prices and fees. The rate gives the value of an asset in the currency at a
+    // time, for the trades that aren't quoted in the currency and the assets received out of trades
+    pub fn from_history<F>(
+        method: CostMethod,
+        currency: &str,
+        registry: &AssetRegistry,
+        trades: &HashMap<String, Trade>,
+        ledgers: &HashMap<String, LedgerEntry>,
+        rate: F,
+    ) -> Result<Self, Errors>
+    where
+        F: Fn(&str, DateTime<Utc>) -> Option<f64>,
+    {
+        let currency = registry.resolve_asset(currency)?;
+        let mut accounting = Self::new(method, currency);
+
+        let mut trades: Vec<(&String, &Trade)> = trades.iter().collect();
+        let mut ledgers: Vec<(&String, &LedgerEntry)> = ledgers.iter().collect();
+        trades.sort_by_key(|(txid, trade)| (trade.time, *txid));
+        ledgers.sort_by_key(|(id, entry)| (entry.time, *id));
+
+        let mut ledgers = ledgers.into_iter().peekable();
+        for (txid, trade) in trades {
+            while let Some((id, entry)) = ledgers.next_if(|(_, entry)| entry.time < trade.time) {
+                accounting.add_ledger_entry(id, entry, registry, &rate)?;
+            }
+            accounting.add_trade(txid, trade, registry, &rate)?;
+        }
+        for (id, entry) in ledgers {
+            accounting.add_ledger_entry(id, entry, registry, &rate)?;
+        }
+
+        Ok(accounting)
+    }
+
+    pub fn method(&self) -> CostMethod {
+        self.method
+    }
+
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    // Adds the lots of an acquisition. The currency itself isn't tracked
+    pub fn acquire(&mut self, asset: &str, refid: &str, time: DateTime<Utc>, volume: f64, cost: f64) {
+        if asset == self.currency || volume <= 0.0 {
+            return;
+        }
+
+        self.lots.entry(asset.to_string()).or_default().push_back(Lot {
+            asset: asset.to_string(),
+            refid: refid.to_string(),
+            acquired: time,
+            volume,
+            cost,
+        });
+    }
+
+    // Realizes the gain of a disposal, taking the lots according to the cost method. A volume without lots
+    // left is disposed of at no cost
+    pub fn dispose(&mut self, asset: &str, refid: &str, time: DateTime<Utc>, volume: f64, proceeds: f64) {
+        if asset == self.currency || volume <= 0.0 {
+            return;
+        }
+
+        for (lot, taken, cost) in self.take(asset, volume) {
+            self.disposals.push(Disposal {
+                asset: asset.to_string(),
+                refid: refid.to_string(),
+                lot_refid: lot.as_ref().map(|lot| lot.refid.clone()),
+                acquired: lot.map(|lot| lot.acquired),
+                disposed: time,
+                volume: taken,
+                cost,
+                proceeds: proceeds * taken / volume,
+            });
+        }
+    }
+
+    // Takes the volume out of the lots without realizing a gain, such as for withdrawals
+    pub fn remove(&mut self, asset: &str, volume: f64) {
+        if asset != self.currency && volume > 0.0 {
+            self.take(asset, volume);
+        }
+    }
+
+    // Trades quoted in another currency are valued with the rate of the quote, and dispose of or acquire the
+    // quote as well. Fees are in the quote, added to the cost of a buy and deducted from the proceeds of a
+    // sell. Margin trades are left out, since they don't change the balances
+    pub fn add_trade<F>(&mut self, txid: &str, trade: &Trade, registry: &AssetRegistry, rate: F) -> Result<(), Errors>
+    where
+        F: Fn(&str, DateTime<Utc>) -> Option<f64>,
+    {
+        if trade.margin != 0.0 || trade.postxid.is_some() {
+            return Ok(());
+        }
+
+        let pair = registry.pair(&trade.pair)?;
+        let quote_rate = if pair.quote == self.currency {
+            1.0
+        } else {
+            rate(&pair.quote, trade.time)
+                .ok_or_else(|| Errors::InvalidParams(format!("No rate of {} at {}", pair.quote, trade.time)))?
+        };
+
+        match trade.kind {
+            Side::Buy => {
+                let spent = trade.cost + trade.fee;
+                self.dispose(&pair.quote, txid, trade.time, spent, spent * quote_rate);
+                self.acquire(&pair.base, txid, trade.time, trade.vol, spent * quote_rate);
+            }
+            Side::Sell => {
+                let received = trade.cost - trade.fee;
+                self.dispose(&pair.base, txid, trade.time, trade.vol, received * quote_rate);
+                self.acquire(&pair.quote, txid, trade.time, received, received * quote_rate);
+            }
+            Side::Unknown(_) => return Err(Errors::InvalidParams(format!("Unknown side of trade {}", txid))),
+        }
+
+        Ok(())
+    }
+
+    // Applies the ledger entry, returning whether it changed the lots. Assets received are acquired at the
+    // value given by the rate, and need one, since a lot without a cost would count fully as gain once disposed
+    // of. A rate of 0 acquires them at no cost deliberately. Assets spent are disposed of at that value, assets
+    // withdrawn or transferred out are removed. Entries of trades and transfers between the spot and the
+    // staking wallets are left out
+    pub fn add_ledger_entry<F>(
+        &mut self,
+        id: &str,
+        entry: &LedgerEntry,
+        registry: &AssetRegistry,
+        rate: F,
+    ) -> Result<bool, Errors>
+    where
+        F: Fn(&str, DateTime<Utc>) -> Option<f64>,
+    {
+        let staking_transfer = entry.kind == LedgerType::Transfer
+            && entry
+                .subtype
+                .as_deref()
+                .map_or(false, |subtype| subtype.contains("staking"));
+        let skipped = matches!(
+            entry.kind,
+            LedgerType::Trade | LedgerType::Margin | LedgerType::Rollover | LedgerType::Settled
+        );
+        if skipped || staking_transfer {
+            return Ok(false);
+        }
+
+        // Staked balances are the same asset
+        let balance_asset = BalanceAsset::from(entry.asset.as_str());
+        let asset = registry
+            .resolve_asset(&balance_asset.asset)
+            .unwrap_or(&balance_asset.asset)
+            .to_string();
+        if asset == self.currency {
+            return Ok(false);
+        }
+        let price = || {
+            rate(&asset, entry.time)
+                .ok_or_else(|| Errors::InvalidParams(format!("No rate of {} at {}", asset, entry.time)))
+        };
+
+        if entry.amount > 0.0 {
+            let volume = entry.amount - entry.fee;
+            let cost = volume * price()?;
+            self.acquire(&asset, id, entry.time, volume, cost);
+        } else if entry.kind == LedgerType::Spend {
+            let price = price()?;
+            self.dispose(&asset, id, entry.time, -entry.amount + entry.fee, -entry.amount * price);
+        } else {
+            self.remove(&asset, -entry.amount + entry.fee);
+        }
+
+        Ok(true)
+    }
+
+    // Held lots of the asset, oldest first
+    pub fn lots(&self, asset: &str) -> impl Iterator<Item = &Lot> {
+        self.lots.get(asset).into_iter().flatten()
+    }
+
+    pub fn disposals(&self) -> &[Disposal] {
+        &self.disposals
+    }
+
+    // Realized gains by asset
+    pub fn realized(&self) -> HashMap<String, f64> {
+        let mut realized = HashMap::new();
+        for disposal in &self.disposals {
+            *realized.entry(disposal.asset.clone()).or_insert(0.0) += disposal.gain();
+        }
+
+        realized
+    }
+
+    // Profit and loss of every asset held or disposed of, sorted by asset. Prices are in the currency, keyed
+    // by Kraken's asset names
+    pub fn summary(&self, prices: &HashMap<String, f64>) -> Vec<AssetPnl> {
+        let realized = self.realized();
+        let mut assets: Vec<&String> = self.lots.keys().chain(realized.keys()).collect();
+        assets.sort();
+        assets.dedup();
+
+        assets
+            .into_iter()
+            .map(|asset| {
+                let volume: f64 = self.lots(asset).map(|lot| lot.volume).sum();
+                let cost: f64 = self.lots(asset).map(|lot| lot.cost).sum();
+                AssetPnl {
+                    asset: asset.clone(),
+                    volume,
+                    cost,
+                    realized: realized.get(asset).copied().unwrap_or(0.0),
+                    unrealized: prices.get(asset).map(|price| volume * price - cost),
+                }
+            })
+            .collect()
+    }
+
+    // Takes the volume out of the lots, returning the lot, volume and cost of every part taken
+    fn take(&mut self, asset: &str, volume: f64) -> Vec<(Option<Lot>, f64, f64)> {
+        let lots = self.lots.entry(asset.to_string()).or_default();
+        let mut taken = vec![];
+        let mut remaining = volume;
+
+        if self.method == CostMethod::Average {
+            let held: f64 = lots.iter().map(|lot| lot.volume).sum();
+            let part = remaining.min(held);
+            if part > 0.0 {
+                let cost: f64 = lots.iter().map(|lot| lot.cost).sum::<f64>() * part / held;
+                for lot in lots.iter_mut() {
+                    lot.cost -= lot.cost * part / held;
+                    lot.volume -= lot.volume * part / held;
+                }
+                taken.push((None, part, cost));
+                remaining -= part;
+            }
+        } else {
+            while remaining > VOLUME_TOLERANCE {
+                let lot = match self.method {
+                    CostMethod::Lifo => lots.back_mut(),
+                    _ => lots.front_mut(),
+                };
+                let lot = match lot {
+                    Some(lot) => lot,
+                    None => break,
+                };
+
+                let part = remaining.min(lot.volume);
+                let cost = lot.cost * part / lot.volume;
+                taken.push((Some(lot.clone()), part, cost));
+                lot.volume -= part;
+                lot.cost -= cost;
+                remaining -= part;
+
+                if lot.volume <= VOLUME_TOLERANCE {
+                    match self.method {
+                        CostMethod::Lifo => lots.pop_back(),
+                        _ => lots.pop_front(),
+                    };
+                }
+            }
+        }
+
+        lots.retain(|lot| lot.volume > VOLUME_TOLERANCE);
+        if remaining > VOLUME_TOLERANCE {
+            taken.push((None, remaining, 0.0));
+        }
+
+        taken
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::tests::{create_test_registry, create_trade, time};
+
+    fn create_trades() -> HashMap<String, Trade> {
+        vec![
+            ("T1", create_trade("XXBTZUSD", "buy", 1000, 10000.0, 1.0, 10.0)),
+            ("T2", create_trade("XXBTZUSD", "buy", 2000, 20000.0, 1.0, 20.0)),
+            ("T3", create_trade("XXBTZUSD", "sell", 3000, 30000.0, 1.5, 45.0)),
+        ]
+        .into_iter()
+        .map(|(txid, trade)| (txid.to_string(), trade))
+        .collect()
+    }
+
+    #[test]
+    fn test_methods() {
+        let registry = create_test_registry();
+        let trades = create_trades();
+        let ledgers = HashMap::new();
+        let no_rate = |_: &str, _: DateTime<Utc>| None;
+
+        let fifo = Accounting::from_history(CostMethod::Fifo, "USD", &registry, &trades, &ledgers, no_rate).unwrap();
+        assert_eq!(fifo.currency(), "ZUSD");
+        let disposals = fifo.disposals();
+        assert_eq!(disposals.len(), 2);
+        assert_eq!(disposals[0].lot_refid.as_deref(), Some("T1"));
+        assert_eq!(disposals[0].acquired, Some(time(1000)));
+        assert_eq!(disposals[0].disposed, time(3000));
+        assert_eq!(
+            (disposals[0].volume, disposals[0].cost, disposals[0].proceeds),
+            (1.0, 10010.0, 29970.0)
+        );
+        assert_eq!(disposals[1].lot_refid.as_deref(), Some("T2"));
+        assert_eq!(
+            (disposals[1].volume, disposals[1].cost, disposals[1].proceeds),
+            (0.5, 10010.0, 14985.0)
+        );
+        assert_eq!(fifo.realized()["XXBT"], 24935.0);
+        let lots: Vec<&Lot> = fifo.lots("XXBT").collect();
+        assert_eq!(lots.len(), 1);
+        assert_eq!((lots[0].volume, lots[0].cost), (0.5, 10010.0));
+
+        let lifo = Accounting::from_history(CostMethod::Lifo, "USD", &registry, &trades, &ledgers, no_rate).unwrap();
+        let disposals = lifo.disposals();
+        assert_eq!(disposals[0].lot_refid.as_deref(), Some("T2"));
+        assert_eq!((disposals[0].volume, disposals[0].cost), (1.0, 20020.0));
+        assert_eq!(disposals[1].lot_refid.as_deref(), Some("T1"));
+        assert_eq!((disposals[1].volume, disposals[1].cost), (0.5, 5005.0));
+        assert_eq!(lifo.realized()["XXBT"], 44955.0 - 25025.0);
+
+        let average =
+            Accounting::from_history(CostMethod::Average, "USD", &registry, &trades, &ledgers, no_rate).unwrap();
+        let disposals = average.disposals();
+        assert_eq!(disposals.len(), 1);
+        assert_eq!(disposals[0].acquired, None);
+        assert_eq!((disposals[0].volume, disposals[0].cost), (1.5, 22522.5));
+        let prices = vec![("XXBT".to_string(), 40000.0)].into_iter().collect();
+        let summary = average.summary(&prices);
+        assert_eq!(summary.len(), 1);
+        assert_eq!(summary[0].asset, "XXBT");
+        assert!((summary[0].volume - 0.5).abs() < 1e-12);
+        assert!((summary[0].cost - 7507.5).abs() < 1e-9);
+        assert!((summary[0].realized - 22432.5).abs() < 1e-9);
+        assert!((summary[0].unrealized.unwrap() - 12492.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cross_trades() {
+        let registry = create_test_registry();
+        let mut accounting = Accounting::new(CostMethod::Fifo, "ZUSD");
+        let rate = |asset: &str, _: DateTime<Utc>| if asset == "XXBT" { Some(20000.0) } else { None };
+
+        let buy = create_trade("XXBTZUSD", "buy", 1000, 10000.0, 1.0, 0.0);
+        accounting.add_trade("T1", &buy, &registry, rate).unwrap();
+        // Buying ETH with XBT disposes of the XBT spent, fees included
+        let buy = create_trade("XETHXXBT", "buy", 2000, 0.05, 10.0, 0.001);
+        accounting.add_trade("T2", &buy, &registry, rate).unwrap();
+
+        let disposals = accounting.disposals();
+        assert_eq!(disposals.len(), 1);
+        assert_eq!(disposals[0].asset, "XXBT");
+        assert!((disposals[0].volume - 0.501).abs() < 1e-12);
+        assert!((disposals[0].cost - 5010.0).abs() < 1e-9);
+        assert!((disposals[0].proceeds - 10020.0).abs() < 1e-9);
+        let eth: Vec<&Lot> = accounting.lots("XETH").collect();
+        assert!((eth[0].cost - 10020.0).abs() < 1e-9);
+
+        // Selling ETH for XBT acquires the XBT received
+        let sell = create_trade("XETHXXBT", "sell", 3000, 0.06, 5.0, 0.0);
+        accounting.add_trade("T3", &sell, &registry, rate).unwrap();
+        assert!((accounting.disposals()[1].gain() - (6000.0 - 5010.0)).abs() < 1e-9);
+        let xbt: Vec<&Lot> = accounting.lots("XXBT").collect();
+        assert_eq!(xbt.len(), 2);
+        assert_eq!(xbt[1].refid, "T3");
+        assert!((xbt[1].volume - 0.3).abs() < 1e-12);
+
+        // Without a rate of the quote
+        let rate = |_: &str, _: DateTime<Utc>| None;
+        assert!(accounting.add_trade("T4", &sell, &registry, rate).is_err());
+    }
+
+    #[test]
+    fn test_ledger_entries() {
+        let registry = create_test_registry();
+        let mut accounting = Accounting::new(CostMethod::Fifo, "ZUSD");
+        let rate = |asset: &str, _: DateTime<Utc>| if asset == "DOT" { Some(5.0) } else { None };
+        let entry = |kind: &str, subtype: &str, asset: &str, amount: f64, fee: f64| -> LedgerEntry {
+            let entry = serde_json::json!({
+                "refid": "QCC7JC-HBHLN-OT4GYE", "time": 1000, "type": kind, "subtype": subtype,
+                "aclass": "currency", "asset": asset, "amount": amount.to_string(), "fee": fee.to_string(),
+                "balance": "0"
+            });
+            serde_json::from_str(&entry.to_string()).unwrap()
+        };
+
+        // Acquired at the rate, which is needed
+        assert!(accounting
+            .add_ledger_entry("L1", &entry("deposit", "", "DOT", 10.0, 0.0), &registry, rate)
+            .unwrap());
+        assert!(accounting
+            .add_ledger_entry("L2", &entry("staking", "", "DOT.S", 1.0, 0.0), &registry, rate)
+            .unwrap());
+        let deposit = entry("deposit", "", "XXBT", 1.0, 0.0);
+        assert!(matches!(
+            accounting.add_ledger_entry("L3", &deposit, &registry, rate),
+            Err(Errors::InvalidParams(_))
+        ));
+        let dot: Vec<&Lot> = accounting.lots("DOT").collect();
+        assert_eq!((dot[0].volume, dot[0].cost), (10.0, 50.0));
+        assert_eq!((dot[1].volume, dot[1].cost), (1.0, 5.0));
+        assert!(accounting.lots("XXBT").next().is_none());
+        let free = |_: &str, _: DateTime<Utc>| Some(0.0);
+        assert!(accounting.add_ledger_entry("L3", &deposit, &registry, free).unwrap());
+        assert_eq!(accounting.lots("XXBT").next().unwrap().cost, 0.0);
+
+        // Left out
+        let transfer = entry("transfer", "spottostaking", "DOT", -10.0, 0.0);
+        assert!(!accounting.add_ledger_entry("L4", &transfer, &registry, rate).unwrap());
+        let trade = entry("trade", "", "DOT", -1.0, 0.0);
+        assert!(!accounting.add_ledger_entry("L5", &trade, &registry, rate).unwrap());
+        let deposit = entry("deposit", "", "ZUSD", 100.0, 0.0);
+        assert!(!accounting.add_ledger_entry("L6", &deposit, &registry, rate).unwrap());
+
+        // Withdrawals don't realize a gain
+        let withdrawal = entry("withdrawal", "", "DOT", -4.0, 1.0);
+        assert!(accounting.add_ledger_entry("L7", &withdrawal, &registry, rate).unwrap());
+        assert!(accounting.disposals().is_empty());
+        assert_eq!(accounting.lots("DOT").next().unwrap().volume, 5.0);
+
+        let spend = entry("spend", "", "DOT", -6.0, 0.0);
+        assert!(accounting.add_ledger_entry("L8", &spend, &registry, rate).unwrap());
+        let disposals = accounting.disposals();
+        assert_eq!(disposals.len(), 2);
+        assert_eq!(
+            (disposals[0].volume, disposals[0].cost, disposals[0].proceeds),
+            (5.0, 25.0, 25.0)
+        );
+        assert_eq!(disposals[1].lot_refid.as_deref(), Some("L2"));
+        assert_eq!(accounting.lots("DOT").count(), 0);
+
+        // More than held is disposed of at no cost
+        let spend = entry("spend", "", "DOT", -2.0, 0.0);
+        accounting.add_ledger_entry("L9", &spend, &registry, rate).unwrap();
+        let disposal = accounting.disposals().last().unwrap();
+        assert_eq!(
+            (disposal.lot_refid.as_ref(), disposal.volume, disposal.cost),
+            (None, 2.0, 0.0)
+        );
+    }
+}
@@ -0,0 +1,215 @@
+// Order book maintained from the book channel of the WebSocket API
+use super::{
+    ws::{BookEntry, BookSnapshot, BookUpdate, Channel, Client, Event},
+    Errors, OrderBook,
+};
+use std::cmp::Ordering;
+
+// Levels of each side included in Kraken's checksum
+const CHECKSUM_LEVELS: usize = 10;
+
+pub struct LocalOrderBook {
+    pair: String,
+    depth: u64,
+    // Sorted from the lowest price
+    asks: Vec<BookEntry>,
+    // Sorted from the highest price
+    bids: Vec<BookEntry>,
+    // Whether a snapshot was received since the last checksum mismatch
+    synced: bool,
+    // Used to request a fresh snapshot on a checksum mismatch
+    client: Option<Client>,
+}
+
+impl LocalOrderBook {
+    // Book fed by hand, such as with recorded messages. It isn't resynced on a checksum mismatch
+    pub fn new(pair: &str, depth: u64) -> Self {
+        Self {
+            pair: pair.to_string(),
+            depth,
+            asks: Vec::new(),
+            bids: Vec::new(),
+            synced: false,
+            client: None,
+        }
+    }
+
+    // Subscribes to the book of the pair. The events of the client still have to be passed to apply
+    pub fn subscribe(client: &Client, pair: &str, depth: u64) -> Result<Self, Errors> {
+        client.subscribe(Channel::Book { depth }, &[pair])?;
+
+        Ok(Self {
+            client: Some(client.clone()),
+            ..Self::new(pair, depth)
+        })
+    }
+
+    pub fn pair(&self) -> &str {
+        &self.pair
+    }
+
+    pub fn depth(&self) -> u64 {
+        self.depth
+    }
+
+    pub fn asks(&self) -> &[BookEntry] {
+        &self.asks
+    }
+
+    pub fn bids(&self) -> &[BookEntry] {
+        &self.bids
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.synced
+    }
+
+    pub fn to_order_book(&self) -> OrderBook {
+        OrderBook {
+            asks: self.asks.iter().map(BookEntry::level).collect(),
+            bids: self.bids.iter().map(BookEntry::level).collect(),
+        }
+    }
+
+    // Applies the book events of the pair and depth, other events are ignored. Returns whether the book
+    // changed. On a checksum mismatch the book is cleared until a new snapshot arrives, which is requested
+    // through the client if there is one
+    pub fn apply(&mut self, event: &Event) -> Result<bool, Errors> {
+        match event {
+            Event::BookSnapshot(snapshot) if self.is_own(&snapshot.pair, snapshot.depth) => {
+                self.apply_snapshot(snapshot);
+                Ok(true)
+            }
+            Event::BookUpdate(update) if self.is_own(&update.pair, update.depth) && self.synced => {
+                self.apply_update(update)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    // CRC32 of the price and volume of the top levels, asks first. Prices and volumes are taken as sent by
+    // Kraken, without the decimal point and the leading zeros
+    pub fn checksum(&self) -> u32 {
+        let mut data = String::new();
+        for entry in self
+            .asks
+            .iter()
+            .take(CHECKSUM_LEVELS)
+            .chain(self.bids.iter().take(CHECKSUM_LEVELS))
+        {
+            for value in &[&entry.raw_price, &entry.raw_volume] {
+                let digits = value.replace('.', "");
+                data.push_str(digits.trim_start_matches('0'));
+            }
+        }
+
+        crc32(data.as_bytes())
+    }
+
+    fn is_own(&self, pair: &str, depth: u64) -> bool {
+        self.pair == pair && self.depth == depth
+    }
+
+    fn apply_snapshot(&mut self, snapshot: &BookSnapshot) {
+        self.asks.clear();
+        self.bids.clear();
+        for entry in &snapshot.asks {
+            upsert(&mut self.asks, entry, BookSide::Ask);
+        }
+        for entry in &snapshot.bids {
+            upsert(&mut self.bids, entry, BookSide::Bid);
+        }
+        self.truncate();
+        self.synced = true;
+    }
+
+    fn apply_update(&mut self, update: &BookUpdate) -> Result<(), Errors> {
+        for entry in &update.asks {
+            upsert(&mut self.asks, entry, BookSide::Ask);
+        }
+        for entry in &update.bids {
+            upsert(&mut self.bids, entry, BookSide::Bid);
+        }
+        self.truncate();
+
+        let expected = match update.checksum {
+            Some(checksum) => checksum,
+            None => return Ok(()),
+        };
+        let calculated = self.checksum();
+        if calculated == expected {
+            return Ok(());
+        }
+
+        self.asks.clear();
+        self.bids.clear();
+        self.synced = false;
+        if let Some(client) = &self.client {
+            client.resubscribe(Channel::Book { depth: self.depth }, &[&self.pair])?;
+        }
+
+        Err(Errors::ChecksumMismatch {
+            pair: self.pair.clone(),
+            expected,
+            calculated,
+        })
+    }
+
+    // Levels out of the subscribed depth aren't updated by Kraken anymore
+    fn truncate(&mut self) {
+        self.asks.truncate(self.depth as usize);
+        self.bids.truncate(self.depth as usize);
+    }
+}
+
+#[derive(Clone, Copy)]
+enum BookSide {
+    Ask,
+    Bid,
+}
+
+// Inserts, replaces or, for a volume of 0, removes the level of the entry's price
+fn upsert(levels: &mut Vec<BookEntry>, entry: &BookEntry, side: BookSide) {
+    let position = levels.binary_search_by(|level| {
+        let ordering = level.price.partial_cmp(&entry.price).unwrap_or(Ordering::Equal);
+        match side {
+            BookSide::Ask => ordering,
+            BookSide::Bid => ordering.reverse(),
+        }
+    });
+
+    match (position, entry.volume == 0.0) {
+        (Ok(index), true) => {
+            levels.remove(index);
+        }
+        (Ok(index), false) => levels[index] = entry.clone(),
+        (Err(index), false) => levels.insert(index, entry.clone()),
+        (Err(_), true) => {}
+    }
+}
+
+// CRC-32 (IEEE 802.3), as used by Kraken for the book checksum
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+}
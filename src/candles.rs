@@ -0,0 +1,246 @@
+// OHLC candles of any interval built from raw trades, the way Kraken's OHLC endpoint builds them
+use super::{Candle, Errors, PublicTrade};
+use chrono::{DateTime, Utc};
+
+// Builds the candles of the trades as they come, from the REST trades endpoint, the WebSocket trade feed or
+// a HistoryStore. Like Kraken, intervals without trades get a candle at the previous close, with a zero vwap,
+// volume and count
+pub struct CandleAggregator {
+    // Length of the candles, in seconds
+    interval: i64,
+    committed: Vec<Candle>,
+    // Candle of the interval of the last trade, until a later interval begins
+    current: Option<Candle>,
+    // Sum of price * volume of the trades of the current candle
+    value: f64,
+}
+
+impl CandleAggregator {
+    // Interval in minutes, as for the OHLC endpoint
+    pub fn new(interval: u32) -> Result<Self, Errors> {
+        if interval == 0 {
+            return Err(Errors::InvalidParams("Interval must be at least a minute".to_string()));
+        }
+
+        Ok(Self {
+            interval: i64::from(interval) * 60,
+            committed: vec![],
+            current: None,
+            value: 0.0,
+        })
+    }
+
+    // Beginning of the interval the time is in
+    pub fn interval_start(&self, time: &DateTime<Utc>) -> DateTime<Utc> {
+        let start = time.timestamp().div_euclid(self.interval) * self.interval;
+        DateTime::from_timestamp(start, 0).unwrap_or(*time)
+    }
+
+    // Trades have to be added in the order they were made. A trade of an interval before the current one is
+    // an error
+    pub fn add(&mut self, trade: &PublicTrade) -> Result<(), Errors> {
+        let start = self.interval_start(&trade.time);
+        if let Some(current) = &self.current {
+            if start < current.time {
+                return Err(Errors::InvalidParams(format!(
+                    "Trade at {} is before the candle at {}",
+                    trade.time, current.time
+                )));
+            }
+        }
+        self.advance(&start);
+
+        match &mut self.current {
+            Some(current) if current.count > 0 => {
+                current.high = current.high.max(trade.price);
+                current.low = current.low.min(trade.price);
+                current.close = trade.price;
+                current.volume += trade.volume;
+                current.count += 1;
+            }
+            _ => {
+                self.current = Some(Candle {
+                    time: start,
+                    open: trade.price,
+                    high: trade.price,
+                    low: trade.price,
+                    close: trade.price,
+                    vwap: 0.0,
+                    volume: trade.volume,
+                    count: 1,
+                });
+                self.value = 0.0;
+            }
+        }
+        self.value += trade.price * trade.volume;
+        if let Some(current) = &mut self.current {
+            if current.volume > 0.0 {
+                current.vwap = self.value / current.volume;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn add_trades<'a>(&mut self, trades: impl IntoIterator<Item = &'a PublicTrade>) -> Result<(), Errors> {
+        for trade in trades {
+            self.add(trade)?;
+        }
+
+        Ok(())
+    }
+
+    // Commits the candles of the intervals that ended by the time, filling the ones without trades. Meant to
+    // be called when no trade came for a while, such as on a heartbeat of the trade feed
+    pub fn advance(&mut self, time: &DateTime<Utc>) {
+        let start = self.interval_start(time);
+        while let Some(current) = self.current.take() {
+            if current.time >= start {
+                self.current = Some(current);
+                break;
+            }
+
+            let next = DateTime::from_timestamp(current.time.timestamp() + self.interval, 0).unwrap_or(start);
+            self.current = Some(Candle {
+                time: next,
+                open: current.close,
+                high: current.close,
+                low: current.close,
+                close: current.close,
+                vwap: 0.0,
+                volume: 0.0,
+                count: 0,
+            });
+            self.value = 0.0;
+            self.committed.push(current);
+        }
+    }
+
+    // Candle of the interval that hasn't ended yet, Kraken's last uncommitted candle
+    pub fn current(&self) -> Option<&Candle> {
+        self.current.as_ref()
+    }
+
+    // Candles of the intervals that ended, oldest first
+    pub fn committed(&self) -> &[Candle] {
+        &self.committed
+    }
+
+    // Removes the committed candles, to hand them over as they are completed
+    pub fn take_committed(&mut self) -> Vec<Candle> {
+        std::mem::take(&mut self.committed)
+    }
+
+    // All the candles, the current one last
+    pub fn finish(mut self) -> Vec<Candle> {
+        self.committed.extend(self.current.take());
+        self.committed
+    }
+}
+
+// Candles of the interval in minutes for trades in the order they were made, the last one possibly incomplete
+pub fn aggregate(trades: &[PublicTrade], interval: u32) -> Result<Vec<Candle>, Errors> {
+    let mut aggregator = CandleAggregator::new(interval)?;
+    aggregator.add_trades(trades)?;
+
+    Ok(aggregator.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::tests::time;
+
+    fn create_trades(trades: &[(&str, &str, f64)]) -> Vec<PublicTrade> {
+        let trades: Vec<serde_json::Value> = trades
+            .iter()
+            .map(|(price, volume, time)| serde_json::json!([price, volume, time, "b", "l", ""]))
+            .collect();
+
+        serde_json::from_str(&serde_json::Value::from(trades).to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let trades = create_trades(&[
+            ("100.0", "1.0", 60.5),
+            ("110.0", "3.0", 90.0),
+            ("90.0", "1.0", 119.9),
+            ("105.0", "2.0", 120.0),
+            // Nothing between 180 and 300
+            ("95.0", "0.5", 310.0),
+        ]);
+
+        let candles = aggregate(&trades, 1).unwrap();
+        let times: Vec<i64> = candles.iter().map(|candle| candle.time.timestamp()).collect();
+        assert_eq!(times, vec![60, 120, 180, 240, 300]);
+
+        let candle = &candles[0];
+        assert_eq!(
+            (candle.open, candle.high, candle.low, candle.close),
+            (100.0, 110.0, 90.0, 90.0)
+        );
+        assert_eq!((candle.volume, candle.count), (5.0, 3));
+        assert!((candle.vwap - 520.0 / 5.0).abs() < 1e-12);
+        assert_eq!((candles[1].open, candles[1].vwap, candles[1].count), (105.0, 105.0, 1));
+
+        // Empty intervals stay at the previous close
+        for candle in &candles[2..4] {
+            assert_eq!(
+                (candle.open, candle.high, candle.low, candle.close),
+                (105.0, 105.0, 105.0, 105.0)
+            );
+            assert_eq!((candle.vwap, candle.volume, candle.count), (0.0, 0.0, 0));
+        }
+        assert_eq!((candles[4].open, candles[4].count), (95.0, 1));
+
+        let candles = aggregate(&trades, 5).unwrap();
+        let times: Vec<i64> = candles.iter().map(|candle| candle.time.timestamp()).collect();
+        assert_eq!(times, vec![0, 300]);
+        assert_eq!((candles[0].close, candles[0].volume, candles[0].count), (105.0, 7.0, 4));
+        assert!((candles[0].vwap - 730.0 / 7.0).abs() < 1e-12);
+
+        assert!(aggregate(&trades, 0).is_err());
+        assert!(aggregate(&[], 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_aggregator() {
+        let mut aggregator = CandleAggregator::new(1).unwrap();
+        assert_eq!(aggregator.interval_start(&time(119)), time(60));
+        aggregator
+            .add_trades(&create_trades(&[("100.0", "1.0", 60.0), ("101.0", "1.0", 70.0)]))
+            .unwrap();
+        assert!(aggregator.committed().is_empty());
+        assert_eq!(aggregator.current().unwrap().close, 101.0);
+
+        // Without trades, the intervals are committed as time goes by
+        aggregator.advance(&time(130));
+        assert_eq!(aggregator.committed().len(), 1);
+        assert_eq!(aggregator.current().unwrap().time, time(120));
+        assert_eq!(aggregator.current().unwrap().count, 0);
+        aggregator.advance(&time(179));
+        assert_eq!(aggregator.committed().len(), 1);
+
+        aggregator
+            .add_trades(&create_trades(&[("102.0", "2.0", 150.0)]))
+            .unwrap();
+        let candle = aggregator.current().unwrap();
+        assert_eq!(
+            (candle.open, candle.low, candle.vwap, candle.count),
+            (102.0, 102.0, 102.0, 1)
+        );
+        assert!(aggregator
+            .add_trades(&create_trades(&[("99.0", "1.0", 100.0)]))
+            .is_err());
+
+        aggregator
+            .add_trades(&create_trades(&[("103.0", "1.0", 200.0)]))
+            .unwrap();
+        let committed = aggregator.take_committed();
+        assert_eq!(committed.len(), 2);
+        assert_eq!(committed[1].close, 102.0);
+        assert!(aggregator.committed().is_empty());
+        assert_eq!(aggregator.finish().len(), 1);
+    }
+}
@@ -0,0 +1,301 @@
+// Conversion of amounts between any two assets through the traded pairs
+use super::{registry::AssetRegistry, Errors, Ticker};
+use std::collections::HashMap;
+
+// Longest chain of pairs tried to convert between two assets
+const MAX_STEPS: usize = 4;
+
+// Ticker price used for the rates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PriceSource {
+    // The bid when selling the base, the ask when buying it, as a market order would get
+    BidAsk,
+    Mid,
+    Last,
+    // Today's opening price
+    Open,
+}
+
+impl PriceSource {
+    // Price of the pair to sell the base for the quote, and to buy the base with the quote
+    fn prices(self, ticker: &Ticker) -> Option<(f64, f64)> {
+        let ask = ticker.a.first().copied();
+        let bid = ticker.b.first().copied();
+        let (sell, buy) = match self {
+            Self::BidAsk => (bid?, ask?),
+            Self::Mid => {
+                let mid = (bid? + ask?) / 2.0;
+                (mid, mid)
+            }
+            Self::Last => {
+                let last = ticker.c.first().copied()?;
+                (last, last)
+            }
+            Self::Open => (ticker.o, ticker.o),
+        };
+
+        if sell > 0.0 && buy > 0.0 && sell.is_finite() && buy.is_finite() {
+            Some((sell, buy))
+        } else {
+            None
+        }
+    }
+}
+
+// Trade of one pair in a conversion
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConversionStep {
+    pub pair: String,
+    pub from: String,
+    pub to: String,
+    // Ticker price of the pair, quote per base
+    pub price: f64,
+    // Amount of to received per from
+    pub rate: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conversion {
+    pub from: String,
+    pub to: String,
+    // Amount of to received per from, through the whole path
+    pub rate: f64,
+    // Empty when from and to are the same asset
+    pub path: Vec<ConversionStep>,
+}
+
+impl Conversion {
+    pub fn convert(&self, amount: f64) -> f64 {
+        amount * self.rate
+    }
+
+    // Pairs of the path, in order
+    pub fn pairs(&self) -> Vec<&str> {
+        self.path.iter().map(|step| step.pair.as_str()).collect()
+    }
+}
+
+pub struct ConversionGraph<'a> {
+    registry: &'a AssetRegistry,
+    // None when built from given prices
+    source: Option<PriceSource>,
+    // Steps leaving each asset, by Kraken's asset name
+    steps: HashMap<String, Vec<ConversionStep>>,
+}
+
+impl<'a> ConversionGraph<'a> {
+    // Builds the graph from the pairs of the registry that have a ticker. Tickers can be keyed by any
+    // spelling of the pair name
+    pub fn new(registry: &'a AssetRegistry, tickers: &HashMap<String, Ticker>, source: PriceSource) -> Self {
+        let prices = tickers
+            .iter()
+            .filter_map(|(name, ticker)| Some((name.as_str(), source.prices(ticker)?)));
+
+        Self::build(registry, Some(source), prices)
+    }
+
+    // Builds the graph from one price per pair, quote per base, used both ways, such as the closes of candles.
+    // Prices can be keyed by any spelling of the pair name
+    pub fn from_prices(registry: &'a AssetRegistry, prices: &HashMap<String, f64>) -> Self {
+        let prices = prices
+            .iter()
+            .filter(|(_, price)| **price > 0.0 && price.is_finite())
+            .map(|(name, price)| (name.as_str(), (*price, *price)));
+
+        Self::build(registry, None, prices)
+    }
+
+    // Adds the steps of the pairs from their prices to sell and to buy the base
+    fn build<'b>(
+        registry: &'a AssetRegistry,
+        source: Option<PriceSource>,
+        prices: impl Iterator<Item = (&'b str, (f64, f64))>,
+    ) -> Self {
+        let mut steps: HashMap<String, Vec<ConversionStep>> = HashMap::new();
+        for (name, (sell, buy)) in prices {
+            let pair_name = match registry.resolve_pair(name) {
+                Ok(pair_name) => pair_name,
+                Err(_) => continue,
+            };
+            let pair = &registry.pairs()[pair_name];
+
+            steps.entry(pair.base.clone()).or_default().push(ConversionStep {
+                pair: pair_name.to_string(),
+                from: pair.base.clone(),
+                to: pair.quote.clone(),
+                price: sell,
+                rate: sell,
+            });
+            steps.entry(pair.quote.clone()).or_default().push(ConversionStep {
+                pair: pair_name.to_string(),
+                from: pair.quote.clone(),
+                to: pair.base.clone(),
+                price: buy,
+                rate: 1.0 / buy,
+            });
+        }
+
+        Self {
+            registry,
+            source,
+            steps,
+        }
+    }
+
+    pub fn source(&self) -> Option<PriceSource> {
+        self.source
+    }
+
+    // Path giving the most of to per from, among the ones of up to MAX_STEPS pairs. The assets can be in any
+    // spelling known by the registry
+    pub fn best_path(&self, from: &str, to: &str) -> Result<Conversion, Errors> {
+        let from = self.registry.resolve_asset(from)?;
+        let to = self.registry.resolve_asset(to)?;
+        let mut best = Conversion {
+            from: from.to_string(),
+            to: to.to_string(),
+            rate: 1.0,
+            path: vec![],
+        };
+        if from == to {
+            return Ok(best);
+        }
+
+        // Best conversion to each asset reached with the number of steps of the round. Paths don't go
+        // through an asset twice, so price differences can't make them loop
+        let mut reached: HashMap<&str, (f64, Vec<&ConversionStep>)> = HashMap::new();
+        reached.insert(from, (1.0, vec![]));
+        let mut found = false;
+        for _ in 0..MAX_STEPS {
+            let mut next: HashMap<&str, (f64, Vec<&ConversionStep>)> = HashMap::new();
+            for (asset, (rate, path)) in &reached {
+                for step in self.steps.get(*asset).into_iter().flatten() {
+                    let visited = step.to == from || path.iter().any(|previous| previous.to == step.to);
+                    if visited {
+                        continue;
+                    }
+                    let rate = rate * step.rate;
+                    let better = next.get(step.to.as_str()).map_or(true, |(best, _)| rate > *best);
+                    if better {
+                        let mut path = path.clone();
+                        path.push(step);
+                        next.insert(&step.to, (rate, path));
+                    }
+                }
+            }
+
+            // Shorter paths win ties
+            if let Some((rate, path)) = next.get(to) {
+                if !found || *rate > best.rate {
+                    best.rate = *rate;
+                    best.path = path.iter().map(|step| (*step).clone()).collect();
+                    found = true;
+                }
+            }
+            next.remove(to);
+            reached = next;
+        }
+
+        if found {
+            Ok(best)
+        } else {
+            Err(Errors::InvalidParams(format!("No conversion from {} to {}", from, to)))
+        }
+    }
+
+    // Amount of the from asset in the to asset, through the best path
+    pub fn convert(&self, amount: f64, from: &str, to: &str) -> Result<f64, Errors> {
+        Ok(self.best_path(from, to)?.convert(amount))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::tests::{create_test_registry, create_tickers};
+
+    // Mid prices are consistent between the pairs, with a 0.1% spread except for ETH/XBT. DOT/USD has no prices
+    const TICKERS: &[(&str, f64, f64, f64, f64)] = &[
+        ("XXBTZUSD", 50050.0, 49950.0, 50000.0, 50000.0),
+        ("XXBTZEUR", 40040.0, 39960.0, 40000.0, 40000.0),
+        ("XETHXXBT", 0.0603, 0.0597, 0.06, 0.06),
+        ("XETHZUSD", 3003.0, 2997.0, 3000.0, 3000.0),
+        ("XDGEUR", 0.2002, 0.1998, 0.2, 0.2),
+        ("ZEURZUSD", 1.25125, 1.24875, 1.25, 1.25),
+        ("DOTUSD", 0.0, 0.0, 0.0, 0.0),
+    ];
+
+    #[test]
+    fn test_best_path() {
+        let registry = create_test_registry();
+        let tickers = create_tickers(TICKERS);
+        let graph = ConversionGraph::new(&registry, &tickers, PriceSource::BidAsk);
+
+        let conversion = graph.best_path("BTC", "USD").unwrap();
+        assert_eq!((conversion.from.as_str(), conversion.to.as_str()), ("XXBT", "ZUSD"));
+        assert_eq!(conversion.pairs(), vec!["XXBTZUSD"]);
+        assert_eq!(conversion.rate, 49950.0);
+
+        // Buying uses the ask
+        let conversion = graph.best_path("USD", "XBT").unwrap();
+        assert_eq!(conversion.pairs(), vec!["XXBTZUSD"]);
+        assert_eq!(conversion.rate, 1.0 / 50050.0);
+
+        // Without a direct pair
+        let conversion = graph.best_path("XDG", "USD").unwrap();
+        assert_eq!(conversion.pairs(), vec!["XDGEUR", "ZEURZUSD"]);
+        assert!((conversion.rate - 0.1998 * 1.24875).abs() < 1e-12);
+        assert_eq!(conversion.path[0].from, "XXDG");
+        assert_eq!(conversion.path[1].to, "ZUSD");
+        assert!((graph.convert(1000.0, "DOGE", "USD").unwrap() - 249.50025).abs() < 1e-9);
+
+        // The wider spread of ETH/XBT makes the path through USD give more
+        let conversion = graph.best_path("ETH", "EUR").unwrap();
+        assert_eq!(conversion.pairs(), vec!["XETHZUSD", "ZEURZUSD"]);
+        assert!((conversion.rate - 2997.0 / 1.25125).abs() < 1e-9);
+
+        let conversion = graph.best_path("USD", "ZUSD").unwrap();
+        assert_eq!(conversion.rate, 1.0);
+        assert!(conversion.path.is_empty());
+
+        assert!(matches!(graph.best_path("DOT", "USD"), Err(Errors::InvalidParams(_))));
+        assert!(graph.best_path("ADA", "USD").is_err());
+    }
+
+    #[test]
+    fn test_price_sources() {
+        let registry = create_test_registry();
+        let tickers = create_tickers(TICKERS);
+
+        let graph = ConversionGraph::new(&registry, &tickers, PriceSource::Mid);
+        assert!((graph.best_path("BTC", "USD").unwrap().rate - 50000.0).abs() < 1e-9);
+        assert!((graph.best_path("USD", "BTC").unwrap().rate - 1.0 / 50000.0).abs() < 1e-15);
+
+        let graph = ConversionGraph::new(&registry, &tickers, PriceSource::Last);
+        assert_eq!(graph.source(), Some(PriceSource::Last));
+        let conversion = graph.best_path("XDG", "USD").unwrap();
+        assert!((conversion.rate - 0.25).abs() < 1e-12);
+        let conversion = graph.best_path("ETH", "EUR").unwrap();
+        assert!((conversion.rate - 2400.0).abs() < 1e-9);
+
+        let graph = ConversionGraph::new(&registry, &tickers, PriceSource::Open);
+        assert!((graph.best_path("XDG", "USD").unwrap().rate - 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_from_prices() {
+        let registry = create_test_registry();
+        let prices: HashMap<String, f64> = vec![("XBTEUR", 40000.0), ("EURUSD", 1.25), ("DOTUSD", 0.0)]
+            .into_iter()
+            .map(|(pair, price)| (pair.to_string(), price))
+            .collect();
+        let graph = ConversionGraph::from_prices(&registry, &prices);
+        assert_eq!(graph.source(), None);
+
+        let conversion = graph.best_path("XBT", "USD").unwrap();
+        assert_eq!(conversion.pairs(), vec!["XXBTZEUR", "ZEURZUSD"]);
+        assert!((conversion.rate - 50000.0).abs() < 1e-9);
+        assert!((graph.best_path("USD", "EUR").unwrap().rate - 0.8).abs() < 1e-12);
+        assert!(graph.best_path("DOT", "USD").is_err());
+    }
+}
@@ -0,0 +1,44 @@
+// Fields of the CSV files written by the crate, such as the history and the tax reports
+
+// Quotes the field if it has a separator, a quote or a line break
+pub(crate) fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// Fields of a line written with csv_field
+pub(crate) fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fields() {
+        assert_eq!(split_csv_line("a,\"b,\"\"c\"\"\",,d"), vec!["a", "b,\"c\"", "", "d"]);
+        assert_eq!(split_csv_line(&csv_field("x,\"y\"")), vec!["x,\"y\""]);
+        assert_eq!(csv_field("abc"), "abc");
+    }
+}
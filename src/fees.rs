@@ -0,0 +1,206 @@
+// Estimation of the trading fees of an order from the fee tiers of its pair
+use super::{AssetPairInfo, Errors, NewOrder, OrderFlag, Side, TradeVolume};
+
+// Currency the fee is charged in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FeeCurrency {
+    Base,
+    Quote,
+}
+
+impl FeeCurrency {
+    // Kraken charges the quote currency on buys and the base currency on sells, unless fcib or fciq is set
+    pub fn of(order: &NewOrder) -> Self {
+        if order.oflags.contains(&OrderFlag::Fcib) {
+            Self::Base
+        } else if order.oflags.contains(&OrderFlag::Fciq) {
+            Self::Quote
+        } else {
+            match order.side {
+                Side::Sell => Self::Base,
+                _ => Self::Quote,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeeEstimate {
+    pub currency: FeeCurrency,
+    // Value of the order in quote currency
+    pub cost: f64,
+    // Fees in percent
+    pub maker_rate: f64,
+    pub taker_rate: f64,
+    // Fees in quote currency
+    pub maker_fee: f64,
+    pub taker_fee: f64,
+    // Fees in the currency they are charged in
+    pub maker_charged: f64,
+    pub taker_charged: f64,
+}
+
+pub struct FeeEstimator<'a> {
+    pair: &'a AssetPairInfo,
+    // 30 day volume, in the fee_volume_currency of the pair
+    volume: f64,
+}
+
+impl<'a> FeeEstimator<'a> {
+    pub fn new(pair: &'a AssetPairInfo, volume: f64) -> Self {
+        Self { pair, volume }
+    }
+
+    // Uses the volume returned by Kraken::trade_volume
+    pub fn with_trade_volume(pair: &'a AssetPairInfo, trade_volume: &TradeVolume) -> Result<Self, Errors> {
+        if trade_volume.currency != pair.fee_volume_currency {
+            return Err(Errors::InvalidParams(format!(
+                "Trade volume is in {}, the fees of the pair in {}",
+                trade_volume.currency, pair.fee_volume_currency
+            )));
+        }
+
+        Ok(Self::new(pair, trade_volume.volume))
+    }
+
+    // Taker fee in percent of the volume's tier
+    pub fn taker_rate(&self) -> f64 {
+        tier_fee(&self.pair.fees, self.volume)
+    }
+
+    // Maker fee in percent of the volume's tier. Pairs without a maker schedule charge the taker fee
+    pub fn maker_rate(&self) -> f64 {
+        match &self.pair.fees_maker {
+            Some(fees) => tier_fee(fees, self.volume),
+            None => self.taker_rate(),
+        }
+    }
+
+    // Fees of the order if it's filled at the price
+    pub fn estimate(&self, order: &NewOrder, price: f64) -> Result<FeeEstimate, Errors> {
+        if !price.is_finite() || price <= 0.0 {
+            return Err(Errors::InvalidParams(format!("Invalid price: {}", price)));
+        }
+
+        let cost = if order.oflags.contains(&OrderFlag::Viqc) {
+            order.volume
+        } else {
+            order.volume * price
+        };
+        let currency = FeeCurrency::of(order);
+        let (maker_rate, taker_rate) = (self.maker_rate(), self.taker_rate());
+        let (maker_fee, taker_fee) = (cost * maker_rate / 100.0, cost * taker_rate / 100.0);
+        let charged = |fee: f64| match currency {
+            FeeCurrency::Base => fee / price,
+            FeeCurrency::Quote => fee,
+        };
+
+        Ok(FeeEstimate {
+            currency,
+            cost,
+            maker_rate,
+            taker_rate,
+            maker_fee,
+            taker_fee,
+            maker_charged: charged(maker_fee),
+            taker_charged: charged(taker_fee),
+        })
+    }
+
+    // Volume in base currency that can be bought at the price for the amount of quote currency, fees in
+    // quote currency included
+    pub fn net_volume(&self, amount: f64, price: f64, maker: bool) -> f64 {
+        let rate = if maker { self.maker_rate() } else { self.taker_rate() };
+        amount / (price * (1.0 + rate / 100.0))
+    }
+}
+
+// Fee of the highest tier reached by the volume. Tiers are pairs of the minimum volume and the fee in percent
+fn tier_fee(tiers: &[Vec<f64>], volume: f64) -> f64 {
+    tiers
+        .iter()
+        .rfind(|tier| tier.len() == 2 && tier[0] <= volume)
+        .or_else(|| tiers.first())
+        .and_then(|tier| tier.get(1).copied())
+        .unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::tests::create_pair;
+    use crate::{OrderFlags, OrderType};
+
+    #[test]
+    fn test_tiers() {
+        let mut pair = create_pair("XBTUSD", Some("XBT/USD"), "XXBT", "ZUSD");
+
+        let estimator = FeeEstimator::new(&pair, 0.0);
+        assert_eq!((estimator.maker_rate(), estimator.taker_rate()), (0.16, 0.26));
+        let estimator = FeeEstimator::new(&pair, 50000.0);
+        assert_eq!((estimator.maker_rate(), estimator.taker_rate()), (0.14, 0.24));
+        let estimator = FeeEstimator::new(&pair, 1000000.0);
+        assert_eq!((estimator.maker_rate(), estimator.taker_rate()), (0.12, 0.22));
+
+        pair.fees_maker = None;
+        let estimator = FeeEstimator::new(&pair, 75000.0);
+        assert_eq!((estimator.maker_rate(), estimator.taker_rate()), (0.24, 0.24));
+    }
+
+    #[test]
+    fn test_estimate() {
+        let pair = create_pair("XBTUSD", Some("XBT/USD"), "XXBT", "ZUSD");
+        let estimator = FeeEstimator::new(&pair, 60000.0);
+
+        let order = NewOrder::new("XBTUSD", Side::Buy, OrderType::Limit, 2.0);
+        let estimate = estimator.estimate(&order, 10000.0).unwrap();
+        assert_eq!(estimate.currency, FeeCurrency::Quote);
+        assert_eq!(estimate.cost, 20000.0);
+        assert!((estimate.maker_fee - 28.0).abs() < 1e-9);
+        assert!((estimate.taker_fee - 48.0).abs() < 1e-9);
+        assert_eq!(estimate.taker_charged, estimate.taker_fee);
+
+        // Sells are charged in base currency unless fciq is set
+        let mut order = NewOrder::new("XBTUSD", Side::Sell, OrderType::Limit, 2.0);
+        let estimate = estimator.estimate(&order, 10000.0).unwrap();
+        assert_eq!(estimate.currency, FeeCurrency::Base);
+        assert!((estimate.taker_fee - 48.0).abs() < 1e-9);
+        assert!((estimate.taker_charged - 0.0048).abs() < 1e-12);
+        order.oflags = OrderFlags(vec![OrderFlag::Fciq]);
+        assert_eq!(FeeCurrency::of(&order), FeeCurrency::Quote);
+
+        // A volume in quote currency is the cost itself
+        let mut order = NewOrder::new("XBTUSD", Side::Buy, OrderType::Market, 1000.0);
+        order.oflags = OrderFlags(vec![OrderFlag::Viqc, OrderFlag::Fcib]);
+        let estimate = estimator.estimate(&order, 10000.0).unwrap();
+        assert_eq!(estimate.currency, FeeCurrency::Base);
+        assert_eq!(estimate.cost, 1000.0);
+        assert!((estimate.taker_charged - 0.00024).abs() < 1e-12);
+
+        assert!(estimator.estimate(&order, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_net_volume() {
+        let pair = create_pair("XBTUSD", Some("XBT/USD"), "XXBT", "ZUSD");
+        let estimator = FeeEstimator::new(&pair, 0.0);
+
+        let volume = estimator.net_volume(10026.0, 10000.0, false);
+        assert!((volume - 1.0).abs() < 1e-12);
+        let order = NewOrder::new("XBTUSD", Side::Buy, OrderType::Limit, volume);
+        let estimate = estimator.estimate(&order, 10000.0).unwrap();
+        assert!((estimate.cost + estimate.taker_fee - 10026.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_trade_volume() {
+        let pair = create_pair("XBTUSD", Some("XBT/USD"), "XXBT", "ZUSD");
+        let trade_volume: TradeVolume =
+            serde_json::from_str(r#"{"currency": "ZUSD", "volume": "120000.5000"}"#).unwrap();
+        let estimator = FeeEstimator::with_trade_volume(&pair, &trade_volume).unwrap();
+        assert_eq!(estimator.taker_rate(), 0.22);
+
+        let trade_volume: TradeVolume = serde_json::from_str(r#"{"currency": "ZEUR", "volume": "10.0"}"#).unwrap();
+        assert!(FeeEstimator::with_trade_volume(&pair, &trade_volume).is_err());
+    }
+}
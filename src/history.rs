@@ -0,0 +1,545 @@
+// Download of the trade and OHLC history of pairs into local CSV files, resumed from the last stored cursor
+use super::{
+    csv::{csv_field, split_csv_line},
+    rate_limit::{RateLimiter, RATE_LIMIT_ERROR},
+    Candle, Errors, Kraken, OrderType, PublicTrade, Side,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+use tokio::{
+    fs::{self, OpenOptions},
+    io::{self, AsyncWriteExt},
+};
+
+const TRADES_HEADER: &str = "time,price,volume,side,ordertype,misc,trade_id";
+const CANDLES_HEADER: &str = "time,open,high,low,close,vwap,volume,count";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dataset {
+    Trades,
+    // Candles of the interval, in minutes
+    Ohlc(u32),
+}
+
+impl Dataset {
+    fn file_name(&self, pair: &str) -> String {
+        let pair: String = pair
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        match self {
+            Self::Trades => format!("{}_trades", pair),
+            Self::Ohlc(interval) => format!("{}_ohlc_{}", pair, interval),
+        }
+    }
+}
+
+// Saved after the rows of each response are appended. Rows past length were written by a download interrupted
+// before it could save the cursor, and are downloaded again
+#[derive(Debug, Serialize, Deserialize)]
+struct Cursor {
+    since: String,
+    length: u64,
+}
+
+// CSV file of each pair and dataset in a directory, with the cursor to resume it next to it
+pub struct HistoryStore {
+    dir: PathBuf,
+}
+
+impl HistoryStore {
+    pub fn new(dir: &Path) -> Self {
+        Self { dir: dir.to_path_buf() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn data_path(&self, pair: &str, dataset: Dataset) -> PathBuf {
+        self.dir.join(format!("{}.csv", dataset.file_name(pair)))
+    }
+
+    fn cursor_path(&self, pair: &str, dataset: Dataset) -> PathBuf {
+        self.dir.join(format!("{}.cursor", dataset.file_name(pair)))
+    }
+
+    async fn read_cursor(&self, pair: &str, dataset: Dataset) -> Result<Option<Cursor>, Errors> {
+        match fs::read(self.cursor_path(pair, dataset)).await {
+            Ok(data) => serde_json::from_slice(&data)
+                .map(Some)
+                .map_err(|error| Errors::Parse(error.to_string())),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    // since of the next request, none when nothing was stored yet
+    pub async fn cursor(&self, pair: &str, dataset: Dataset) -> Result<Option<String>, Errors> {
+        Ok(self.read_cursor(pair, dataset).await?.map(|cursor| cursor.since))
+    }
+
+    // Removes the rows appended without saving the cursor, by a download that was interrupted, and returns the
+    // cursor. Called before appending, while no other download of the pair and dataset runs
+    pub async fn recover(&self, pair: &str, dataset: Dataset) -> Result<Option<String>, Errors> {
+        let cursor = self.read_cursor(pair, dataset).await?;
+        let length = cursor.as_ref().map_or(0, |cursor| cursor.length);
+
+        let data_path = self.data_path(pair, dataset);
+        match fs::metadata(&data_path).await {
+            Ok(metadata) if metadata.len() > length => {
+                let mut file = OpenOptions::new().write(true).open(&data_path).await?;
+                file.set_len(length).await?;
+            }
+            Ok(_) => {}
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
+        }
+
+        Ok(cursor.map(|cursor| cursor.since))
+    }
+
+    // Appends the trades and saves since as the cursor to resume from. The store has to be recovered first
+    pub async fn append_trades(&self, pair: &str, trades: &[PublicTrade], since: &str) -> Result<(), Errors> {
+        let rows = trades
+            .iter()
+            .map(|trade| {
+                [
+                    format_time(&trade.time),
+                    trade.price.to_string(),
+                    trade.volume.to_string(),
+                    trade.side.to_string(),
+                    trade.ordertype.to_string(),
+                    csv_field(&trade.misc),
+                    trade.trade_id.map(|id| id.to_string()).unwrap_or_default(),
+                ]
+                .join(",")
+            })
+            .collect();
+
+        self.append(pair, Dataset::Trades, TRADES_HEADER, rows, since).await
+    }
+
+    // Appends the candles and saves since as the cursor to resume from. The store has to be recovered first
+    pub async fn append_candles(
+        &self,
+        pair: &str,
+        interval: u32,
+        candles: &[Candle],
+        since: &str,
+    ) -> Result<(), Errors> {
+        let rows = candles
+            .iter()
+            .map(|candle| {
+                [
+                    candle.time.timestamp().to_string(),
+                    candle.open.to_string(),
+                    candle.high.to_string(),
+                    candle.low.to_string(),
+                    candle.close.to_string(),
+                    candle.vwap.to_string(),
+                    candle.volume.to_string(),
+                    candle.count.to_string(),
+                ]
+                .join(",")
+            })
+            .collect();
+
+        self.append(pair, Dataset::Ohlc(interval), CANDLES_HEADER, rows, since)
+            .await
+    }
+
+    async fn append(
+        &self,
+        pair: &str,
+        dataset: Dataset,
+        header: &str,
+        rows: Vec<String>,
+        since: &str,
+    ) -> Result<(), Errors> {
+        fs::create_dir_all(&self.dir).await?;
+
+        let data_path = self.data_path(pair, dataset);
+        let mut file = OpenOptions::new().create(true).append(true).open(&data_path).await?;
+        let mut data = String::new();
+        if file.metadata().await?.len() == 0 {
+            data.push_str(header);
+            data.push('\n');
+        }
+        for row in rows {
+            data.push_str(&row);
+            data.push('\n');
+        }
+        file.write_all(data.as_bytes()).await?;
+        file.sync_all().await?;
+
+        // Written to a temporary file first so an interrupted write can't leave a broken cursor
+        let cursor = Cursor {
+            since: since.to_string(),
+            length: file.metadata().await?.len(),
+        };
+        let cursor_path = self.cursor_path(pair, dataset);
+        let temporary_path = cursor_path.with_extension("cursor.tmp");
+        let data = serde_json::to_vec(&cursor).map_err(|error| Errors::Parse(error.to_string()))?;
+        fs::write(&temporary_path, data).await?;
+        fs::rename(&temporary_path, &cursor_path).await?;
+
+        Ok(())
+    }
+
+    // Stored trades of the pair, oldest first
+    pub async fn trades(&self, pair: &str) -> Result<Vec<PublicTrade>, Errors> {
+        self.rows(pair, Dataset::Trades)
+            .await?
+            .iter()
+            .map(|fields| {
+                if fields.len() != 7 {
+                    return Err(Errors::Parse(format!("Invalid trade row: {}", fields.join(","))));
+                }
+                Ok(PublicTrade {
+                    time: parse_time(&fields[0])?,
+                    price: parse_number(&fields[1])?,
+                    volume: parse_number(&fields[2])?,
+                    side: Side::from(fields[3].as_str()),
+                    ordertype: OrderType::from(fields[4].as_str()),
+                    misc: fields[5].clone(),
+                    trade_id: match fields[6].as_str() {
+                        "" => None,
+                        id => Some(parse_number(id)?),
+                    },
+                })
+            })
+            .collect()
+    }
+
+    // Stored committed candles of the pair and interval, oldest first
+    pub async fn candles(&self, pair: &str, interval: u32) -> Result<Vec<Candle>, Errors> {
+        self.rows(pair, Dataset::Ohlc(interval))
+            .await?
+            .iter()
+            .map(|fields| {
+                if fields.len() != 8 {
+                    return Err(Errors::Parse(format!("Invalid candle row: {}", fields.join(","))));
+                }
+                let time = parse_number(&fields[0])?;
+                Ok(Candle {
+                    time: DateTime::from_timestamp(time, 0)
+                        .ok_or_else(|| Errors::Parse(format!("Invalid timestamp {}", time)))?,
+                    open: parse_number(&fields[1])?,
+                    high: parse_number(&fields[2])?,
+                    low: parse_number(&fields[3])?,
+                    close: parse_number(&fields[4])?,
+                    vwap: parse_number(&fields[5])?,
+                    volume: parse_number(&fields[6])?,
+                    count: parse_number(&fields[7])?,
+                })
+            })
+            .collect()
+    }
+
+    // Fields of the rows up to the stored cursor, without the header. Rows a download is appending are left out
+    async fn rows(&self, pair: &str, dataset: Dataset) -> Result<Vec<Vec<String>>, Errors> {
+        let length = match self.read_cursor(pair, dataset).await? {
+            Some(cursor) => cursor.length as usize,
+            None => return Ok(vec![]),
+        };
+        let mut data = fs::read(self.data_path(pair, dataset)).await?;
+        data.truncate(length);
+        let data = String::from_utf8(data).map_err(|error| Errors::Parse(error.to_string()))?;
+
+        Ok(data.lines().skip(1).map(split_csv_line).collect())
+    }
+}
+
+// Outcome of downloading the history of a pair
+#[derive(Debug, Clone, PartialEq)]
+pub struct Download {
+    pub pair: String,
+    pub dataset: Dataset,
+    pub requests: u32,
+    // Rows appended to the store
+    pub rows: usize,
+    // Stored cursor after the download
+    pub cursor: Option<String>,
+}
+
+pub struct Downloader<'a> {
+    kraken: &'a Kraken,
+    store: HistoryStore,
+    limiter: RateLimiter,
+    // Times a call rejected by Kraken's rate limit is retried, after waiting retry_delay
+    pub retries: u32,
+    pub retry_delay: Duration,
+}
+
+impl<'a> Downloader<'a> {
+    pub fn new(kraken: &'a Kraken, store: HistoryStore, limiter: RateLimiter) -> Self {
+        Self {
+            kraken,
+            store,
+            limiter,
+            retries: 3,
+            retry_delay: Duration::from_secs(5),
+        }
+    }
+
+    pub fn store(&self) -> &HistoryStore {
+        &self.store
+    }
+
+    // Brings every dataset of every pair up to date, one after the other
+    pub async fn download(&mut self, pairs: &[&str], datasets: &[Dataset]) -> Result<Vec<Download>, Errors> {
+        let mut downloads = vec![];
+        for pair in pairs {
+            for dataset in datasets {
+                let download = match dataset {
+                    Dataset::Trades => self.download_trades(pair).await?,
+                    Dataset::Ohlc(interval) => self.download_ohlc(pair, *interval).await?,
+                };
+                downloads.push(download);
+            }
+        }
+
+        Ok(downloads)
+    }
+
+    // Pages through the trades from the stored cursor, or from the first trade of the pair, until the
+    // most recent one
+    pub async fn download_trades(&mut self, pair: &str) -> Result<Download, Errors> {
+        let kraken = self.kraken;
+        let mut download = Download {
+            pair: pair.to_string(),
+            dataset: Dataset::Trades,
+            requests: 0,
+            rows: 0,
+            cursor: self.store.recover(pair, Dataset::Trades).await?,
+        };
+
+        loop {
+            let since = download.cursor.clone().unwrap_or_else(|| "0".to_string());
+            let params = [("pair", pair), ("since", since.as_str())];
+            let response = self.call(|| kraken.recent_trades(&params)).await?;
+            download.requests += 1;
+
+            let trades = response
+                .pairs
+                .into_iter()
+                .next()
+                .map(|(_, trades)| trades)
+                .unwrap_or_default();
+            if trades.is_empty() || response.last == since {
+                break;
+            }
+            self.store.append_trades(pair, &trades, &response.last).await?;
+            download.rows += trades.len();
+            download.cursor = Some(response.last);
+        }
+
+        Ok(download)
+    }
+
+    // Stores the committed candles after the stored cursor. Kraken only returns the most recent 720 candles
+    // of an interval, so older ones are out of reach of a first download
+    pub async fn download_ohlc(&mut self, pair: &str, interval: u32) -> Result<Download, Errors> {
+        let kraken = self.kraken;
+        let dataset = Dataset::Ohlc(interval);
+        let interval_param = interval.to_string();
+        let mut download = Download {
+            pair: pair.to_string(),
+            dataset,
+            requests: 0,
+            rows: 0,
+            cursor: self.store.recover(pair, dataset).await?,
+        };
+
+        loop {
+            let mut params = vec![("pair", pair), ("interval", interval_param.as_str())];
+            if let Some(since) = &download.cursor {
+                params.push(("since", since));
+            }
+            let response = self.call(|| kraken.ohlc(&params)).await?;
+            download.requests += 1;
+
+            // The last candle is the current one, committed once its interval ends
+            let since = download.cursor.as_deref().and_then(|since| i64::from_str(since).ok());
+            let last = response.last as i64;
+            let candles: Vec<Candle> = response
+                .pairs
+                .into_iter()
+                .next()
+                .map(|(_, candles)| candles)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|candle| {
+                    let time = candle.time.timestamp();
+                    time <= last && since.map_or(true, |since| time > since)
+                })
+                .collect();
+            if candles.is_empty() {
+                break;
+            }
+            let cursor = last.to_string();
+            self.store.append_candles(pair, interval, &candles, &cursor).await?;
+            download.rows += candles.len();
+            download.cursor = Some(cursor);
+        }
+
+        Ok(download)
+    }
+
+    // Makes the call within the rate limit, retrying it when Kraken rejects it for going over
+    async fn call<T, F, Fut>(&mut self, call: F) -> Result<T, Errors>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, Errors>>,
+    {
+        let mut retries = 0;
+        loop {
+            self.limiter.acquire(1.0).await;
+            match call().await {
+                Err(Errors::Kraken(error)) if error.contains(RATE_LIMIT_ERROR) && retries < self.retries => {
+                    retries += 1;
+                    tokio::time::delay_for(self.retry_delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+// Seconds and nanoseconds, so the time is stored exactly
+fn format_time(time: &DateTime<Utc>) -> String {
+    format!("{}.{:09}", time.timestamp(), time.timestamp_subsec_nanos())
+}
+
+fn parse_time(s: &str) -> Result<DateTime<Utc>, Errors> {
+    let (seconds, nanos) = s.split_once('.').unwrap_or((s, "0"));
+    let seconds: i64 = parse_number(seconds)?;
+    let nanos: u32 = parse_number(&format!("{:0<9}", nanos))?;
+
+    DateTime::from_timestamp(seconds, nanos).ok_or_else(|| Errors::Parse(format!("Invalid timestamp {}", s)))
+}
+
+fn parse_number<T: FromStr>(s: &str) -> Result<T, Errors> {
+    T::from_str(s).map_err(|_| Errors::Parse(format!("Invalid number {}", s)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn create_store(name: &str) -> HistoryStore {
+        let dir = env::temp_dir().join(format!("kraken_history_{}_{}", name, std::process::id()));
+        HistoryStore::new(&dir)
+    }
+
+    fn create_trades() -> Vec<PublicTrade> {
+        let trades = serde_json::json!([
+            ["50000.1", "0.5", 1616663618.5366, "b", "l", "", 1],
+            ["50001.2", "0.25", 1616663619.1, "s", "m", "a,\"b\"", 2],
+        ]);
+        serde_json::from_str(&trades.to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_trades_store() {
+        let store = create_store("trades");
+        assert_eq!(store.cursor("XBT/USD", Dataset::Trades).await.unwrap(), None);
+        assert!(store.trades("XBT/USD").await.unwrap().is_empty());
+
+        let trades = create_trades();
+        store
+            .append_trades("XBT/USD", &trades[..1], "1616663618536600000")
+            .await
+            .unwrap();
+        store
+            .append_trades("XBT/USD", &trades[1..], "1616663619100000000")
+            .await
+            .unwrap();
+        assert_eq!(
+            store.cursor("XBT/USD", Dataset::Trades).await.unwrap(),
+            Some("1616663619100000000".to_string())
+        );
+        assert!(store
+            .data_path("XBT/USD", Dataset::Trades)
+            .ends_with("XBT_USD_trades.csv"));
+        assert_eq!(store.trades("XBT/USD").await.unwrap(), trades);
+
+        fs::remove_dir_all(store.dir()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_candles_store() {
+        let store = create_store("candles");
+        let candles: Vec<Candle> = serde_json::from_str(
+            r#"[[1616662740, "52591.9", "52599.9", "52591.8", "52599.9", "52599.1", "0.11091626", 5],
+                [1616662800, "52600.0", "52600.0", "52590.1", "52590.1", "52595.2", "1.5", 3]]"#,
+        )
+        .unwrap();
+
+        store
+            .append_candles("XXBTZUSD", 1, &candles, "1616662800")
+            .await
+            .unwrap();
+        assert_eq!(store.candles("XXBTZUSD", 1).await.unwrap(), candles);
+        assert!(store.candles("XXBTZUSD", 5).await.unwrap().is_empty());
+        assert_eq!(
+            store.cursor("XXBTZUSD", Dataset::Ohlc(1)).await.unwrap(),
+            Some("1616662800".to_string())
+        );
+
+        fs::remove_dir_all(store.dir()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_append() {
+        let store = create_store("interrupted");
+        let trades = create_trades();
+        store.append_trades("XBTUSD", &trades[..1], "1").await.unwrap();
+
+        // Rows written by a download that stopped before saving the cursor
+        let path = store.data_path("XBTUSD", Dataset::Trades);
+        let mut file = OpenOptions::new().append(true).open(&path).await.unwrap();
+        file.write_all(b"1616663619.100000000,50001.2,0.2").await.unwrap();
+        drop(file);
+        let length = fs::metadata(&path).await.unwrap().len();
+
+        // Reads leave them out without touching the file, the recovery removes them
+        assert_eq!(store.trades("XBTUSD").await.unwrap(), &trades[..1]);
+        assert_eq!(fs::metadata(&path).await.unwrap().len(), length);
+        assert_eq!(
+            store.recover("XBTUSD", Dataset::Trades).await.unwrap(),
+            Some("1".to_string())
+        );
+        assert!(fs::metadata(&path).await.unwrap().len() < length);
+        store.append_trades("XBTUSD", &trades[1..], "2").await.unwrap();
+        assert_eq!(store.trades("XBTUSD").await.unwrap(), trades);
+
+        // Without a cursor, nothing was stored for sure
+        fs::remove_file(store.cursor_path("XBTUSD", Dataset::Trades))
+            .await
+            .unwrap();
+        assert_eq!(store.cursor("XBTUSD", Dataset::Trades).await.unwrap(), None);
+        assert!(store.trades("XBTUSD").await.unwrap().is_empty());
+        assert_eq!(store.recover("XBTUSD", Dataset::Trades).await.unwrap(), None);
+        assert_eq!(fs::metadata(&path).await.unwrap().len(), 0);
+
+        fs::remove_dir_all(store.dir()).await.unwrap();
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(
+            parse_time("1616663618.5").unwrap(),
+            DateTime::from_timestamp(1616663618, 500_000_000).unwrap()
+        );
+        assert!(parse_time("x.1").is_err());
+    }
+}
@@ -1,33 +1,89 @@
 // https://www.kraken.com/features/api
-use base64;
+use chrono::{DateTime, Utc};
+use futures::{
+    future::Future,
+    stream::{self, Stream},
+};
 use hmac::{Hmac, Mac, NewMac};
 use reqwest::{
     self,
-    header::{HeaderMap, HeaderValue},
+    header::{HeaderMap, HeaderValue, CONTENT_TYPE},
     Client, RequestBuilder,
 };
 use serde::{
-    de::{Deserializer, Error},
-    Deserialize, Serialize,
+    de::{Deserializer, Error, SeqAccess, Visitor},
+    Deserialize, Serialize, Serializer,
 };
 use sha2::{Digest, Sha256, Sha512};
 use std::{
-    collections::HashMap,
-    error, fmt,
+    cmp::Reverse,
+    collections::{HashMap, VecDeque},
+    error, fmt, io,
+    num::ParseFloatError,
+    path::{Path, PathBuf},
     str::FromStr,
     time::{self, Duration},
 };
+use tokio::{
+    fs::{self, File},
+    io::{AsyncWrite, AsyncWriteExt},
+    time::delay_for,
+};
+use tokio_tungstenite::tungstenite;
 use url::{form_urlencoded, Url};
 
+pub mod accounting;
+pub mod book;
+pub mod candles;
+pub mod conversion;
+mod csv;
+pub mod fees;
+pub mod history;
+pub mod portfolio;
+pub mod rate_limit;
+pub mod registry;
+pub mod tax;
+pub mod trading;
+pub mod validation;
+pub mod ws;
+
 pub struct Urls {
     assets: String,
     asset_pairs: String,
     ticker: String,
     order_book: String,
+    ohlc: String,
+    recent_trades: String,
     account_balance: String,
     trade_balance: String,
     open_orders: String,
     closed_orders: String,
+    trades_history: String,
+    ledgers: String,
+    deposit_methods: String,
+    deposit_addresses: String,
+    deposit_status: String,
+    withdraw_info: String,
+    withdraw: String,
+    withdraw_status: String,
+    withdraw_cancel: String,
+    wallet_transfer: String,
+    create_subaccount: String,
+    account_transfer: String,
+    add_export: String,
+    export_status: String,
+    retrieve_export: String,
+    remove_export: String,
+    stakeable_assets: String,
+    stake: String,
+    unstake: String,
+    staking_pending: String,
+    staking_transactions: String,
+    websockets_token: String,
+    trade_volume: String,
+    add_order: String,
+    cancel_order: String,
+    cancel_all: String,
 }
 
 impl Urls {
@@ -37,10 +93,38 @@ impl Urls {
             asset_pairs: format!("{}{}", domain, "/0/public/AssetPairs"),
             ticker: format!("{}{}", domain, "/0/public/Ticker"),
             order_book: format!("{}{}", domain, "/0/public/Depth"),
+            ohlc: format!("{}{}", domain, "/0/public/OHLC"),
+            recent_trades: format!("{}{}", domain, "/0/public/Trades"),
             account_balance: format!("{}{}", domain, "/0/private/Balance"),
             trade_balance: format!("{}{}", domain, "/0/private/TradeBalance"),
             open_orders: format!("{}{}", domain, "/0/private/OpenOrders"),
             closed_orders: format!("{}{}", domain, "/0/private/ClosedOrders"),
+            trades_history: format!("{}{}", domain, "/0/private/TradesHistory"),
+            ledgers: format!("{}{}", domain, "/0/private/Ledgers"),
+            deposit_methods: format!("{}{}", domain, "/0/private/DepositMethods"),
+            deposit_addresses: format!("{}{}", domain, "/0/private/DepositAddresses"),
+            deposit_status: format!("{}{}", domain, "/0/private/DepositStatus"),
+            withdraw_info: format!("{}{}", domain, "/0/private/WithdrawInfo"),
+            withdraw: format!("{}{}", domain, "/0/private/Withdraw"),
+            withdraw_status: format!("{}{}", domain, "/0/private/WithdrawStatus"),
+            withdraw_cancel: format!("{}{}", domain, "/0/private/WithdrawCancel"),
+            wallet_transfer: format!("{}{}", domain, "/0/private/WalletTransfer"),
+            create_subaccount: format!("{}{}", domain, "/0/private/CreateSubaccount"),
+            account_transfer: format!("{}{}", domain, "/0/private/AccountTransfer"),
+            add_export: format!("{}{}", domain, "/0/private/AddExport"),
+            export_status: format!("{}{}", domain, "/0/private/ExportStatus"),
+            retrieve_export: format!("{}{}", domain, "/0/private/RetrieveExport"),
+            remove_export: format!("{}{}", domain, "/0/private/RemoveExport"),
+            stakeable_assets: format!("{}{}", domain, "/0/private/Staking/Assets"),
+            stake: format!("{}{}", domain, "/0/private/Stake"),
+            unstake: format!("{}{}", domain, "/0/private/Unstake"),
+            staking_pending: format!("{}{}", domain, "/0/private/Staking/Pending"),
+            staking_transactions: format!("{}{}", domain, "/0/private/Staking/Transactions"),
+            websockets_token: format!("{}{}", domain, "/0/private/GetWebSocketsToken"),
+            trade_volume: format!("{}{}", domain, "/0/private/TradeVolume"),
+            add_order: format!("{}{}", domain, "/0/private/AddOrder"),
+            cancel_order: format!("{}{}", domain, "/0/private/CancelOrder"),
+            cancel_all: format!("{}{}", domain, "/0/private/CancelAll"),
         }
     }
 }
@@ -50,6 +134,30 @@ pub enum Errors {
     Request(reqwest::Error),
     Kraken(String),
     Decode(base64::DecodeError),
+    Io(io::Error),
+    WebSocket(tungstenite::Error),
+    // The WebSocket connection was lost. The client reconnects on its own
+    Disconnected(String),
+    // Messages of a WebSocket feed were missed. The data has to be fetched again through the REST API
+    SequenceGap {
+        channel: String,
+        expected: u64,
+        received: u64,
+    },
+    // A locally maintained order book diverged from Kraken's
+    ChecksumMismatch {
+        pair: String,
+        expected: u32,
+        calculated: u32,
+    },
+    Parse(String),
+    InvalidParams(String),
+    // A report export couldn't be completed, such as when it disappeared from export_status
+    Export(String),
+    // Waited too long for Kraken to finish something, such as generating a report
+    Timeout(String),
+    // An order that Kraken would reject for its pair, found before sending it
+    InvalidOrder(Vec<validation::OrderViolation>),
     InvalidFormat,
 }
 
@@ -60,6 +168,35 @@ impl fmt::Display for Errors {
             Self::InvalidFormat => write!(f, "Invalid format"),
             Self::Kraken(error) => write!(f, "{}", error),
             Self::Decode(error) => write!(f, "{}", error),
+            Self::Io(error) => write!(f, "{}", error),
+            Self::WebSocket(error) => write!(f, "{}", error),
+            Self::Disconnected(reason) => write!(f, "Disconnected: {}", reason),
+            Self::SequenceGap {
+                channel,
+                expected,
+                received,
+            } => write!(
+                f,
+                "Expected sequence {} of {}, received {}",
+                expected, channel, received
+            ),
+            Self::ChecksumMismatch {
+                pair,
+                expected,
+                calculated,
+            } => write!(
+                f,
+                "Checksum of the {} book is {}, expected {}",
+                pair, calculated, expected
+            ),
+            Self::Parse(error) => write!(f, "{}", error),
+            Self::InvalidParams(error) => write!(f, "{}", error),
+            Self::Export(error) => write!(f, "{}", error),
+            Self::Timeout(error) => write!(f, "Timed out: {}", error),
+            Self::InvalidOrder(violations) => {
+                let violations: Vec<String> = violations.iter().map(ToString::to_string).collect();
+                write!(f, "Invalid order: {}", violations.join(", "))
+            }
         }
     }
 }
@@ -71,6 +208,16 @@ impl error::Error for Errors {
             Self::InvalidFormat => None,
             Self::Kraken(_) => None,
             Self::Decode(error) => error.source(),
+            Self::Io(error) => error.source(),
+            Self::WebSocket(error) => error.source(),
+            Self::Disconnected(_) => None,
+            Self::SequenceGap { .. } => None,
+            Self::ChecksumMismatch { .. } => None,
+            Self::Parse(_) => None,
+            Self::InvalidParams(_) => None,
+            Self::Export(_) => None,
+            Self::Timeout(_) => None,
+            Self::InvalidOrder(_) => None,
         }
     }
 }
@@ -87,6 +234,18 @@ impl From<base64::DecodeError> for Errors {
     }
 }
 
+impl From<io::Error> for Errors {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<tungstenite::Error> for Errors {
+    fn from(error: tungstenite::Error) -> Self {
+        Self::WebSocket(error)
+    }
+}
+
 pub struct Credentials {
     api_key: String,
     secret: String,
@@ -113,18 +272,21 @@ where
 {
     let s: Option<&str> = Deserialize::deserialize(deserializer)?;
 
-    return match s {
-        Some(s) => {
-            println!("s: {:?}", s);
-            let num: f64 = s.parse().unwrap();
-            println!("num: {:?}", num);
-            Ok(Some(num))
-        }
-        None => {
-            println!("none");
-            Ok(None)
-        }
-    };
+    match s {
+        Some(s) => f64::from_str(s).map(Some).map_err(D::Error::custom),
+        None => Ok(None),
+    }
+}
+
+// Inverse of from_f64_option_str, so the values can be read back
+fn to_f64_option_str<S>(value: &Option<f64>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match value {
+        Some(value) => serializer.serialize_some(&value.to_string()),
+        None => serializer.serialize_none(),
+    }
 }
 
 fn from_f64_str_vec<'de, D>(deserializer: D) -> Result<Vec<f64>, D::Error>
@@ -137,23 +299,116 @@ where
     Ok(floats_vec)
 }
 
-fn from_tuple<'de, D>(deserializer: D) -> Result<Vec<(f64, f64, u64)>, D::Error>
+fn from_f64_str_map<'de, D>(deserializer: D) -> Result<HashMap<String, f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: HashMap<String, String> = Deserialize::deserialize(deserializer)?;
+    s.into_iter()
+        .map(|(key, value)| Ok((key, f64::from_str(&value).map_err(D::Error::custom)?)))
+        .collect()
+}
+
+fn from_order_book_levels<'de, D>(deserializer: D) -> Result<Vec<OrderBookLevel>, D::Error>
 where
     D: Deserializer<'de>,
 {
     let s: Vec<(String, String, u64)> = Deserialize::deserialize(deserializer)?;
-    let floats_vec: Vec<(f64, f64, u64)> = s
-        .iter()
-        .map(|tuple| (tuple.0.parse().unwrap(), tuple.1.parse().unwrap(), tuple.2))
-        .collect();
+    s.iter()
+        .map(|(price, volume, timestamp)| {
+            Ok(OrderBookLevel {
+                price: f64::from_str(price).map_err(D::Error::custom)?,
+                volume: f64::from_str(volume).map_err(D::Error::custom)?,
+                timestamp: *timestamp,
+            })
+        })
+        .collect()
+}
 
-    Ok(floats_vec)
+// Kraken sends Unix timestamps in seconds, with the fraction holding the sub second part
+fn timestamp_to_datetime(timestamp: f64) -> Option<DateTime<Utc>> {
+    let seconds = timestamp.trunc() as i64;
+    let nanos = (timestamp.fract() * 1_000_000_000.0).round() as u32;
+
+    DateTime::from_timestamp(seconds, nanos.min(999_999_999))
+}
+
+fn from_timestamp<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    // The WebSocket feeds send timestamps as strings
+    let timestamp: NumberOrString = Deserialize::deserialize(deserializer)?;
+    let timestamp = timestamp.to_f64().map_err(D::Error::custom)?;
+
+    timestamp_to_datetime(timestamp).ok_or_else(|| D::Error::custom(format!("invalid timestamp {}", timestamp)))
 }
 
-#[derive(Debug, Serialize)]
-struct PrivatePostData {
-    nonce: String,
-    otp: Option<String>,
+// Kraken sends false instead of the amount when there is no limit
+fn from_f64_str_or_false<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Amount {
+        Amount(String),
+        NoLimit(bool),
+    }
+
+    match Deserialize::deserialize(deserializer)? {
+        Amount::Amount(amount) => f64::from_str(&amount).map(Some).map_err(D::Error::custom),
+        Amount::NoLimit(false) => Ok(None),
+        Amount::NoLimit(true) => Err(D::Error::custom("expected an amount or false")),
+    }
+}
+
+// Same as from_timestamp_option, for timestamps sent as strings
+fn from_timestamp_str_option<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let timestamp: Option<String> = Deserialize::deserialize(deserializer)?;
+
+    match timestamp {
+        Some(timestamp) => {
+            let timestamp = f64::from_str(&timestamp).map_err(D::Error::custom)?;
+            if timestamp == 0.0 {
+                return Ok(None);
+            }
+
+            timestamp_to_datetime(timestamp)
+                .map(Some)
+                .ok_or_else(|| D::Error::custom(format!("invalid timestamp {}", timestamp)))
+        }
+        None => Ok(None),
+    }
+}
+
+// A missing timestamp or 0 means the time is not set
+fn from_timestamp_option<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let timestamp: Option<NumberOrString> = Deserialize::deserialize(deserializer)?;
+    let timestamp = timestamp.map(|timestamp| timestamp.to_f64()).transpose();
+
+    match timestamp.map_err(D::Error::custom)? {
+        Some(timestamp) if timestamp != 0.0 => timestamp_to_datetime(timestamp)
+            .map(Some)
+            .ok_or_else(|| D::Error::custom(format!("invalid timestamp {}", timestamp))),
+        _ => Ok(None),
+    }
+}
+
+fn from_enum_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: for<'a> From<&'a str>,
+{
+    let s: String = Deserialize::deserialize(deserializer)?;
+
+    Ok(T::from(s.as_str()))
 }
 
 #[derive(Debug, Deserialize)]
@@ -169,20 +424,51 @@ enum Responses {
     AssetPairs(AssetPairs),
     Ticker(HashMap<String, Ticker>),
     OrderBook(HashMap<String, OrderBook>),
+    Ohlc(Ohlc),
+    RecentTrades(RecentTrades),
     TradeBalance(TradeBalance),
-    // TODO convert to float
-    Balance(HashMap<String, String>),
-    OpenOrders { open: HashMap<String, Order> },
-    // This is missing the closed orders count. not sure if neccessery
-    ClosedOrders { closed: HashMap<String, Order> },
+    TradesHistory(TradesHistoryPage),
+    Ledgers(LedgersPage),
+    WithdrawInfo(WithdrawInfo),
+    AccountTransfer(AccountTransfer),
+    WebSocketsToken(WebSocketsToken),
+    TradeVolume(TradeVolume),
+    AddOrder(AddOrderResponse),
+    ExportId {
+        id: ExportId,
+    },
+    RefId {
+        refid: RefId,
+    },
+    Balance(#[serde(deserialize_with = "from_f64_str_map")] HashMap<String, f64>),
+    OpenOrders {
+        open: HashMap<String, Order>,
+    },
+    ClosedOrders(ClosedOrdersPage),
+    // Has to come after the pages, as they have a count as well
+    Canceled {
+        count: u64,
+    },
+    // An empty array would otherwise always be decoded as the first list response below
+    #[allow(dead_code)]
+    EmptyList(Vec<()>),
+    DepositMethods(Vec<DepositMethod>),
+    DepositAddresses(Vec<DepositAddress>),
+    FundingTransactions(Vec<FundingTransaction>),
+    ExportStatus(Vec<ExportReport>),
+    StakeableAssets(Vec<StakeableAsset>),
+    StakingTransactions(Vec<StakingTransaction>),
+    Bool(bool),
+    // Both fields are optional, so it has to be the last object response
+    RemoveExport(RemoveExport),
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Asset {
-    aclass: String,
-    altname: String,
-    decimals: u64,
-    display_decimals: u64,
+    pub aclass: String,
+    pub altname: String,
+    pub decimals: u64,
+    pub display_decimals: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -193,78 +479,420 @@ pub enum AssetPairs {
     Margin(HashMap<String, AssetPairMargin>),
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AssetPairInfo {
-    altname: String,
-    wsname: Option<String>,
-    aclass_base: String,
-    base: String,
-    aclass_quote: String,
-    quote: String,
-    lot: String,
-    pair_decimals: u64,
-    lot_decimals: u64,
-    lot_multiplier: u64,
-    leverage_buy: Vec<u64>,
-    leverage_sell: Vec<u64>,
-    fees: Vec<Vec<f64>>,
-    fees_maker: Option<Vec<Vec<f64>>>,
-    fee_volume_currency: String,
-    margin_call: u64,
-    margin_stop: u64,
+    pub altname: String,
+    pub wsname: Option<String>,
+    pub aclass_base: String,
+    pub base: String,
+    pub aclass_quote: String,
+    pub quote: String,
+    pub lot: String,
+    pub pair_decimals: u64,
+    pub lot_decimals: u64,
+    pub lot_multiplier: u64,
+    pub leverage_buy: Vec<u64>,
+    pub leverage_sell: Vec<u64>,
+    pub fees: Vec<Vec<f64>>,
+    pub fees_maker: Option<Vec<Vec<f64>>>,
+    pub fee_volume_currency: String,
+    pub margin_call: u64,
+    pub margin_stop: u64,
     #[serde(default)]
     #[serde(deserialize_with = "from_f64_option_str")]
-    ordermin: Option<f64>,
+    #[serde(serialize_with = "to_f64_option_str")]
+    pub ordermin: Option<f64>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct AssetPairFees {
-    fees: Vec<Vec<f64>>,
-    fee_volume_currency: String,
+    pub fees: Vec<Vec<f64>>,
+    pub fee_volume_currency: String,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct AssetPairMargin {
-    margin_call: u64,
-    margin_level: u64,
+    pub margin_call: u64,
+    pub margin_level: u64,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Ticker {
     // Ask array (<price>, <whole lot volume>, <lot volume>)
     #[serde(deserialize_with = "from_f64_str_vec")]
-    a: Vec<f64>,
+    pub a: Vec<f64>,
     // Bid array (<price>, <whole lot volume>, <lot volume>)
     #[serde(deserialize_with = "from_f64_str_vec")]
-    b: Vec<f64>,
+    pub b: Vec<f64>,
     // Last trade closed array (<price>, <lot volume>)
     #[serde(deserialize_with = "from_f64_str_vec")]
-    c: Vec<f64>,
+    pub c: Vec<f64>,
     // Volume array (<today>, <last 24 hours>)
     #[serde(deserialize_with = "from_f64_str_vec")]
-    v: Vec<f64>,
+    pub v: Vec<f64>,
     // Volume weighted average price array (<today>, <last 24 hours>)
     #[serde(deserialize_with = "from_f64_str_vec")]
-    p: Vec<f64>,
+    pub p: Vec<f64>,
     // Number of trades array (<today>, <last 24 hours>)
-    t: Vec<u64>,
+    pub t: Vec<u64>,
     // Low array(<today>, <last 24 hours>)
     #[serde(deserialize_with = "from_f64_str_vec")]
-    l: Vec<f64>,
+    pub l: Vec<f64>,
     // High array(<today>, <last 24 hours>)
     #[serde(deserialize_with = "from_f64_str_vec")]
-    h: Vec<f64>,
+    pub h: Vec<f64>,
     // Today's opening price
     #[serde(deserialize_with = "from_f64_str")]
-    o: f64,
+    pub o: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrderBookLevel {
+    pub price: f64,
+    pub volume: f64,
+    // Unix timestamp of the last update of the level
+    pub timestamp: u64,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct OrderBook {
-    #[serde(deserialize_with = "from_tuple")]
-    pub asks: Vec<(f64, f64, u64)>,
-    #[serde(deserialize_with = "from_tuple")]
-    pub bids: Vec<(f64, f64, u64)>,
+    // Sorted by price, lowest first
+    #[serde(deserialize_with = "from_order_book_levels")]
+    pub asks: Vec<OrderBookLevel>,
+    // Sorted by price, highest first
+    #[serde(deserialize_with = "from_order_book_levels")]
+    pub bids: Vec<OrderBookLevel>,
+}
+
+impl OrderBook {
+    pub fn best_ask(&self) -> Option<&OrderBookLevel> {
+        self.asks.first()
+    }
+
+    pub fn best_bid(&self) -> Option<&OrderBookLevel> {
+        self.bids.first()
+    }
+
+    // Difference between the best ask and the best bid
+    pub fn spread(&self) -> Option<f64> {
+        match (self.best_ask(), self.best_bid()) {
+            (Some(ask), Some(bid)) => Some(ask.price - bid.price),
+            _ => None,
+        }
+    }
+
+    pub fn mid_price(&self) -> Option<f64> {
+        match (self.best_ask(), self.best_bid()) {
+            (Some(ask), Some(bid)) => Some((ask.price + bid.price) / 2.0),
+            _ => None,
+        }
+    }
+
+    // Total volume offered on the asks side at or below the given price
+    pub fn ask_depth_at(&self, price: f64) -> f64 {
+        self.asks
+            .iter()
+            .take_while(|level| level.price <= price)
+            .map(|level| level.volume)
+            .sum()
+    }
+
+    // Total volume bid on the bids side at or above the given price
+    pub fn bid_depth_at(&self, price: f64) -> f64 {
+        self.bids
+            .iter()
+            .take_while(|level| level.price >= price)
+            .map(|level| level.volume)
+            .sum()
+    }
+
+    // Fill of a market order of the side, walking the asks for a buy and the bids for a sell. None if the
+    // amount isn't positive or there is no level to fill with
+    pub fn estimate_fill(&self, side: &Side, amount: BookAmount) -> Option<FillEstimate> {
+        let levels = match side {
+            Side::Buy => &self.asks,
+            Side::Sell => &self.bids,
+            Side::Unknown(_) => return None,
+        };
+        let reference = self.mid_price().or_else(|| levels.first().map(|level| level.price))?;
+        let mut remaining = match amount {
+            BookAmount::Base(amount) | BookAmount::Quote(amount) if amount > 0.0 => amount,
+            _ => return None,
+        };
+
+        // Ignores the error of the float sums
+        let filled = |remaining: f64, volume: f64, cost: f64| remaining <= f64::EPSILON * volume.max(cost);
+
+        let (mut volume, mut cost, mut worst_price, mut count) = (0.0, 0.0, reference, 0);
+        for level in levels {
+            if filled(remaining, volume, cost) {
+                break;
+            }
+            let level_volume = match amount {
+                BookAmount::Base(_) => level.volume.min(remaining),
+                BookAmount::Quote(_) => level.volume.min(remaining / level.price),
+            };
+            volume += level_volume;
+            cost += level_volume * level.price;
+            remaining -= match amount {
+                BookAmount::Base(_) => level_volume,
+                BookAmount::Quote(_) => level_volume * level.price,
+            };
+            worst_price = level.price;
+            count += 1;
+        }
+
+        if count == 0 {
+            return None;
+        }
+        let vwap = cost / volume;
+        let impact = match side {
+            Side::Sell => reference - vwap,
+            _ => vwap - reference,
+        };
+
+        Some(FillEstimate {
+            volume,
+            cost,
+            vwap,
+            worst_price,
+            levels: count,
+            impact_bps: impact / reference * 10_000.0,
+            remaining: if filled(remaining, volume, cost) {
+                0.0
+            } else {
+                remaining
+            },
+        })
+    }
+
+    // Volume weighted average price of a market order of the side
+    pub fn vwap(&self, side: &Side, amount: BookAmount) -> Option<f64> {
+        self.estimate_fill(side, amount).map(|fill| fill.vwap)
+    }
+
+    // Distance of the average price of a market order from the mid price, in basis points
+    pub fn price_impact_bps(&self, side: &Side, amount: BookAmount) -> Option<f64> {
+        self.estimate_fill(side, amount).map(|fill| fill.impact_bps)
+    }
+
+    // Volume offered within the percent of the mid price on each side
+    pub fn liquidity_within(&self, percent: f64) -> Option<Liquidity> {
+        let mid = self.mid_price()?;
+        let ask_limit = mid * (1.0 + percent / 100.0);
+        let bid_limit = mid * (1.0 - percent / 100.0);
+        let asks = self.asks.iter().take_while(|level| level.price <= ask_limit);
+        let bids = self.bids.iter().take_while(|level| level.price >= bid_limit);
+
+        Some(Liquidity {
+            ask_volume: asks.clone().map(|level| level.volume).sum(),
+            ask_value: asks.map(|level| level.volume * level.price).sum(),
+            bid_volume: bids.clone().map(|level| level.volume).sum(),
+            bid_value: bids.map(|level| level.volume * level.price).sum(),
+        })
+    }
+
+    // Whether the book can fill a market order of the side for the whole amount without moving the
+    // average price more than max_impact_bps from the mid price
+    pub fn depth_report(&self, side: &Side, amount: BookAmount, max_impact_bps: f64) -> Option<DepthReport> {
+        let fill = self.estimate_fill(side, amount)?;
+        let sufficient = fill.remaining == 0.0 && fill.impact_bps <= max_impact_bps;
+
+        Some(DepthReport {
+            fill,
+            max_impact_bps,
+            sufficient,
+        })
+    }
+}
+
+// Size of an order to fill from an order book
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BookAmount {
+    Base(f64),
+    Quote(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FillEstimate {
+    // Base currency filled
+    pub volume: f64,
+    // Quote currency spent or received
+    pub cost: f64,
+    pub vwap: f64,
+    // Price of the last level reached
+    pub worst_price: f64,
+    // Number of levels reached
+    pub levels: usize,
+    // Distance of the vwap from the mid price, positive when worse
+    pub impact_bps: f64,
+    // Part of the amount the book didn't have the volume for, in the currency of the amount
+    pub remaining: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Liquidity {
+    // Base currency
+    pub ask_volume: f64,
+    pub bid_volume: f64,
+    // Quote currency
+    pub ask_value: f64,
+    pub bid_value: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepthReport {
+    pub fill: FillEstimate,
+    pub max_impact_bps: f64,
+    pub sufficient: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candle {
+    pub time: DateTime<Utc>,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    // Volume weighted average price
+    pub vwap: f64,
+    pub volume: f64,
+    // Number of trades in the interval
+    pub count: u64,
+}
+
+// Kraken sends each candle as an array of
+// (<time>, <open>, <high>, <low>, <close>, <vwap>, <volume>, <count>)
+impl<'de> Deserialize<'de> for Candle {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let (time, open, high, low, close, vwap, volume, count): (
+            i64,
+            String,
+            String,
+            String,
+            String,
+            String,
+            String,
+            u64,
+        ) = Deserialize::deserialize(deserializer)?;
+
+        Ok(Self {
+            time: timestamp_to_datetime(time as f64)
+                .ok_or_else(|| D::Error::custom(format!("invalid timestamp {}", time)))?,
+            open: f64::from_str(&open).map_err(D::Error::custom)?,
+            high: f64::from_str(&high).map_err(D::Error::custom)?,
+            low: f64::from_str(&low).map_err(D::Error::custom)?,
+            close: f64::from_str(&close).map_err(D::Error::custom)?,
+            vwap: f64::from_str(&vwap).map_err(D::Error::custom)?,
+            volume: f64::from_str(&volume).map_err(D::Error::custom)?,
+            count,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Ohlc {
+    // Id to be used as since when polling for new, committed OHLC data
+    pub last: u64,
+    // Candles of the requested pair, keyed by the pair name
+    #[serde(flatten)]
+    pub pairs: HashMap<String, Vec<Candle>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PublicTrade {
+    pub price: f64,
+    pub volume: f64,
+    pub time: DateTime<Utc>,
+    pub side: Side,
+    pub ordertype: OrderType,
+    pub misc: String,
+    // Only sent by newer versions of the api
+    pub trade_id: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumberOrString {
+    Number(f64),
+    String(String),
+}
+
+impl NumberOrString {
+    fn to_f64(&self) -> Result<f64, ParseFloatError> {
+        match self {
+            Self::Number(number) => Ok(*number),
+            Self::String(s) => f64::from_str(s),
+        }
+    }
+}
+
+struct PublicTradeVisitor;
+
+impl<'de> Visitor<'de> for PublicTradeVisitor {
+    type Value = PublicTrade;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "an array of (<price>, <volume>, <time>, <buy/sell>, <market/limit>, <miscellaneous>)"
+        )
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let price: String = seq.next_element()?.ok_or_else(|| A::Error::invalid_length(0, &self))?;
+        let volume: String = seq.next_element()?.ok_or_else(|| A::Error::invalid_length(1, &self))?;
+        // The WebSocket trade feed sends the time as a string
+        let time: NumberOrString = seq.next_element()?.ok_or_else(|| A::Error::invalid_length(2, &self))?;
+        let time = time.to_f64().map_err(A::Error::custom)?;
+        let side: String = seq.next_element()?.ok_or_else(|| A::Error::invalid_length(3, &self))?;
+        let ordertype: String = seq.next_element()?.ok_or_else(|| A::Error::invalid_length(4, &self))?;
+        let misc: String = seq.next_element()?.ok_or_else(|| A::Error::invalid_length(5, &self))?;
+        let trade_id: Option<u64> = seq.next_element()?;
+
+        Ok(PublicTrade {
+            price: f64::from_str(&price).map_err(A::Error::custom)?,
+            volume: f64::from_str(&volume).map_err(A::Error::custom)?,
+            time: timestamp_to_datetime(time).ok_or_else(|| A::Error::custom(format!("invalid timestamp {}", time)))?,
+            // The trades endpoint uses the abbreviated b/s and m/l
+            side: match side.as_str() {
+                "b" => Side::Buy,
+                "s" => Side::Sell,
+                _ => Side::from(side.as_str()),
+            },
+            ordertype: match ordertype.as_str() {
+                "m" => OrderType::Market,
+                "l" => OrderType::Limit,
+                _ => OrderType::from(ordertype.as_str()),
+            },
+            misc,
+            trade_id,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for PublicTrade {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(PublicTradeVisitor)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecentTrades {
+    // Id to be used as since when polling for new trade data
+    pub last: String,
+    // Trades of the requested pair, keyed by the pair name
+    #[serde(flatten)]
+    pub pairs: HashMap<String, Vec<PublicTrade>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -302,73 +930,93 @@ pub struct TradeBalance {
 #[derive(Debug, Deserialize)]
 pub struct Order {
     // Referral order transaction id that created this order
-    refid: Option<String>,
+    pub refid: Option<String>,
     // User reference id
-    userref: Option<u64>,
+    pub userref: Option<u64>,
     // Status of order:
     //     pending = order pending book entry
     //     open = open order
     //     closed = closed order
     //     canceled = order canceled
     //     expired = order expired
-    status: String,
-    // Unix timestamp of when order was placed
-    opentm: f64,
-    // Unix timestamp of order start time (or 0 if not set)
-    starttm: f64,
-    // Unix timestamp of order end time (or 0 if not set)
-    expiretm: f64,
-    // Unix timestamp of when order was closed (available in closed order)
-    closetm: Option<f64>,
+    #[serde(deserialize_with = "from_enum_str")]
+    pub status: OrderStatus,
+    // Time of when order was placed
+    #[serde(deserialize_with = "from_timestamp")]
+    pub opentm: DateTime<Utc>,
+    // Order start time (None if not set)
+    #[serde(default)]
+    #[serde(deserialize_with = "from_timestamp_option")]
+    pub starttm: Option<DateTime<Utc>>,
+    // Order end time (None if not set)
+    #[serde(default)]
+    #[serde(deserialize_with = "from_timestamp_option")]
+    pub expiretm: Option<DateTime<Utc>>,
+    // Time of when order was closed (available in closed order)
+    #[serde(default)]
+    #[serde(deserialize_with = "from_timestamp_option")]
+    pub closetm: Option<DateTime<Utc>>,
     // Amount of available order info matching criteria (available in closed order)
-    reason: Option<String>,
-    descr: OpenOrderDescription,
+    pub reason: Option<String>,
+    pub descr: OpenOrderDescription,
     // Volume of order (base currency unless viqc set in oflags)
     #[serde(deserialize_with = "from_f64_str")]
-    vol: f64,
+    pub vol: f64,
     // Volume executed (base currency unless viqc set in oflags)
     #[serde(deserialize_with = "from_f64_str")]
-    vol_exec: f64,
+    pub vol_exec: f64,
     // Total cost (quote currency unless unless viqc set in oflags)
     #[serde(deserialize_with = "from_f64_str")]
-    cost: f64,
+    pub cost: f64,
     // Total fee (quote currency)
     #[serde(deserialize_with = "from_f64_str")]
-    fee: f64,
+    pub fee: f64,
     // Average price (quote currency unless viqc set in oflags)
+    #[serde(alias = "avg_price")]
     #[serde(deserialize_with = "from_f64_str")]
-    price: f64,
+    pub price: f64,
     // Stop price (quote currency, for trailing stops)
     #[serde(deserialize_with = "from_f64_str")]
-    stopprice: f64,
+    pub stopprice: f64,
     // Triggered limit price (quote currency, when limit based order type triggered)
     #[serde(deserialize_with = "from_f64_str")]
-    limitprice: f64,
+    pub limitprice: f64,
     // Comma delimited list of miscellaneous info
     //     stopped = triggered by stop price
     //     touched = triggered by touch price
     //     liquidated = liquidation
     //     partial = partial fill
-    misc: String,
+    #[serde(deserialize_with = "from_enum_str")]
+    pub misc: OrderMisc,
     // Comma delimited list of order flags
     //     viqc = volume in quote currency
     //     fcib = prefer fee in base currency (default if selling)
     //     fciq = prefer fee in quote currency (default if buying)
     //     nompp = no market price protection
-    oflags: String,
+    //     post = post only order
+    #[serde(deserialize_with = "from_enum_str")]
+    pub oflags: OrderFlags,
     // Array of trade ids related to order (if trades info requested and data available)
-    trades: Option<Vec<String>>,
+    pub trades: Option<Vec<String>>,
     // The amount of closed orders
-    count: Option<u64>,
+    pub count: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClosedOrdersPage {
+    pub closed: HashMap<String, Order>,
+    // The total amount of closed orders matching the criteria
+    pub count: u64,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct OpenOrderDescription {
     // Asset pair
-    pair: String,
+    pub pair: String,
     // Type of order
     #[serde(rename = "type")]
-    kind: String,
+    #[serde(deserialize_with = "from_enum_str")]
+    pub kind: Side,
     // Order type:
     //     market
     //     limit (price = limit price)
@@ -382,172 +1030,2221 @@ pub struct OpenOrderDescription {
     //     trailing-stop-limit (price = trailing stop offset, price2 = triggered limit offset)
     //     stop-loss-and-limit (price = stop loss price, price2 = limit price)
     //     settle-position
-    ordertype: String,
+    #[serde(deserialize_with = "from_enum_str")]
+    pub ordertype: OrderType,
     // Primary price
     #[serde(deserialize_with = "from_f64_str")]
-    price: f64,
+    pub price: f64,
     // Secondary price
     #[serde(deserialize_with = "from_f64_str")]
-    price2: f64,
+    pub price2: f64,
     // This could be a number if enabled in the account
-    leverage: String,
+    pub leverage: String,
     // Order description
-    order: String,
+    pub order: String,
     // Conditional close order description (if conditional close set)
-    close: String,
+    pub close: String,
 }
 
-pub struct Kraken {
-    credentials: Credentials,
-    client: Client,
-    urls: Urls,
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderStatus {
+    Pending,
+    Open,
+    Closed,
+    Canceled,
+    Expired,
+    Unknown(String),
 }
 
-// TODO add private methods:
-//  * query orders info
-//  * trades history
-//  * query trades info
-//  * open positions
-//  * ledgers info
-//  * query ledgers
-//  * trade volume
-//  * add order
-//  * cancel order
-//  Maybe change the naming of the params returned from kraken
-impl Kraken {
-    pub fn new(credentials: Credentials, urls: Urls) -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(30))
-            .build()
-            .expect("Can't create reqwest client");
+impl OrderStatus {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Pending => "pending",
+            Self::Open => "open",
+            Self::Closed => "closed",
+            Self::Canceled => "canceled",
+            Self::Expired => "expired",
+            Self::Unknown(status) => status,
+        }
+    }
+}
 
-        Self {
-            credentials,
-            client,
-            urls,
+impl From<&str> for OrderStatus {
+    fn from(s: &str) -> Self {
+        match s {
+            "pending" => Self::Pending,
+            "open" => Self::Open,
+            "closed" => Self::Closed,
+            "canceled" => Self::Canceled,
+            "expired" => Self::Expired,
+            _ => Self::Unknown(s.to_string()),
         }
     }
+}
 
-    pub async fn assets(&self, params: &[(&str, &str)]) -> Result<HashMap<String, Asset>, Errors> {
-        let request = self.client.get(&self.urls.assets).query(params);
-        let response = request.send().await?.json::<KrakenResponse>().await?;
+#[derive(Debug, Clone, PartialEq)]
+pub enum Side {
+    Buy,
+    Sell,
+    Unknown(String),
+}
 
-        if response.error.len() != 0 {
-            let error = response.error.join(" ");
-            return Err(Errors::Kraken(error));
+impl Side {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Buy => "buy",
+            Self::Sell => "sell",
+            Self::Unknown(side) => side,
         }
+    }
+}
 
-        match response.result.unwrap() {
-            Responses::Assets(response) => Ok(response),
-            _ => Err(Errors::InvalidFormat),
+impl From<&str> for Side {
+    fn from(s: &str) -> Self {
+        match s {
+            "buy" => Self::Buy,
+            "sell" => Self::Sell,
+            _ => Self::Unknown(s.to_string()),
         }
     }
+}
 
-    pub async fn asset_pairs(&self, params: &[(&str, &str)]) -> Result<AssetPairs, Errors> {
-        let request = self.client.get(&self.urls.asset_pairs).query(params);
-        let response = request.send().await?.json::<KrakenResponse>().await?;
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
 
-        if response.error.len() != 0 {
-            let error = response.error.join(" ");
-            return Err(Errors::Kraken(error));
-        }
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderType {
+    Market,
+    Limit,
+    StopLoss,
+    TakeProfit,
+    StopLossProfit,
+    StopLossProfitLimit,
+    StopLossLimit,
+    TakeProfitLimit,
+    TrailingStop,
+    TrailingStopLimit,
+    StopLossAndLimit,
+    SettlePosition,
+    Unknown(String),
+}
 
-        match response.result.unwrap() {
-            Responses::AssetPairs(response) => Ok(response),
-            _ => Err(Errors::InvalidFormat),
+impl OrderType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Market => "market",
+            Self::Limit => "limit",
+            Self::StopLoss => "stop-loss",
+            Self::TakeProfit => "take-profit",
+            Self::StopLossProfit => "stop-loss-profit",
+            Self::StopLossProfitLimit => "stop-loss-profit-limit",
+            Self::StopLossLimit => "stop-loss-limit",
+            Self::TakeProfitLimit => "take-profit-limit",
+            Self::TrailingStop => "trailing-stop",
+            Self::TrailingStopLimit => "trailing-stop-limit",
+            Self::StopLossAndLimit => "stop-loss-and-limit",
+            Self::SettlePosition => "settle-position",
+            Self::Unknown(order_type) => order_type,
         }
     }
+}
 
-    pub async fn ticker(&self, params: &[(&str, &str)]) -> Result<HashMap<String, Ticker>, Errors> {
-        let request = self.client.get(&self.urls.ticker).query(params);
-        let response = request.send().await?.json::<KrakenResponse>().await?;
-
-        if response.error.len() != 0 {
-            let error = response.error.join(" ");
-            return Err(Errors::Kraken(error));
+impl From<&str> for OrderType {
+    fn from(s: &str) -> Self {
+        match s {
+            "market" => Self::Market,
+            "limit" => Self::Limit,
+            "stop-loss" => Self::StopLoss,
+            "take-profit" => Self::TakeProfit,
+            "stop-loss-profit" => Self::StopLossProfit,
+            "stop-loss-profit-limit" => Self::StopLossProfitLimit,
+            "stop-loss-limit" => Self::StopLossLimit,
+            "take-profit-limit" => Self::TakeProfitLimit,
+            "trailing-stop" => Self::TrailingStop,
+            "trailing-stop-limit" => Self::TrailingStopLimit,
+            "stop-loss-and-limit" => Self::StopLossAndLimit,
+            "settle-position" => Self::SettlePosition,
+            _ => Self::Unknown(s.to_string()),
         }
+    }
+}
 
-        match response.result.unwrap() {
-            Responses::Ticker(response) => Ok(response),
-            _ => Err(Errors::InvalidFormat),
-        }
+impl fmt::Display for OrderType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
     }
+}
 
-    pub async fn order_book(&self, params: &[(&str, &str)]) -> Result<HashMap<String, OrderBook>, Errors> {
-        let request = self.client.get(&self.urls.order_book).query(params);
-        let response = request.send().await?.json::<KrakenResponse>().await?;
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderFlag {
+    // Volume in quote currency
+    Viqc,
+    // Prefer fee in base currency
+    Fcib,
+    // Prefer fee in quote currency
+    Fciq,
+    // No market price protection
+    Nompp,
+    // Post only order
+    Post,
+    Unknown(String),
+}
 
-        if response.error.len() != 0 {
-            let error = response.error.join(" ");
-            return Err(Errors::Kraken(error));
+impl OrderFlag {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Viqc => "viqc",
+            Self::Fcib => "fcib",
+            Self::Fciq => "fciq",
+            Self::Nompp => "nompp",
+            Self::Post => "post",
+            Self::Unknown(flag) => flag,
         }
+    }
+}
 
-        match response.result.unwrap() {
-            Responses::OrderBook(response) => Ok(response),
-            _ => Err(Errors::InvalidFormat),
+impl From<&str> for OrderFlag {
+    fn from(s: &str) -> Self {
+        match s {
+            "viqc" => Self::Viqc,
+            "fcib" => Self::Fcib,
+            "fciq" => Self::Fciq,
+            "nompp" => Self::Nompp,
+            "post" => Self::Post,
+            _ => Self::Unknown(s.to_string()),
         }
     }
+}
 
-    pub async fn account_balance(&self, params: &[(&str, &str)]) -> Result<HashMap<String, String>, Errors> {
-        let request = self.private_request(&self.urls.account_balance, params)?;
-        let response = request.send().await?.json::<KrakenResponse>().await?;
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OrderFlags(pub Vec<OrderFlag>);
 
-        if response.error.len() != 0 {
-            let error = response.error.join(" ");
-            return Err(Errors::Kraken(error));
-        }
+impl OrderFlags {
+    pub fn contains(&self, flag: &OrderFlag) -> bool {
+        self.0.contains(flag)
+    }
 
-        match response.result.unwrap() {
-            Responses::Balance(response) => Ok(response),
-            _ => Err(Errors::InvalidFormat),
-        }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
     }
 
-    pub async fn trade_balance(&self, params: &[(&str, &str)]) -> Result<TradeBalance, Errors> {
-        let request = self.private_request(&self.urls.trade_balance, params)?;
-        let response = request.send().await?.json::<KrakenResponse>().await?;
+    pub fn iter(&self) -> impl Iterator<Item = &OrderFlag> {
+        self.0.iter()
+    }
+}
 
-        if response.error.len() != 0 {
-            let error = response.error.join(" ");
-            return Err(Errors::Kraken(error));
-        }
+// Kraken sends the flags as a comma delimited list
+impl From<&str> for OrderFlags {
+    fn from(s: &str) -> Self {
+        Self(
+            s.split(',')
+                .filter(|flag| !flag.is_empty())
+                .map(OrderFlag::from)
+                .collect(),
+        )
+    }
+}
 
-        match response.result.unwrap() {
-            Responses::TradeBalance(response) => Ok(response),
-            _ => Err(Errors::InvalidFormat),
-        }
+impl fmt::Display for OrderFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags: Vec<&str> = self.iter().map(|flag| flag.as_str()).collect();
+        write!(f, "{}", flags.join(","))
     }
+}
 
-    pub async fn open_orders(&self, params: &[(&str, &str)]) -> Result<HashMap<String, Order>, Errors> {
-        let request = self.private_request(&self.urls.open_orders, params)?;
-        let response = request.send().await?.json::<KrakenResponse>().await?;
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderMiscInfo {
+    // Triggered by stop price
+    Stopped,
+    // Triggered by touch price
+    Touched,
+    Liquidated,
+    // Partial fill
+    Partial,
+    Unknown(String),
+}
 
-        if response.error.len() != 0 {
-            let error = response.error.join(" ");
-            return Err(Errors::Kraken(error));
+impl OrderMiscInfo {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Stopped => "stopped",
+            Self::Touched => "touched",
+            Self::Liquidated => "liquidated",
+            Self::Partial => "partial",
+            Self::Unknown(info) => info,
         }
+    }
+}
 
-        match response.result.unwrap() {
-            Responses::OpenOrders { open } => Ok(open),
-            _ => Err(Errors::InvalidFormat),
+impl From<&str> for OrderMiscInfo {
+    fn from(s: &str) -> Self {
+        match s {
+            "stopped" => Self::Stopped,
+            "touched" => Self::Touched,
+            "liquidated" => Self::Liquidated,
+            "partial" => Self::Partial,
+            _ => Self::Unknown(s.to_string()),
         }
     }
+}
 
-    pub async fn closed_orders(&self, params: &[(&str, &str)]) -> Result<HashMap<String, Order>, Errors> {
-        let request = self.private_request(&self.urls.closed_orders, params)?;
-        let response = request.send().await?.json::<KrakenResponse>().await?;
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OrderMisc(pub Vec<OrderMiscInfo>);
 
-        if response.error.len() != 0 {
-            let error = response.error.join(" ");
+impl OrderMisc {
+    pub fn contains(&self, info: &OrderMiscInfo) -> bool {
+        self.0.contains(info)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &OrderMiscInfo> {
+        self.0.iter()
+    }
+}
+
+// Kraken sends the info as a comma delimited list
+impl From<&str> for OrderMisc {
+    fn from(s: &str) -> Self {
+        Self(
+            s.split(',')
+                .filter(|info| !info.is_empty())
+                .map(OrderMiscInfo::from)
+                .collect(),
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Trade {
+    // Order responsible for execution of trade
+    pub ordertxid: String,
+    // Position id the trade belongs to
+    #[serde(default)]
+    pub postxid: Option<String>,
+    // Asset pair
+    pub pair: String,
+    // Time of trade
+    #[serde(deserialize_with = "from_timestamp")]
+    pub time: DateTime<Utc>,
+    // Type of order (buy/sell)
+    #[serde(rename = "type")]
+    #[serde(deserialize_with = "from_enum_str")]
+    pub kind: Side,
+    #[serde(deserialize_with = "from_enum_str")]
+    pub ordertype: OrderType,
+    // Average price order was executed at (quote currency)
+    #[serde(deserialize_with = "from_f64_str")]
+    pub price: f64,
+    // Total cost of order (quote currency)
+    #[serde(deserialize_with = "from_f64_str")]
+    pub cost: f64,
+    // Total fee (quote currency)
+    #[serde(deserialize_with = "from_f64_str")]
+    pub fee: f64,
+    // Volume (base currency)
+    #[serde(deserialize_with = "from_f64_str")]
+    pub vol: f64,
+    // Initial margin (quote currency)
+    #[serde(deserialize_with = "from_f64_str")]
+    pub margin: f64,
+    // Comma delimited list of miscellaneous info
+    //     closing = trade closes all or part of a position
+    #[serde(default)]
+    pub misc: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TradesHistoryPage {
+    pub trades: HashMap<String, Trade>,
+    // The total amount of trades matching the criteria
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LedgerType {
+    Trade,
+    Deposit,
+    Withdrawal,
+    Transfer,
+    Margin,
+    Rollover,
+    Spend,
+    Receive,
+    Settled,
+    Adjustment,
+    Staking,
+    Unknown(String),
+}
+
+impl LedgerType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Trade => "trade",
+            Self::Deposit => "deposit",
+            Self::Withdrawal => "withdrawal",
+            Self::Transfer => "transfer",
+            Self::Margin => "margin",
+            Self::Rollover => "rollover",
+            Self::Spend => "spend",
+            Self::Receive => "receive",
+            Self::Settled => "settled",
+            Self::Adjustment => "adjustment",
+            Self::Staking => "staking",
+            Self::Unknown(kind) => kind,
+        }
+    }
+}
+
+impl From<&str> for LedgerType {
+    fn from(s: &str) -> Self {
+        match s {
+            "trade" => Self::Trade,
+            "deposit" => Self::Deposit,
+            "withdrawal" => Self::Withdrawal,
+            "transfer" => Self::Transfer,
+            "margin" => Self::Margin,
+            "rollover" => Self::Rollover,
+            "spend" => Self::Spend,
+            "receive" => Self::Receive,
+            "settled" => Self::Settled,
+            "adjustment" => Self::Adjustment,
+            "staking" => Self::Staking,
+            _ => Self::Unknown(s.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LedgerEntry {
+    // Reference id
+    pub refid: String,
+    // Time of ledger
+    #[serde(deserialize_with = "from_timestamp")]
+    pub time: DateTime<Utc>,
+    // Type of ledger entry
+    #[serde(rename = "type")]
+    #[serde(deserialize_with = "from_enum_str")]
+    pub kind: LedgerType,
+    // Additional info relating to the ledger entry type, where applicable
+    #[serde(default)]
+    pub subtype: Option<String>,
+    // Asset class
+    pub aclass: String,
+    pub asset: String,
+    // Transaction amount
+    #[serde(deserialize_with = "from_f64_str")]
+    pub amount: f64,
+    // Transaction fee
+    #[serde(deserialize_with = "from_f64_str")]
+    pub fee: f64,
+    // Resulting balance
+    #[serde(deserialize_with = "from_f64_str")]
+    pub balance: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LedgersPage {
+    pub ledger: HashMap<String, LedgerEntry>,
+    // The total amount of ledger entries matching the criteria
+    pub count: u64,
+}
+
+impl OpenOrderDescription {
+    pub fn parse_order(&self) -> Result<OrderDescription, Errors> {
+        OrderDescription::from_str(&self.order)
+    }
+
+    // None if there is no conditional close set
+    pub fn parse_close(&self) -> Result<Option<OrderDescription>, Errors> {
+        if self.close.trim().is_empty() {
+            return Ok(None);
+        }
+
+        OrderDescription::from_str(&self.close).map(Some)
+    }
+}
+
+// Structured form of the human readable order descriptions Kraken returns, such as
+//     buy 1.25000000 XBTUSD @ limit 9000.0 with 2:1 leverage
+//     sell 0.50000000 XBTUSD @ stop loss 8000.0 -> limit 7900.0
+//     close position @ take profit 9500.0
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderDescription {
+    // None when closing a position
+    pub side: Option<Side>,
+    // None when closing a position
+    pub volume: Option<f64>,
+    // None when closing a position
+    pub pair: Option<String>,
+    pub ordertype: OrderType,
+    // Primary price
+    pub price: Option<DescriptionPrice>,
+    // Secondary price
+    pub price2: Option<DescriptionPrice>,
+    pub leverage: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PriceKind {
+    Absolute,
+    // +5 or -5, added to the last price. The value keeps its sign
+    Relative,
+    // #5, added to or subtracted from the last price depending on the side and the order type
+    Directional,
+}
+
+// Price of an order description, without its markers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DescriptionPrice {
+    pub value: f64,
+    pub kind: PriceKind,
+    // The value is a percentage of the last price, as in +5%. Percentages are always relative, so a bare 5%
+    // is Relative
+    pub percent: bool,
+}
+
+impl OrderDescription {
+    pub fn is_close_position(&self) -> bool {
+        self.side.is_none()
+    }
+}
+
+fn parse_description_price(price: &str) -> Option<DescriptionPrice> {
+    let (price, percent) = match price.strip_suffix('%') {
+        Some(price) => (price, true),
+        None => (price, false),
+    };
+    let (price, kind) = match price.chars().next() {
+        Some('+') => (&price[1..], PriceKind::Relative),
+        Some('-') => (price, PriceKind::Relative),
+        Some('#') => (&price[1..], PriceKind::Directional),
+        _ if percent => (price, PriceKind::Relative),
+        _ => (price, PriceKind::Absolute),
+    };
+
+    Some(DescriptionPrice {
+        value: f64::from_str(price).ok()?,
+        kind,
+        percent,
+    })
+}
+
+// Splits "stop loss 8000.0" into the order type words and the price following them
+fn parse_description_type(words: &[&str]) -> (String, Option<DescriptionPrice>) {
+    match words.split_last() {
+        Some((last, rest)) => match parse_description_price(last) {
+            Some(price) => (rest.join("-"), Some(price)),
+            None => (words.join("-"), None),
+        },
+        None => (String::new(), None),
+    }
+}
+
+impl FromStr for OrderDescription {
+    type Err = Errors;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Errors::Parse(format!("Invalid order description: {}", s));
+
+        let (head, tail) = match s.find(" @ ") {
+            Some(index) => (&s[..index], &s[index + 3..]),
+            None => return Err(invalid()),
+        };
+
+        let head: Vec<&str> = head.split_whitespace().collect();
+        let (side, volume, pair) = match head.as_slice() {
+            ["close", "position"] => (None, None, None),
+            [side, volume, pair] => {
+                let volume = f64::from_str(volume).map_err(|_| invalid())?;
+                (Some(Side::from(*side)), Some(volume), Some(pair.to_string()))
+            }
+            _ => return Err(invalid()),
+        };
+
+        let (tail, leverage) = match tail.find(" with ") {
+            Some(index) => {
+                let leverage: Vec<&str> = tail[index + 6..].split_whitespace().collect();
+                let leverage = match leverage.as_slice() {
+                    [ratio, "leverage"] => ratio
+                        .split(':')
+                        .next()
+                        .and_then(|leverage| u64::from_str(leverage).ok())
+                        .ok_or_else(invalid)?,
+                    _ => return Err(invalid()),
+                };
+                (&tail[..index], Some(leverage))
+            }
+            None => (tail, None),
+        };
+
+        let mut parts = tail.split("->");
+        let primary: Vec<&str> = parts.next().unwrap_or("").split_whitespace().collect();
+        let secondary: Vec<&str> = parts.next().unwrap_or("").split_whitespace().collect();
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+
+        let (primary, price) = parse_description_type(&primary);
+        let (secondary, price2) = parse_description_type(&secondary);
+        if primary.is_empty() {
+            return Err(invalid());
+        }
+
+        let ordertype = match (primary.as_str(), secondary.as_str()) {
+            (primary, "") => OrderType::from(primary),
+            ("stop-loss", "take-profit") => OrderType::StopLossProfit,
+            (primary, "limit") => OrderType::from(format!("{}-limit", primary).as_str()),
+            (primary, secondary) => OrderType::Unknown(format!("{} -> {}", primary, secondary)),
+        };
+
+        Ok(Self {
+            side,
+            volume,
+            pair,
+            ordertype,
+            price,
+            price2,
+            leverage,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DepositMethod {
+    // Name of deposit method
+    pub method: String,
+    // Maximum net amount that can be deposited right now (None if there is no limit)
+    #[serde(deserialize_with = "from_f64_str_or_false")]
+    pub limit: Option<f64>,
+    // Amount of fees that will be paid
+    #[serde(default)]
+    #[serde(deserialize_with = "from_f64_option_str")]
+    pub fee: Option<f64>,
+    // Address setup fee
+    #[serde(rename = "address-setup-fee")]
+    #[serde(default)]
+    #[serde(deserialize_with = "from_f64_option_str")]
+    pub address_setup_fee: Option<f64>,
+    // Whether or not method has an address setup fee
+    #[serde(rename = "gen-address")]
+    #[serde(default)]
+    pub gen_address: bool,
+    // Minimum net amount that can be deposited
+    #[serde(default)]
+    #[serde(deserialize_with = "from_f64_option_str")]
+    pub minimum: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DepositAddress {
+    pub address: String,
+    // Expiration time (None if not expiring)
+    #[serde(default)]
+    #[serde(deserialize_with = "from_timestamp_str_option")]
+    pub expiretm: Option<DateTime<Utc>>,
+    // Whether or not the address has ever been used
+    #[serde(default)]
+    pub new: bool,
+    // Destination tag, needed by assets such as XRP
+    #[serde(default)]
+    pub tag: Option<String>,
+    // Memo, needed by assets such as XLM and EOS
+    #[serde(default)]
+    pub memo: Option<String>,
+}
+
+// Status of a deposit or a withdrawal
+#[derive(Debug, Clone, PartialEq)]
+pub enum FundingStatus {
+    Initial,
+    Pending,
+    Settled,
+    Success,
+    Failure,
+    Unknown(String),
+}
+
+impl FundingStatus {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Initial => "Initial",
+            Self::Pending => "Pending",
+            Self::Settled => "Settled",
+            Self::Success => "Success",
+            Self::Failure => "Failure",
+            Self::Unknown(status) => status,
+        }
+    }
+}
+
+impl From<&str> for FundingStatus {
+    fn from(s: &str) -> Self {
+        match s {
+            "Initial" => Self::Initial,
+            "Pending" => Self::Pending,
+            "Settled" => Self::Settled,
+            "Success" => Self::Success,
+            "Failure" => Self::Failure,
+            _ => Self::Unknown(s.to_string()),
+        }
+    }
+}
+
+// Additional status property of a deposit or a withdrawal
+#[derive(Debug, Clone, PartialEq)]
+pub enum FundingStatusProp {
+    // Cancelation requested
+    CancelPending,
+    Canceled,
+    // Cancelation requested but was denied
+    CancelDenied,
+    // A return transaction initiated by Kraken
+    Return,
+    // On hold pending review
+    OnHold,
+    Unknown(String),
+}
+
+impl From<&str> for FundingStatusProp {
+    fn from(s: &str) -> Self {
+        match s {
+            "cancel-pending" => Self::CancelPending,
+            "canceled" => Self::Canceled,
+            "cancel-denied" => Self::CancelDenied,
+            "return" => Self::Return,
+            "onhold" => Self::OnHold,
+            _ => Self::Unknown(s.to_string()),
+        }
+    }
+}
+
+fn from_funding_status_prop<'de, D>(deserializer: D) -> Result<Option<FundingStatusProp>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: Option<String> = Deserialize::deserialize(deserializer)?;
+
+    Ok(s.map(|s| FundingStatusProp::from(s.as_str())))
+}
+
+// A deposit or a withdrawal
+#[derive(Debug, Deserialize)]
+pub struct FundingTransaction {
+    // Name of the method used
+    pub method: String,
+    // Asset class
+    pub aclass: String,
+    pub asset: String,
+    // Reference id
+    pub refid: String,
+    // Method transaction id
+    pub txid: String,
+    // Method transaction information
+    pub info: String,
+    // Amount deposited or withdrawn
+    #[serde(deserialize_with = "from_f64_str")]
+    pub amount: f64,
+    // Fees paid
+    #[serde(default)]
+    #[serde(deserialize_with = "from_f64_option_str")]
+    pub fee: Option<f64>,
+    // Time when request was made
+    #[serde(deserialize_with = "from_timestamp")]
+    pub time: DateTime<Utc>,
+    #[serde(deserialize_with = "from_enum_str")]
+    pub status: FundingStatus,
+    #[serde(rename = "status-prop")]
+    #[serde(default)]
+    #[serde(deserialize_with = "from_funding_status_prop")]
+    pub status_prop: Option<FundingStatusProp>,
+}
+
+// Reference id of a funding request, such as a withdrawal
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+pub struct RefId(pub String);
+
+impl RefId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RefId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+fn validate_amount(amount: f64) -> Result<f64, Errors> {
+    if !amount.is_finite() || amount <= 0.0 {
+        return Err(Errors::InvalidParams(format!("Invalid amount: {}", amount)));
+    }
+
+    Ok(amount)
+}
+
+// Name of the withdrawal key, as set up in the account
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WithdrawalKey(String);
+
+impl WithdrawalKey {
+    pub fn new(key: &str) -> Self {
+        Self(key.to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WithdrawalAmount(f64);
+
+impl WithdrawalAmount {
+    pub fn new(amount: f64) -> Result<Self, Errors> {
+        validate_amount(amount)?;
+
+        Ok(Self(amount))
+    }
+
+    pub fn value(&self) -> f64 {
+        self.0
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct WithdrawInfo {
+    // Name of the withdrawal method that will be used
+    method: String,
+    // Maximum net amount that can be withdrawn right now
+    #[serde(deserialize_with = "from_f64_str")]
+    limit: f64,
+    // Net amount that will be sent, after fees
+    #[serde(deserialize_with = "from_f64_str")]
+    amount: f64,
+    // Amount of fees that will be paid
+    #[serde(deserialize_with = "from_f64_str")]
+    fee: f64,
+}
+
+// A withdrawal that had its fee and limit fetched with withdraw_info. It can only be created by withdraw_info,
+// and it has to be passed to withdraw to move the funds
+#[derive(Debug)]
+pub struct WithdrawalPlan {
+    asset: String,
+    key: WithdrawalKey,
+    amount: WithdrawalAmount,
+    info: WithdrawInfo,
+}
+
+impl WithdrawalPlan {
+    pub fn asset(&self) -> &str {
+        &self.asset
+    }
+
+    pub fn key(&self) -> &WithdrawalKey {
+        &self.key
+    }
+
+    // The requested amount
+    pub fn amount(&self) -> WithdrawalAmount {
+        self.amount
+    }
+
+    pub fn method(&self) -> &str {
+        &self.info.method
+    }
+
+    pub fn limit(&self) -> f64 {
+        self.info.limit
+    }
+
+    // The amount that will be sent, after fees
+    pub fn net_amount(&self) -> f64 {
+        self.info.amount
+    }
+
+    pub fn fee(&self) -> f64 {
+        self.info.fee
+    }
+
+    pub fn exceeds_limit(&self) -> bool {
+        self.info.amount > self.info.limit
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Wallet {
+    Spot,
+    Futures,
+}
+
+impl Wallet {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Spot => "Spot Wallet",
+            Self::Futures => "Futures Wallet",
+        }
+    }
+}
+
+// Id of a transfer between a master account and its subaccounts
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+pub struct TransferId(pub String);
+
+impl TransferId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for TransferId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransferStatus {
+    Pending,
+    Complete,
+    Unknown(String),
+}
+
+impl From<&str> for TransferStatus {
+    fn from(s: &str) -> Self {
+        match s {
+            "pending" => Self::Pending,
+            "complete" => Self::Complete,
+            _ => Self::Unknown(s.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AccountTransfer {
+    pub transfer_id: TransferId,
+    #[serde(deserialize_with = "from_enum_str")]
+    pub status: TransferStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportKind {
+    Trades,
+    Ledgers,
+}
+
+impl ReportKind {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Trades => "trades",
+            Self::Ledgers => "ledgers",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Csv,
+    Tsv,
+}
+
+impl ReportFormat {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Csv => "CSV",
+            Self::Tsv => "TSV",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportRequest {
+    pub report: ReportKind,
+    pub format: ReportFormat,
+    pub description: String,
+    // Fields to include in the report, all of them if empty
+    pub fields: Vec<String>,
+    // Beginning of the report (None for the beginning of the history)
+    pub starttm: Option<DateTime<Utc>>,
+    // End of the report (None for now)
+    pub endtm: Option<DateTime<Utc>>,
+}
+
+impl ExportRequest {
+    pub fn new(report: ReportKind, description: &str) -> Self {
+        Self {
+            report,
+            format: ReportFormat::Csv,
+            description: description.to_string(),
+            fields: Vec::new(),
+            starttm: None,
+            endtm: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+pub struct ExportId(pub String);
+
+impl ExportId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for ExportId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportStatus {
+    Queued,
+    Processing,
+    Processed,
+    Unknown(String),
+}
+
+impl From<&str> for ExportStatus {
+    fn from(s: &str) -> Self {
+        match s {
+            "Queued" => Self::Queued,
+            "Processing" => Self::Processing,
+            "Processed" => Self::Processed,
+            _ => Self::Unknown(s.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportReport {
+    pub id: ExportId,
+    pub descr: String,
+    pub format: String,
+    pub report: String,
+    #[serde(deserialize_with = "from_enum_str")]
+    pub status: ExportStatus,
+    // Comma delimited list of the fields in the report
+    pub fields: String,
+    // Time of when the report was requested
+    #[serde(default)]
+    #[serde(deserialize_with = "from_timestamp_str_option")]
+    pub createdtm: Option<DateTime<Utc>>,
+    // Time of when the report processing started
+    #[serde(default)]
+    #[serde(deserialize_with = "from_timestamp_str_option")]
+    pub starttm: Option<DateTime<Utc>>,
+    // Time of when the report was completed
+    #[serde(default)]
+    #[serde(deserialize_with = "from_timestamp_str_option")]
+    pub completedtm: Option<DateTime<Utc>>,
+    // Beginning of the data in the report
+    #[serde(default)]
+    #[serde(deserialize_with = "from_timestamp_str_option")]
+    pub datastarttm: Option<DateTime<Utc>>,
+    // End of the data in the report
+    #[serde(default)]
+    #[serde(deserialize_with = "from_timestamp_str_option")]
+    pub dataendtm: Option<DateTime<Utc>>,
+    // Asset class
+    #[serde(default)]
+    pub aclass: Option<String>,
+    // Comma delimited list of the assets in the report
+    #[serde(default)]
+    pub asset: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RemoveExportKind {
+    // Cancels a queued or processing report
+    Cancel,
+    // Deletes a processed report
+    Delete,
+}
+
+impl RemoveExportKind {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Cancel => "cancel",
+            Self::Delete => "delete",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RemoveExport {
+    #[serde(default)]
+    delete: bool,
+    #[serde(default)]
+    cancel: bool,
+}
+
+// Kind of the balance, taken from the suffix of the asset name in account_balance
+#[derive(Debug, Clone, PartialEq)]
+pub enum BalanceKind {
+    // No suffix
+    Spot,
+    // .S, staked on chain
+    Staked,
+    // .M, opted in to rewards
+    OptIn,
+    // Any other suffix
+    Other(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceAsset {
+    // Asset name without the suffix
+    pub asset: String,
+    pub kind: BalanceKind,
+}
+
+impl BalanceAsset {
+    pub fn is_staked(&self) -> bool {
+        self.kind == BalanceKind::Staked
+    }
+}
+
+impl From<&str> for BalanceAsset {
+    fn from(s: &str) -> Self {
+        let (asset, kind) = match s.rfind('.') {
+            Some(index) => {
+                let kind = match &s[index + 1..] {
+                    "S" => BalanceKind::Staked,
+                    "M" => BalanceKind::OptIn,
+                    suffix => BalanceKind::Other(suffix.to_string()),
+                };
+                (&s[..index], kind)
+            }
+            None => (s, BalanceKind::Spot),
+        };
+
+        Self {
+            asset: asset.to_string(),
+            kind,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RewardRate {
+    // Reward earned while staking
+    #[serde(deserialize_with = "from_f64_str")]
+    pub reward: f64,
+    // Type of the reward, such as percentage
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StakingMinimumAmount {
+    #[serde(deserialize_with = "from_f64_str")]
+    pub staking: f64,
+    #[serde(deserialize_with = "from_f64_str")]
+    pub unstaking: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StakeableAsset {
+    // Unique id of the staking option, used when staking
+    pub method: String,
+    pub asset: String,
+    // Asset code of the staked balance, such as DOT.S
+    pub staking_asset: String,
+    pub rewards: RewardRate,
+    // Whether the staking operation is on chain or not
+    #[serde(default)]
+    pub on_chain: bool,
+    #[serde(default)]
+    pub can_stake: bool,
+    #[serde(default)]
+    pub can_unstake: bool,
+    #[serde(default)]
+    pub minimum_amount: Option<StakingMinimumAmount>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StakingTransactionType {
+    Bonding,
+    Reward,
+    Unbonding,
+    Unknown(String),
+}
+
+impl From<&str> for StakingTransactionType {
+    fn from(s: &str) -> Self {
+        match s {
+            "bonding" => Self::Bonding,
+            "reward" => Self::Reward,
+            "unbonding" => Self::Unbonding,
+            _ => Self::Unknown(s.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StakingTransaction {
+    // Staking method, as returned by stakeable_assets
+    pub method: String,
+    // Asset class
+    pub aclass: String,
+    pub asset: String,
+    pub refid: RefId,
+    #[serde(deserialize_with = "from_f64_str")]
+    pub amount: f64,
+    #[serde(default)]
+    #[serde(deserialize_with = "from_f64_option_str")]
+    pub fee: Option<f64>,
+    // Time of when the transaction was created
+    #[serde(deserialize_with = "from_timestamp")]
+    pub time: DateTime<Utc>,
+    #[serde(deserialize_with = "from_enum_str")]
+    pub status: FundingStatus,
+    #[serde(rename = "type")]
+    #[serde(deserialize_with = "from_enum_str")]
+    pub kind: StakingTransactionType,
+    // Start of the bonding or unbonding period
+    #[serde(default)]
+    #[serde(deserialize_with = "from_timestamp_option")]
+    pub bond_start: Option<DateTime<Utc>>,
+    // End of the bonding or unbonding period
+    #[serde(default)]
+    #[serde(deserialize_with = "from_timestamp_option")]
+    pub bond_end: Option<DateTime<Utc>>,
+}
+
+// Order to be placed with add_order, through either the REST or the WebSocket API
+#[derive(Debug, Clone)]
+pub struct NewOrder {
+    // Asset pair. The WebSocket API accepts the WebSocket name only, such as XBT/USD
+    pub pair: String,
+    pub side: Side,
+    pub ordertype: OrderType,
+    // Volume (base currency unless viqc set in oflags)
+    pub volume: f64,
+    // Price, depending on the order type as described in OpenOrderDescription
+    pub price: Option<f64>,
+    // Secondary price, depending on the order type as described in OpenOrderDescription
+    pub price2: Option<f64>,
+    // None for no leverage
+    pub leverage: Option<u64>,
+    pub oflags: OrderFlags,
+    // User reference id, to find the order in the responses of open_orders and closed_orders
+    pub userref: Option<i32>,
+    // Only validates the order, without placing it
+    pub validate: bool,
+}
+
+impl NewOrder {
+    pub fn new(pair: &str, side: Side, ordertype: OrderType, volume: f64) -> Self {
+        Self {
+            pair: pair.to_string(),
+            side,
+            ordertype,
+            volume,
+            price: None,
+            price2: None,
+            leverage: None,
+            oflags: OrderFlags(Vec::new()),
+            userref: None,
+            validate: false,
+        }
+    }
+
+    // Params shared by the REST and the WebSocket APIs
+    pub(crate) fn params(&self) -> Result<Vec<(&'static str, String)>, Errors> {
+        let mut params = vec![
+            ("pair", self.pair.clone()),
+            ("type", self.side.to_string()),
+            ("ordertype", self.ordertype.to_string()),
+            ("volume", validate_amount(self.volume)?.to_string()),
+        ];
+        if let Some(price) = self.price {
+            params.push(("price", price.to_string()));
+        }
+        if let Some(price2) = self.price2 {
+            params.push(("price2", price2.to_string()));
+        }
+        if let Some(leverage) = self.leverage {
+            params.push(("leverage", leverage.to_string()));
+        }
+        if !self.oflags.is_empty() {
+            params.push(("oflags", self.oflags.to_string()));
+        }
+        if let Some(userref) = self.userref {
+            params.push(("userref", userref.to_string()));
+        }
+        if self.validate {
+            params.push(("validate", "true".to_string()));
+        }
+
+        Ok(params)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddedOrder {
+    // Description of the order, such as "buy 1.45 XBTUSD @ limit 8000"
+    pub description: String,
+    // Description of the conditional close order, if one was set
+    pub close: Option<String>,
+    // Ids of the placed orders. Empty if the order was only validated
+    pub txid: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AddOrderDescription {
+    order: String,
+    #[serde(default)]
+    close: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AddOrderResponse {
+    descr: AddOrderDescription,
+    #[serde(default)]
+    txid: Vec<String>,
+}
+
+impl From<AddOrderResponse> for AddedOrder {
+    fn from(response: AddOrderResponse) -> Self {
+        Self {
+            description: response.descr.order,
+            close: response.descr.close.filter(|close| !close.is_empty()),
+            txid: response.txid,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebSocketsToken {
+    // Token to authenticate the private WebSocket feeds with
+    pub token: String,
+    // Seconds until the token expires, if it isn't used to establish a connection before that
+    pub expires: u64,
+}
+
+impl WebSocketsToken {
+    pub fn expires_in(&self) -> Duration {
+        Duration::from_secs(self.expires)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TradeVolume {
+    // Currency of the volume, the fee_volume_currency of the pairs
+    pub currency: String,
+    // 30 day trade volume, which decides the fee tier
+    #[serde(deserialize_with = "from_f64_str")]
+    pub volume: f64,
+    // Taker fees of the requested pairs, keyed by the pair name
+    #[serde(default)]
+    pub fees: HashMap<String, TradeVolumeFee>,
+    // Maker fees of the requested pairs that have a maker schedule
+    #[serde(default)]
+    pub fees_maker: HashMap<String, TradeVolumeFee>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TradeVolumeFee {
+    // Current fee in percent
+    #[serde(deserialize_with = "from_f64_str")]
+    pub fee: f64,
+    #[serde(deserialize_with = "from_f64_str")]
+    pub minfee: f64,
+    #[serde(deserialize_with = "from_f64_str")]
+    pub maxfee: f64,
+    // Fee of the next tier, none at the last one
+    #[serde(default)]
+    #[serde(deserialize_with = "from_f64_option_str")]
+    pub nextfee: Option<f64>,
+    // Volume needed for the next tier
+    #[serde(default)]
+    #[serde(deserialize_with = "from_f64_option_str")]
+    pub nextvolume: Option<f64>,
+    #[serde(default)]
+    #[serde(deserialize_with = "from_f64_option_str")]
+    pub tiervolume: Option<f64>,
+}
+
+pub struct Kraken {
+    credentials: Credentials,
+    client: Client,
+    urls: Urls,
+    // Resolves the names of assets and pairs in the params, when set
+    registry: Option<registry::AssetRegistry>,
+}
+
+// TODO add private methods:
+//  * query orders info
+//  * query trades info
+//  * open positions
+//  * query ledgers
+//  * add order
+//  * cancel order
+//  Maybe change the naming of the params returned from kraken
+impl Kraken {
+    pub fn new(credentials: Credentials, urls: Urls) -> Self {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .expect("Can't create reqwest client");
+
+        Self {
+            credentials,
+            client,
+            urls,
+            registry: None,
+        }
+    }
+
+    // Lets every method take the names of assets and pairs in any spelling the registry knows, such as BTC or
+    // XBT/USD, instead of Kraken's names only
+    pub fn with_registry(mut self, registry: registry::AssetRegistry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    pub fn registry(&self) -> Option<&registry::AssetRegistry> {
+        self.registry.as_ref()
+    }
+
+    // Replaces the registry, such as with a refreshed one
+    pub fn set_registry(&mut self, registry: registry::AssetRegistry) {
+        self.registry = Some(registry);
+    }
+
+    pub async fn assets(&self, params: &[(&str, &str)]) -> Result<HashMap<String, Asset>, Errors> {
+        let params = self.resolve_params(params);
+        let request = self.client.get(&self.urls.assets).query(&params);
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::Assets(response) => Ok(response),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    pub async fn asset_pairs(&self, params: &[(&str, &str)]) -> Result<AssetPairs, Errors> {
+        let params = self.resolve_params(params);
+        let request = self.client.get(&self.urls.asset_pairs).query(&params);
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::AssetPairs(response) => Ok(response),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    pub async fn ticker(&self, params: &[(&str, &str)]) -> Result<HashMap<String, Ticker>, Errors> {
+        let params = self.resolve_params(params);
+        let request = self.client.get(&self.urls.ticker).query(&params);
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::Ticker(response) => Ok(response),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    pub async fn order_book(&self, params: &[(&str, &str)]) -> Result<HashMap<String, OrderBook>, Errors> {
+        let params = self.resolve_params(params);
+        let request = self.client.get(&self.urls.order_book).query(&params);
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::OrderBook(response) => Ok(response),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    pub async fn ohlc(&self, params: &[(&str, &str)]) -> Result<Ohlc, Errors> {
+        let params = self.resolve_params(params);
+        let request = self.client.get(&self.urls.ohlc).query(&params);
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::Ohlc(response) => Ok(response),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    pub async fn recent_trades(&self, params: &[(&str, &str)]) -> Result<RecentTrades, Errors> {
+        let params = self.resolve_params(params);
+        let request = self.client.get(&self.urls.recent_trades).query(&params);
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::RecentTrades(response) => Ok(response),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // Staked balances are returned under the staking asset name, such as DOT.S. BalanceAsset can be used to tell
+    // them apart from the spot balances
+    pub async fn account_balance(&self, params: &[(&str, &str)]) -> Result<HashMap<String, f64>, Errors> {
+        let request = self.private_request(&self.urls.account_balance, params)?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::Balance(response) => Ok(response),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    pub async fn trade_balance(&self, params: &[(&str, &str)]) -> Result<TradeBalance, Errors> {
+        let request = self.private_request(&self.urls.trade_balance, params)?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::TradeBalance(response) => Ok(response),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    pub async fn open_orders(&self, params: &[(&str, &str)]) -> Result<HashMap<String, Order>, Errors> {
+        let request = self.private_request(&self.urls.open_orders, params)?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::OpenOrders { open } => Ok(open),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    pub async fn closed_orders(&self, params: &[(&str, &str)]) -> Result<ClosedOrdersPage, Errors> {
+        let request = self.private_request(&self.urls.closed_orders, params)?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::ClosedOrders(response) => Ok(response),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // Walks the closed orders by moving ofs forward until all the orders matching params were yielded.
+    // Each page is yielded newest first
+    pub fn closed_orders_stream<'a>(
+        &'a self,
+        params: &'a [(&'a str, &'a str)],
+    ) -> impl Stream<Item = Result<(String, Order), Errors>> + 'a {
+        paginate(move |ofs| async move {
+            let ofs = ofs.to_string();
+            let page = self.closed_orders(&with_offset(params, &ofs)).await?;
+            let mut closed: Vec<(String, Order)> = page.closed.into_iter().collect();
+            closed.sort_by_key(|(_, order)| Reverse(order.opentm));
+
+            Ok((closed, page.count))
+        })
+    }
+
+    pub async fn trades_history(&self, params: &[(&str, &str)]) -> Result<TradesHistoryPage, Errors> {
+        let request = self.private_request(&self.urls.trades_history, params)?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::TradesHistory(response) => Ok(response),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // Same as closed_orders_stream, for the trades history
+    pub fn trades_history_stream<'a>(
+        &'a self,
+        params: &'a [(&'a str, &'a str)],
+    ) -> impl Stream<Item = Result<(String, Trade), Errors>> + 'a {
+        paginate(move |ofs| async move {
+            let ofs = ofs.to_string();
+            let page = self.trades_history(&with_offset(params, &ofs)).await?;
+            let mut trades: Vec<(String, Trade)> = page.trades.into_iter().collect();
+            trades.sort_by_key(|(_, trade)| Reverse(trade.time));
+
+            Ok((trades, page.count))
+        })
+    }
+
+    pub async fn ledgers(&self, params: &[(&str, &str)]) -> Result<LedgersPage, Errors> {
+        let request = self.private_request(&self.urls.ledgers, params)?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::Ledgers(response) => Ok(response),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // Same as closed_orders_stream, for the ledger entries
+    pub fn ledgers_stream<'a>(
+        &'a self,
+        params: &'a [(&'a str, &'a str)],
+    ) -> impl Stream<Item = Result<(String, LedgerEntry), Errors>> + 'a {
+        paginate(move |ofs| async move {
+            let ofs = ofs.to_string();
+            let page = self.ledgers(&with_offset(params, &ofs)).await?;
+            let mut ledger: Vec<(String, LedgerEntry)> = page.ledger.into_iter().collect();
+            ledger.sort_by_key(|(_, entry)| Reverse(entry.time));
+
+            Ok((ledger, page.count))
+        })
+    }
+
+    pub async fn deposit_methods(&self, asset: &str) -> Result<Vec<DepositMethod>, Errors> {
+        let request = self.private_request(&self.urls.deposit_methods, &[("asset", asset)])?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::DepositMethods(response) => Ok(response),
+            Responses::EmptyList(_) => Ok(Vec::new()),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // When new is true a new address is generated, otherwise the existing ones are returned
+    pub async fn deposit_addresses(&self, asset: &str, method: &str, new: bool) -> Result<Vec<DepositAddress>, Errors> {
+        let new = new.to_string();
+        let params = [("asset", asset), ("method", method), ("new", new.as_str())];
+        let request = self.private_request(&self.urls.deposit_addresses, &params)?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::DepositAddresses(response) => Ok(response),
+            Responses::EmptyList(_) => Ok(Vec::new()),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // Status of the recent deposits
+    pub async fn deposit_status(&self, asset: &str, method: &str) -> Result<Vec<FundingTransaction>, Errors> {
+        let params = [("asset", asset), ("method", method)];
+        let request = self.private_request(&self.urls.deposit_status, &params)?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::FundingTransactions(response) => Ok(response),
+            Responses::EmptyList(_) => Ok(Vec::new()),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // Fetches the fee and the limit of a withdrawal. The returned plan has to be passed to withdraw
+    pub async fn withdraw_info(
+        &self,
+        asset: &str,
+        key: &WithdrawalKey,
+        amount: WithdrawalAmount,
+    ) -> Result<WithdrawalPlan, Errors> {
+        let value = amount.value().to_string();
+        let params = [("asset", asset), ("key", key.as_str()), ("amount", value.as_str())];
+        let request = self.private_request(&self.urls.withdraw_info, &params)?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::WithdrawInfo(info) => Ok(WithdrawalPlan {
+                asset: asset.to_string(),
+                key: key.clone(),
+                amount,
+                info,
+            }),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    pub async fn withdraw(&self, plan: WithdrawalPlan) -> Result<RefId, Errors> {
+        if plan.exceeds_limit() {
+            return Err(Errors::InvalidParams(format!(
+                "Withdrawal of {} {} exceeds the limit of {}",
+                plan.net_amount(),
+                plan.asset(),
+                plan.limit()
+            )));
+        }
+
+        let value = plan.amount().value().to_string();
+        let params = [
+            ("asset", plan.asset()),
+            ("key", plan.key().as_str()),
+            ("amount", value.as_str()),
+        ];
+        let request = self.private_request(&self.urls.withdraw, &params)?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::RefId { refid } => Ok(refid),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // Status of the recent withdrawals, of all the methods if method is None
+    pub async fn withdraw_status(&self, asset: &str, method: Option<&str>) -> Result<Vec<FundingTransaction>, Errors> {
+        let mut params = vec![("asset", asset)];
+        if let Some(method) = method {
+            params.push(("method", method));
+        }
+
+        let request = self.private_request(&self.urls.withdraw_status, &params)?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::FundingTransactions(response) => Ok(response),
+            Responses::EmptyList(_) => Ok(Vec::new()),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // Requests the cancelation of a withdrawal. Returns whether the cancelation was successful
+    pub async fn withdraw_cancel(&self, asset: &str, refid: &RefId) -> Result<bool, Errors> {
+        let params = [("asset", asset), ("refid", refid.as_str())];
+        let request = self.private_request(&self.urls.withdraw_cancel, &params)?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::Bool(canceled) => Ok(canceled),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // Moves funds between the spot and the futures wallets
+    pub async fn wallet_transfer(&self, asset: &str, from: Wallet, to: Wallet, amount: f64) -> Result<RefId, Errors> {
+        if from == to {
+            return Err(Errors::InvalidParams(format!(
+                "Can't transfer from {} to itself",
+                from.as_str()
+            )));
+        }
+        let amount = validate_amount(amount)?.to_string();
+        let params = [
+            ("asset", asset),
+            ("from", from.as_str()),
+            ("to", to.as_str()),
+            ("amount", amount.as_str()),
+        ];
+        let request = self.private_request(&self.urls.wallet_transfer, &params)?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::RefId { refid } => Ok(refid),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // Creates a subaccount of the master account. Returns whether the subaccount was created
+    pub async fn create_subaccount(&self, username: &str, email: &str) -> Result<bool, Errors> {
+        let params = [("username", username), ("email", email)];
+        let request = self.private_request(&self.urls.create_subaccount, &params)?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::Bool(created) => Ok(created),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // Moves funds between the master account and its subaccounts. from and to are the IIBANs of the accounts
+    pub async fn account_transfer(
+        &self,
+        asset: &str,
+        amount: f64,
+        from: &str,
+        to: &str,
+    ) -> Result<AccountTransfer, Errors> {
+        let amount = validate_amount(amount)?.to_string();
+        let params = [
+            ("asset", asset),
+            ("amount", amount.as_str()),
+            ("from", from),
+            ("to", to),
+        ];
+        let request = self.private_request(&self.urls.account_transfer, &params)?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::AccountTransfer(response) => Ok(response),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // Requests a trades or ledgers report. The report is generated asynchronously, its progress can be
+    // followed with export_status
+    pub async fn add_export(&self, export: &ExportRequest) -> Result<ExportId, Errors> {
+        let fields = if export.fields.is_empty() {
+            "all".to_string()
+        } else {
+            export.fields.join(",")
+        };
+        let starttm = export.starttm.map(|time| time.timestamp().to_string());
+        let endtm = export.endtm.map(|time| time.timestamp().to_string());
+
+        let mut params = vec![
+            ("report", export.report.as_str()),
+            ("format", export.format.as_str()),
+            ("description", export.description.as_str()),
+            ("fields", fields.as_str()),
+        ];
+        if let Some(starttm) = &starttm {
+            params.push(("starttm", starttm));
+        }
+        if let Some(endtm) = &endtm {
+            params.push(("endtm", endtm));
+        }
+
+        let request = self.private_request(&self.urls.add_export, &params)?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::ExportId { id } => Ok(id),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    pub async fn export_status(&self, report: ReportKind) -> Result<Vec<ExportReport>, Errors> {
+        let request = self.private_request(&self.urls.export_status, &[("report", report.as_str())])?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::ExportStatus(response) => Ok(response),
+            Responses::EmptyList(_) => Ok(Vec::new()),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // Downloads the zip archive of a processed report into writer, one chunk at a time
+    pub async fn retrieve_export<W>(&self, id: &ExportId, writer: &mut W) -> Result<(), Errors>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let request = self.private_request(&self.urls.retrieve_export, &[("id", id.as_str())])?;
+        let mut response = request.send().await?;
+
+        // Errors are sent as JSON, instead of the archive
+        let is_json = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .map_or(false, |content_type| content_type.starts_with("application/json"));
+        if is_json {
+            let response = response.json::<KrakenResponse>().await?;
+            return Err(Errors::Kraken(response.error.join(" ")));
+        }
+
+        while let Some(chunk) = response.chunk().await? {
+            writer.write_all(&chunk).await?;
+        }
+        writer.flush().await?;
+
+        Ok(())
+    }
+
+    // The archive is downloaded next to path and moved there once complete, so a failed download doesn't
+    // leave a partial file behind
+    pub async fn retrieve_export_to_file(&self, id: &ExportId, path: &Path) -> Result<(), Errors> {
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".part");
+        let partial = PathBuf::from(partial);
+
+        let mut file = File::create(&partial).await?;
+        let retrieved = self.retrieve_export(id, &mut file).await;
+        drop(file);
+
+        match retrieved {
+            Ok(()) => Ok(fs::rename(&partial, path).await?),
+            Err(error) => {
+                let _ = fs::remove_file(&partial).await;
+                Err(error)
+            }
+        }
+    }
+
+    // Returns whether the report was removed
+    pub async fn remove_export(&self, id: &ExportId, kind: RemoveExportKind) -> Result<bool, Errors> {
+        let params = [("id", id.as_str()), ("type", kind.as_str())];
+        let request = self.private_request(&self.urls.remove_export, &params)?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::RemoveExport(response) => Ok(response.delete || response.cancel),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // Runs the whole export workflow: requests the report, polls its status every poll_interval until it's
+    // processed, downloads it to path and deletes it from Kraken. Gives up with Errors::Timeout if the report
+    // isn't processed within max_wait, leaving it on Kraken
+    pub async fn export_report(
+        &self,
+        export: &ExportRequest,
+        path: &Path,
+        poll_interval: Duration,
+        max_wait: Duration,
+    ) -> Result<ExportId, Errors> {
+        let id = self.add_export(export).await?;
+        let deadline = time::Instant::now() + max_wait;
+
+        loop {
+            let reports = self.export_status(export.report).await?;
+            let report = reports
+                .iter()
+                .find(|report| report.id == id)
+                .ok_or_else(|| Errors::Export(format!("Export {} not found", id)))?;
+
+            match &report.status {
+                ExportStatus::Processed => break,
+                ExportStatus::Queued | ExportStatus::Processing => {
+                    if time::Instant::now() + poll_interval > deadline {
+                        return Err(Errors::Timeout(format!(
+                            "Export {} wasn't processed within {:?}",
+                            id, max_wait
+                        )));
+                    }
+                    delay_for(poll_interval).await;
+                }
+                ExportStatus::Unknown(status) => {
+                    return Err(Errors::Export(format!(
+                        "Export {} has unexpected status {}",
+                        id, status
+                    )));
+                }
+            }
+        }
+
+        self.retrieve_export_to_file(&id, path).await?;
+        self.remove_export(&id, RemoveExportKind::Delete).await?;
+
+        Ok(id)
+    }
+
+    pub async fn stakeable_assets(&self) -> Result<Vec<StakeableAsset>, Errors> {
+        let request = self.private_request(&self.urls.stakeable_assets, &[])?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::StakeableAssets(response) => Ok(response),
+            Responses::EmptyList(_) => Ok(Vec::new()),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // method is the staking method of the asset, as returned by stakeable_assets
+    pub async fn stake(&self, asset: &str, amount: f64, method: &str) -> Result<RefId, Errors> {
+        let amount = validate_amount(amount)?.to_string();
+        let params = [("asset", asset), ("amount", amount.as_str()), ("method", method)];
+        let request = self.private_request(&self.urls.stake, &params)?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::RefId { refid } => Ok(refid),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // asset is the staking asset, such as DOT.S
+    pub async fn unstake(&self, asset: &str, amount: f64) -> Result<RefId, Errors> {
+        let amount = validate_amount(amount)?.to_string();
+        let params = [("asset", asset), ("amount", amount.as_str())];
+        let request = self.private_request(&self.urls.unstake, &params)?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::RefId { refid } => Ok(refid),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // Staking transactions that are still in progress
+    pub async fn staking_pending(&self) -> Result<Vec<StakingTransaction>, Errors> {
+        let request = self.private_request(&self.urls.staking_pending, &[])?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::StakingTransactions(response) => Ok(response),
+            Responses::EmptyList(_) => Ok(Vec::new()),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    pub async fn staking_transactions(&self) -> Result<Vec<StakingTransaction>, Errors> {
+        let request = self.private_request(&self.urls.staking_transactions, &[])?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::StakingTransactions(response) => Ok(response),
+            Responses::EmptyList(_) => Ok(Vec::new()),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // Token for the private WebSocket feeds. Once a connection is established with it, the token doesn't expire
+    pub async fn websockets_token(&self) -> Result<WebSocketsToken, Errors> {
+        let request = self.private_request(&self.urls.websockets_token, &[])?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::WebSocketsToken(response) => Ok(response),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // 30 day volume of the account, with the fees of the pairs when given
+    pub async fn trade_volume(&self, pairs: &[&str]) -> Result<TradeVolume, Errors> {
+        let pairs = pairs.join(",");
+        let mut params = vec![];
+        if !pairs.is_empty() {
+            params.push(("pair", pairs.as_str()));
+            params.push(("fee-info", "true"));
+        }
+        let request = self.private_request(&self.urls.trade_volume, &params)?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::TradeVolume(response) => Ok(response),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    pub async fn add_order(&self, order: &NewOrder) -> Result<AddedOrder, Errors> {
+        let params = order.params()?;
+        let params: Vec<(&str, &str)> = params.iter().map(|(key, value)| (*key, value.as_str())).collect();
+        let request = self.private_request(&self.urls.add_order, &params)?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::AddOrder(response) => Ok(response.into()),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // Returns the amount of canceled orders
+    pub async fn cancel_order(&self, txid: &str) -> Result<u64, Errors> {
+        let request = self.private_request(&self.urls.cancel_order, &[("txid", txid)])?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
+            return Err(Errors::Kraken(error));
+        }
+
+        match response.result.unwrap() {
+            Responses::Canceled { count } => Ok(count),
+            _ => Err(Errors::InvalidFormat),
+        }
+    }
+
+    // Cancels all the open orders. Returns the amount of canceled orders
+    pub async fn cancel_all(&self) -> Result<u64, Errors> {
+        let request = self.private_request(&self.urls.cancel_all, &[])?;
+        let response = request.send().await?.json::<KrakenResponse>().await?;
+
+        if !response.error.is_empty() {
+            let error = response.error.join(" ");
             return Err(Errors::Kraken(error));
         }
 
         match response.result.unwrap() {
-            Responses::ClosedOrders { closed } => Ok(closed),
+            Responses::Canceled { count } => Ok(count),
             _ => Err(Errors::InvalidFormat),
         }
     }
 
+    // Names in the asset and pair params are replaced by Kraken's names through the registry. Names it doesn't
+    // know, such as staking assets, are sent as they are
+    fn resolve_params<'a>(&self, params: &[(&'a str, &str)]) -> Vec<(&'a str, String)> {
+        let registry = match &self.registry {
+            Some(registry) => registry,
+            None => return params.iter().map(|(key, value)| (*key, value.to_string())).collect(),
+        };
+
+        params
+            .iter()
+            .map(|(key, value)| {
+                let resolve = |name: &str| {
+                    let resolved = match *key {
+                        "asset" => registry.resolve_asset(name),
+                        _ => registry.resolve_pair(name),
+                    };
+                    resolved.unwrap_or(name).to_string()
+                };
+                let value = match *key {
+                    "asset" | "pair" => value.split(',').map(resolve).collect::<Vec<String>>().join(","),
+                    _ => value.to_string(),
+                };
+                (*key, value)
+            })
+            .collect()
+    }
+
     // TODO replace url type with IntoUrl
     fn private_request(&self, url: &str, params: &[(&str, &str)]) -> Result<RequestBuilder, Errors> {
         let nonce = time::SystemTime::now()
@@ -560,23 +3257,80 @@ impl Kraken {
         query_params.insert("nonce", nonce.as_str());
 
         // This overwrites the nonce above if it was passed in params
-        for (key, value) in params {
-            query_params.insert(key, *value);
+        let params = self.resolve_params(params);
+        for (key, value) in &params {
+            query_params.insert(key, value.as_str());
         }
 
-        let signature = create_signature(url, query_params, &self.credentials.secret)?;
+        let signature = create_signature(url, &query_params, &self.credentials.secret)?;
 
         let mut headers = HeaderMap::new();
         headers.insert("API-Key", HeaderValue::from_str(&self.credentials.api_key).unwrap());
         headers.insert("API-Sign", HeaderValue::from_str(&signature).unwrap());
-        let post_data = PrivatePostData { nonce, otp: None };
 
-        Ok(self.client.post(url).headers(headers).form(&post_data))
+        // The POST data has to be encoded in the same order as it was signed, which holds as long as
+        // query_params isn't modified in between
+        Ok(self.client.post(url).headers(headers).form(&query_params))
     }
 }
 
+// Replaces the ofs param, if it was passed, with the given offset
+fn with_offset<'a>(params: &[(&'a str, &'a str)], ofs: &'a str) -> Vec<(&'a str, &'a str)> {
+    let mut params: Vec<(&str, &str)> = params.iter().filter(|(key, _)| *key != "ofs").cloned().collect();
+    params.push(("ofs", ofs));
+
+    params
+}
+
+struct PaginationState<F, T> {
+    fetch: F,
+    ofs: u64,
+    items: VecDeque<(String, T)>,
+    done: bool,
+}
+
+// Turns an endpoint paginated with ofs into a stream of its items. fetch is called with the current offset
+// and returns the page items together with the total amount of items matching the criteria
+fn paginate<'a, F, Fut, T>(fetch: F) -> impl Stream<Item = Result<(String, T), Errors>> + 'a
+where
+    F: Fn(u64) -> Fut + 'a,
+    Fut: Future<Output = Result<(Vec<(String, T)>, u64), Errors>> + 'a,
+    T: 'a,
+{
+    let state = PaginationState {
+        fetch,
+        ofs: 0,
+        items: VecDeque::new(),
+        done: false,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(item) = state.items.pop_front() {
+                return Some((Ok(item), state));
+            }
+
+            if state.done {
+                return None;
+            }
+
+            match (state.fetch)(state.ofs).await {
+                Ok((items, count)) => {
+                    state.ofs += items.len() as u64;
+                    state.done = items.is_empty() || state.ofs >= count;
+                    state.items.extend(items);
+                }
+                Err(error) => {
+                    state.done = true;
+                    return Some((Err(error), state));
+                }
+            }
+        }
+    })
+}
+
 // Message signature using HMAC-SHA512 of (URI path + SHA256(nonce + POST data)) and base64 decoded secret API key
-fn create_signature(url: &str, params: HashMap<&str, &str>, secret: &str) -> Result<String, Errors> {
+fn create_signature(url: &str, params: &HashMap<&str, &str>, secret: &str) -> Result<String, Errors> {
     // We know for sure "nonce" exists because we pass it in each send_private method
     let nonce = params.get("nonce").unwrap();
     let secret64 = base64::decode(secret)?;
@@ -586,9 +3340,7 @@ fn create_signature(url: &str, params: HashMap<&str, &str>, secret: &str) -> Res
 
     let mut encoded_params = form_urlencoded::Serializer::new(String::new());
     for (key, value) in params.iter() {
-        if *key == "nonce" || *key == "otp" {
-            encoded_params.append_pair(key, value);
-        }
+        encoded_params.append_pair(key, value);
     }
     let encoded_params = encoded_params.finish();
 
@@ -607,18 +3359,101 @@ fn create_signature(url: &str, params: HashMap<&str, &str>, secret: &str) -> Res
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_order_book_fills() {
+        let order_book: OrderBook = serde_json::from_str(
+            r#"{
+                "asks": [["101.0", "1.0", 1603733933], ["102.0", "2.0", 1603733934], ["104.0", "3.0", 1603733935]],
+                "bids": [["99.0", "1.0", 1603733933], ["98.0", "4.0", 1603733934]]
+            }"#,
+        )
+        .unwrap();
+
+        let fill = order_book.estimate_fill(&Side::Buy, BookAmount::Base(2.0)).unwrap();
+        assert_eq!(fill.volume, 2.0);
+        assert_eq!(fill.cost, 203.0);
+        assert_eq!(fill.vwap, 101.5);
+        assert_eq!(fill.worst_price, 102.0);
+        assert_eq!(fill.levels, 2);
+        assert_eq!(fill.impact_bps, 150.0);
+        assert_eq!(fill.remaining, 0.0);
+
+        let fill = order_book.estimate_fill(&Side::Buy, BookAmount::Quote(305.0)).unwrap();
+        assert_eq!(fill.volume, 3.0);
+        assert_eq!(fill.cost, 305.0);
+        assert_eq!(fill.remaining, 0.0);
+
+        let fill = order_book.estimate_fill(&Side::Sell, BookAmount::Base(6.0)).unwrap();
+        assert_eq!(fill.volume, 5.0);
+        assert_eq!(fill.vwap, 98.2);
+        assert_eq!(fill.worst_price, 98.0);
+        assert!((fill.impact_bps - 180.0).abs() < 1e-9);
+        assert_eq!(fill.remaining, 1.0);
+
+        assert_eq!(order_book.vwap(&Side::Buy, BookAmount::Base(0.5)), Some(101.0));
+        assert_eq!(
+            order_book.price_impact_bps(&Side::Sell, BookAmount::Base(1.0)),
+            Some(100.0)
+        );
+        assert_eq!(order_book.estimate_fill(&Side::Buy, BookAmount::Base(0.0)), None);
+
+        let liquidity = order_book.liquidity_within(2.0).unwrap();
+        assert_eq!(liquidity.ask_volume, 3.0);
+        assert_eq!(liquidity.ask_value, 305.0);
+        assert_eq!(liquidity.bid_volume, 5.0);
+        assert_eq!(liquidity.bid_value, 491.0);
+
+        let report = order_book
+            .depth_report(&Side::Buy, BookAmount::Base(2.0), 200.0)
+            .unwrap();
+        assert!(report.sufficient);
+        let report = order_book
+            .depth_report(&Side::Buy, BookAmount::Base(2.0), 100.0)
+            .unwrap();
+        assert!(!report.sufficient);
+        let report = order_book
+            .depth_report(&Side::Sell, BookAmount::Base(6.0), 1000.0)
+            .unwrap();
+        assert!(!report.sufficient);
+
+        let empty = OrderBook {
+            asks: vec![],
+            bids: vec![],
+        };
+        assert_eq!(empty.estimate_fill(&Side::Buy, BookAmount::Base(1.0)), None);
+        assert_eq!(empty.liquidity_within(1.0), None);
+    }
+
+    #[test]
+    fn test_order_book_fill_on_level_boundary() {
+        let order_book: OrderBook = serde_json::from_str(
+            r#"{
+                "asks": [["0.3", "3.0", 1603733933], ["0.4", "1.0", 1603733934]],
+                "bids": [["0.2", "1.0", 1603733933]]
+            }"#,
+        )
+        .unwrap();
+
+        // The quote amount fills the first level exactly, the float leftover doesn't reach the next one
+        let fill = order_book.estimate_fill(&Side::Buy, BookAmount::Quote(0.9)).unwrap();
+        assert_eq!(fill.volume, 3.0);
+        assert_eq!(fill.worst_price, 0.3);
+        assert_eq!(fill.levels, 1);
+        assert_eq!(fill.remaining, 0.0);
+    }
+
     #[test]
     fn test_create_signature() {
         let url = "https://api.kraken.com/0/private/Balance";
         let secret = "NZTRqjFqtb7Jbg5Yx7iRelcfCxiB7pL1FvvK3tokScThZDl0z7oi/m5aHhtKcUp2dIpT8qIbaMfp01Glzw24Ag==";
-        let timestamps = vec![
+        let timestamps = [
             "1603733933254000",
             "1603733979214000",
             "1603733998096000",
             "1603734014787000",
             "1603734032479000",
         ];
-        let hashes = vec![
+        let hashes = [
             "EAIoQ9XIntOdpiFHnt0UTmwqYZAmeeYFR/KwrhRqR1O6dLLNsT8I0R2GJ2p7M9OICQAol6kL9RF49l/aJaOKKw==",
             "vGNimOwAZNm31kjoB1CVh+vUbzj9PA68EYC/J/3zIo3129NwCzImNw6JmVwzALHjDwNR5w8VppatbLGfN5j7ow==",
             "isPAXtmUpxtSRWovhTc3G9qL1ZCeIi+LoyoezQBJbD/gjz4dZHhvvhv4oFtl5wPv7JhomU0TX6qpLborI612hw==",
@@ -629,9 +3464,530 @@ mod tests {
         for (i, nonce) in timestamps.iter().enumerate() {
             let params: HashMap<&str, &str> = vec![("nonce", *nonce)].into_iter().collect();
             let expected = hashes.get(i).unwrap();
-            let signature = create_signature(url, params, secret);
-            assert_eq!(signature.is_ok(), true);
+            let signature = create_signature(url, &params, secret);
+            assert!(signature.is_ok());
             assert_eq!(*expected, signature.unwrap().as_str());
         }
     }
+
+    #[test]
+    fn test_order_book_helpers() {
+        let order_book: OrderBook = serde_json::from_str(
+            r#"{
+                "asks": [["101.0", "1.5", 1603733933], ["102.0", "2.0", 1603733934], ["104.0", "3.0", 1603733935]],
+                "bids": [["99.0", "1.0", 1603733933], ["98.0", "4.0", 1603733934]]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(order_book.best_ask().unwrap().price, 101.0);
+        assert_eq!(order_book.best_ask().unwrap().timestamp, 1603733933);
+        assert_eq!(order_book.best_bid().unwrap().volume, 1.0);
+        assert_eq!(order_book.spread(), Some(2.0));
+        assert_eq!(order_book.mid_price(), Some(100.0));
+        assert_eq!(order_book.ask_depth_at(102.0), 3.5);
+        assert_eq!(order_book.ask_depth_at(100.0), 0.0);
+        assert_eq!(order_book.bid_depth_at(98.0), 5.0);
+
+        let empty = OrderBook {
+            asks: vec![],
+            bids: vec![],
+        };
+        assert_eq!(empty.spread(), None);
+        assert_eq!(empty.mid_price(), None);
+    }
+
+    #[test]
+    fn test_order_enums() {
+        let order: Order = serde_json::from_str(
+            r#"{
+                "refid": null,
+                "userref": 0,
+                "status": "open",
+                "opentm": 1603733933.2541,
+                "starttm": 0,
+                "expiretm": 0,
+                "descr": {
+                    "pair": "XBTUSD",
+                    "type": "buy",
+                    "ordertype": "stop-loss-limit",
+                    "price": "9000.0",
+                    "price2": "9100.0",
+                    "leverage": "none",
+                    "order": "buy 1.25000000 XBTUSD @ stop loss 9000.0 -> limit 9100.0",
+                    "close": ""
+                },
+                "vol": "1.25000000",
+                "vol_exec": "0.00000000",
+                "cost": "0.00000",
+                "fee": "0.00000",
+                "price": "0.00000",
+                "stopprice": "0.00000",
+                "limitprice": "0.00000",
+                "misc": "stopped,rebalanced",
+                "oflags": "fciq,post"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(order.status, OrderStatus::Open);
+        assert_eq!(order.descr.kind, Side::Buy);
+        assert_eq!(order.descr.ordertype, OrderType::StopLossLimit);
+        assert!(order.oflags.contains(&OrderFlag::Fciq));
+        assert!(order.oflags.contains(&OrderFlag::Post));
+        assert!(!order.oflags.contains(&OrderFlag::Viqc));
+        assert_eq!(order.oflags.to_string(), "fciq,post");
+        assert!(order.misc.contains(&OrderMiscInfo::Stopped));
+        assert!(order.misc.contains(&OrderMiscInfo::Unknown("rebalanced".to_string())));
+
+        assert_eq!(OrderType::from("iceberg"), OrderType::Unknown("iceberg".to_string()));
+        assert!(OrderFlags::from("").is_empty());
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let ohlc: Ohlc = serde_json::from_str(
+            r#"{
+                "XXBTZUSD": [[1603733880, "13040.0", "13050.5", "13035.1", "13045.0", "13042.3", "1.25000000", 12]],
+                "last": 1603733880
+            }"#,
+        )
+        .unwrap();
+        let candle = &ohlc.pairs.get("XXBTZUSD").unwrap()[0];
+        assert_eq!(ohlc.last, 1603733880);
+        assert_eq!(candle.time.to_rfc3339(), "2020-10-26T17:38:00+00:00");
+        assert_eq!(candle.count, 12);
+
+        let trades: RecentTrades = serde_json::from_str(
+            r#"{
+                "XXBTZUSD": [
+                    ["13040.00000", "0.01000000", 1603733933.2541, "b", "l", ""],
+                    ["13041.00000", "0.50000000", 1603733934.5, "s", "m", "", 42]
+                ],
+                "last": "1603733934500000000"
+            }"#,
+        )
+        .unwrap();
+        let pair_trades = trades.pairs.get("XXBTZUSD").unwrap();
+        assert_eq!(pair_trades[0].side, Side::Buy);
+        assert_eq!(pair_trades[0].ordertype, OrderType::Limit);
+        assert_eq!(pair_trades[0].time.timestamp_millis(), 1603733933254);
+        assert_eq!(pair_trades[1].trade_id, Some(42));
+
+        assert_eq!(timestamp_to_datetime(0.0).unwrap().timestamp(), 0);
+        let value = serde_json::json!(0);
+        assert_eq!(from_timestamp_option(value).unwrap(), None);
+        let value = serde_json::json!(1603733933.5);
+        assert_eq!(from_timestamp_option(value).unwrap().unwrap().timestamp(), 1603733933);
+    }
+
+    #[test]
+    fn test_order_description() {
+        let price = |value, kind, percent| Some(DescriptionPrice { value, kind, percent });
+        let absolute = |value| price(value, PriceKind::Absolute, false);
+
+        let description = OrderDescription::from_str("buy 1.25 XBTUSD @ limit 9000.0 with 2:1 leverage").unwrap();
+        assert_eq!(description.side, Some(Side::Buy));
+        assert_eq!(description.volume, Some(1.25));
+        assert_eq!(description.pair.as_deref(), Some("XBTUSD"));
+        assert_eq!(description.ordertype, OrderType::Limit);
+        assert_eq!(description.price, absolute(9000.0));
+        assert_eq!(description.price2, None);
+        assert_eq!(description.leverage, Some(2));
+
+        let description = OrderDescription::from_str("sell 0.50000000 XBTUSD @ market").unwrap();
+        assert_eq!(description.side, Some(Side::Sell));
+        assert_eq!(description.ordertype, OrderType::Market);
+        assert_eq!(description.price, None);
+
+        let description = OrderDescription::from_str("sell 0.5 XBTUSD @ stop loss 8000.0 -> limit 7900.0").unwrap();
+        assert_eq!(description.ordertype, OrderType::StopLossLimit);
+        assert_eq!(description.price, absolute(8000.0));
+        assert_eq!(description.price2, absolute(7900.0));
+
+        let description = OrderDescription::from_str("sell 0.5 XBTUSD @ trailing stop +50.0").unwrap();
+        assert_eq!(description.ordertype, OrderType::TrailingStop);
+        assert_eq!(description.price, price(50.0, PriceKind::Relative, false));
+
+        let description = OrderDescription::from_str("buy 0.5 XBTUSD @ limit #5.5%").unwrap();
+        assert_eq!(description.price, price(5.5, PriceKind::Directional, true));
+        for (text, value, kind, percent) in &[
+            ("5", 5.0, PriceKind::Absolute, false),
+            ("+5", 5.0, PriceKind::Relative, false),
+            ("-5", -5.0, PriceKind::Relative, false),
+            ("#5", 5.0, PriceKind::Directional, false),
+            ("5%", 5.0, PriceKind::Relative, true),
+            ("+5%", 5.0, PriceKind::Relative, true),
+        ] {
+            let expected = price(*value, *kind, *percent);
+            assert_eq!(parse_description_price(text), expected, "{}", text);
+        }
+        assert!(parse_description_price("limit").is_none());
+
+        let description =
+            OrderDescription::from_str("close position @ stop loss 8000.0 -> take profit 9500.0").unwrap();
+        assert!(description.is_close_position());
+        assert_eq!(description.ordertype, OrderType::StopLossProfit);
+        assert_eq!(description.price2, absolute(9500.0));
+
+        assert!(OrderDescription::from_str("buy 1.25 XBTUSD").is_err());
+        assert!(OrderDescription::from_str("buy many XBTUSD @ market").is_err());
+        assert!(OrderDescription::from_str("buy 1.25 XBTUSD @ limit 9000.0 with lots of leverage").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_paginate() {
+        use futures::StreamExt;
+
+        let items: Vec<u64> = (0..7).collect();
+        let fetch = |ofs: u64| {
+            let page: Vec<(String, u64)> = items
+                .iter()
+                .skip(ofs as usize)
+                .take(3)
+                .map(|item| (item.to_string(), *item))
+                .collect();
+            async move { Ok((page, 7)) }
+        };
+        let streamed: Vec<u64> = paginate(fetch).map(|item| item.unwrap().1).collect().await;
+        assert_eq!(streamed, items);
+
+        let fetch = |_| async { Err::<(Vec<(String, u64)>, u64), Errors>(Errors::InvalidFormat) };
+        let streamed: Vec<Result<(String, u64), Errors>> = paginate(fetch).collect().await;
+        assert_eq!(streamed.len(), 1);
+        assert!(streamed[0].is_err());
+    }
+
+    #[test]
+    fn test_funding_responses() {
+        let response: KrakenResponse = serde_json::from_str(
+            r#"{
+                "error": [],
+                "result": [
+                    {"method": "Ripple XRP", "limit": false, "fee": "0.00000000", "gen-address": true, "minimum": "1.0"},
+                    {"method": "Bank", "limit": "10000.00", "address-setup-fee": "5.0"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        match response.result.unwrap() {
+            Responses::DepositMethods(methods) => {
+                assert_eq!(methods[0].limit, None);
+                assert_eq!(methods[0].minimum, Some(1.0));
+                assert!(methods[0].gen_address);
+                assert_eq!(methods[1].limit, Some(10000.0));
+                assert_eq!(methods[1].address_setup_fee, Some(5.0));
+            }
+            response => panic!("Unexpected response {:?}", response),
+        }
+
+        let response: KrakenResponse = serde_json::from_str(
+            r#"{
+                "error": [],
+                "result": [{"address": "rLHzPsX6oXkzU2qL12kHCH8G8cnZv1rBJh", "expiretm": "0", "new": true, "tag": "1361101127"}]
+            }"#,
+        )
+        .unwrap();
+        match response.result.unwrap() {
+            Responses::DepositAddresses(addresses) => {
+                assert_eq!(addresses[0].expiretm, None);
+                assert_eq!(addresses[0].tag.as_deref(), Some("1361101127"));
+                assert_eq!(addresses[0].memo, None);
+            }
+            response => panic!("Unexpected response {:?}", response),
+        }
+
+        let response: KrakenResponse = serde_json::from_str(
+            r#"{
+                "error": [],
+                "result": [{
+                    "method": "Bitcoin", "aclass": "currency", "asset": "XXBT", "refid": "QSkyJHs-1b8T2f3K2JKsgNGJMFkRFv",
+                    "txid": "e1d6f9c5", "info": "bc1qxzx6", "amount": "0.78125000", "fee": "0.0000000000",
+                    "time": 1688992722, "status": "Success", "status-prop": "return"
+                }]
+            }"#,
+        )
+        .unwrap();
+        match response.result.unwrap() {
+            Responses::FundingTransactions(deposits) => {
+                assert_eq!(deposits[0].status, FundingStatus::Success);
+                assert_eq!(deposits[0].status_prop, Some(FundingStatusProp::Return));
+                assert_eq!(deposits[0].amount, 0.78125);
+            }
+            response => panic!("Unexpected response {:?}", response),
+        }
+
+        let response: KrakenResponse = serde_json::from_str(r#"{"error": [], "result": []}"#).unwrap();
+        assert!(matches!(response.result.unwrap(), Responses::EmptyList(_)));
+    }
+
+    #[test]
+    fn test_withdrawal_responses() {
+        assert!(WithdrawalAmount::new(0.0).is_err());
+        assert!(WithdrawalAmount::new(-1.0).is_err());
+        assert!(WithdrawalAmount::new(f64::NAN).is_err());
+        assert_eq!(WithdrawalAmount::new(0.5).unwrap().value(), 0.5);
+
+        let response: KrakenResponse = serde_json::from_str(
+            r#"{"error": [], "result": {"method": "Bitcoin", "limit": "332.00956139", "amount": "0.72485000", "fee": "0.00015000"}}"#,
+        )
+        .unwrap();
+        let info = match response.result.unwrap() {
+            Responses::WithdrawInfo(info) => info,
+            response => panic!("Unexpected response {:?}", response),
+        };
+        let plan = WithdrawalPlan {
+            asset: "XBT".to_string(),
+            key: WithdrawalKey::new("btc_wallet"),
+            amount: WithdrawalAmount::new(0.725).unwrap(),
+            info,
+        };
+        assert_eq!(plan.method(), "Bitcoin");
+        assert_eq!(plan.fee(), 0.00015);
+        assert!(!plan.exceeds_limit());
+
+        let response: KrakenResponse =
+            serde_json::from_str(r#"{"error": [], "result": {"refid": "AGBSO6T-UFMTTQ-I7KGS6"}}"#).unwrap();
+        match response.result.unwrap() {
+            Responses::RefId { refid } => assert_eq!(refid.as_str(), "AGBSO6T-UFMTTQ-I7KGS6"),
+            response => panic!("Unexpected response {:?}", response),
+        }
+
+        let response: KrakenResponse = serde_json::from_str(r#"{"error": [], "result": true}"#).unwrap();
+        assert!(matches!(response.result.unwrap(), Responses::Bool(true)));
+    }
+
+    #[test]
+    fn test_export_responses() {
+        let response: KrakenResponse = serde_json::from_str(
+            r#"{
+                "error": [],
+                "result": [{
+                    "id": "VSKC", "descr": "my_trades_1", "format": "CSV", "report": "trades", "subtype": "all",
+                    "status": "Processed", "flags": "0", "fields": "all", "createdtm": "1688669085",
+                    "expiretm": "1689878685", "starttm": "1688669093", "completedtm": "1688669093",
+                    "datastarttm": "1683556800", "dataendtm": "1688669085", "aclass": "forex", "asset": "all"
+                }]
+            }"#,
+        )
+        .unwrap();
+        match response.result.unwrap() {
+            Responses::ExportStatus(reports) => {
+                assert_eq!(reports[0].id.as_str(), "VSKC");
+                assert_eq!(reports[0].status, ExportStatus::Processed);
+                assert_eq!(reports[0].completedtm.unwrap().timestamp(), 1688669093);
+            }
+            response => panic!("Unexpected response {:?}", response),
+        }
+
+        let response: KrakenResponse = serde_json::from_str(r#"{"error": [], "result": {"id": "TCJA"}}"#).unwrap();
+        match response.result.unwrap() {
+            Responses::ExportId { id } => assert_eq!(id.as_str(), "TCJA"),
+            response => panic!("Unexpected response {:?}", response),
+        }
+
+        let response: KrakenResponse = serde_json::from_str(r#"{"error": [], "result": {"delete": true}}"#).unwrap();
+        match response.result.unwrap() {
+            Responses::RemoveExport(response) => assert!(response.delete && !response.cancel),
+            response => panic!("Unexpected response {:?}", response),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_export_download() {
+        let credentials = Credentials::new("key".to_string(), base64::encode("secret"));
+        let kraken = Kraken::new(credentials, Urls::new("http://127.0.0.1:1"));
+        let path = std::env::temp_dir().join(format!("kraken_export_{}.zip", std::process::id()));
+
+        let id = ExportId("TCJA".to_string());
+        assert!(kraken.retrieve_export_to_file(&id, &path).await.is_err());
+        assert!(!path.exists());
+        assert!(!path.with_extension("zip.part").exists());
+    }
+
+    #[test]
+    fn test_staking_responses() {
+        let response: KrakenResponse = serde_json::from_str(
+            r#"{"error": [], "result": {"ZUSD": "171288.6158", "DOT.S": "10.5", "USDC.M": "1.0"}}"#,
+        )
+        .unwrap();
+        match response.result.unwrap() {
+            Responses::Balance(balance) => {
+                assert_eq!(balance.get("ZUSD"), Some(&171288.6158));
+                assert_eq!(balance.get("DOT.S"), Some(&10.5));
+            }
+            response => panic!("Unexpected response {:?}", response),
+        }
+
+        let asset = BalanceAsset::from("DOT.S");
+        assert_eq!(asset.asset, "DOT");
+        assert_eq!(asset.kind, BalanceKind::Staked);
+        assert_eq!(BalanceAsset::from("USDC.M").kind, BalanceKind::OptIn);
+        assert_eq!(BalanceAsset::from("ZUSD").kind, BalanceKind::Spot);
+        assert!(!BalanceAsset::from("ETH2").is_staked());
+        assert!(BalanceAsset::from("DOT.S").is_staked());
+        assert!(!BalanceAsset::from("USDC.M").is_staked());
+        assert!(!BalanceAsset::from("DOT.P").is_staked());
+
+        let response: KrakenResponse = serde_json::from_str(
+            r#"{
+                "error": [],
+                "result": [{
+                    "method": "polkadot-staked", "asset": "DOT", "staking_asset": "DOT.S",
+                    "rewards": {"reward": "12.00", "type": "percentage"}, "on_chain": true, "can_stake": true,
+                    "can_unstake": true, "minimum_amount": {"staking": "0.0000000000", "unstaking": "0.0000000000"}
+                }]
+            }"#,
+        )
+        .unwrap();
+        match response.result.unwrap() {
+            Responses::StakeableAssets(assets) => {
+                assert_eq!(assets[0].staking_asset, "DOT.S");
+                assert_eq!(assets[0].rewards.reward, 12.0);
+            }
+            response => panic!("Unexpected response {:?}", response),
+        }
+
+        let response: KrakenResponse = serde_json::from_str(
+            r#"{
+                "error": [],
+                "result": [{
+                    "method": "polkadot-staked", "aclass": "currency", "asset": "DOT.S", "refid": "RUSB7W6-ESIXUX-K6PVTM",
+                    "amount": "0.3256489714", "fee": "0.0000000000", "time": 1622967367, "status": "Success",
+                    "type": "bonding", "bond_start": 1622971496, "bond_end": 1622971496
+                }]
+            }"#,
+        )
+        .unwrap();
+        match response.result.unwrap() {
+            Responses::StakingTransactions(transactions) => {
+                assert_eq!(transactions[0].kind, StakingTransactionType::Bonding);
+                assert_eq!(transactions[0].status, FundingStatus::Success);
+                assert_eq!(transactions[0].bond_end.unwrap().timestamp(), 1622971496);
+            }
+            response => panic!("Unexpected response {:?}", response),
+        }
+    }
+
+    #[test]
+    fn test_account_transfer_response() {
+        let response: KrakenResponse = serde_json::from_str(
+            r#"{"error": [], "result": {"transfer_id": "TOH3AS2-LPCWR8-JDQGEU", "status": "complete"}}"#,
+        )
+        .unwrap();
+        match response.result.unwrap() {
+            Responses::AccountTransfer(transfer) => {
+                assert_eq!(transfer.transfer_id.as_str(), "TOH3AS2-LPCWR8-JDQGEU");
+                assert_eq!(transfer.status, TransferStatus::Complete);
+            }
+            response => panic!("Unexpected response {:?}", response),
+        }
+    }
+
+    #[test]
+    fn test_resolve_params() {
+        use registry::tests::create_test_registry;
+
+        let credentials = Credentials::new("key".to_string(), base64::encode("secret"));
+        let kraken = Kraken::new(credentials, Urls::new("http://127.0.0.1:1"));
+        let params = [("pair", "btc/usd,SOLUSD"), ("asset", "BTC"), ("interval", "5")];
+        let resolved = kraken.resolve_params(&params);
+        assert_eq!(resolved[0], ("pair", "btc/usd,SOLUSD".to_string()));
+
+        let kraken = kraken.with_registry(create_test_registry());
+        let resolved = kraken.resolve_params(&params);
+        assert_eq!(
+            resolved,
+            vec![
+                ("pair", "XXBTZUSD,SOLUSD".to_string()),
+                ("asset", "XXBT".to_string()),
+                ("interval", "5".to_string()),
+            ]
+        );
+        assert_eq!(kraken.resolve_params(&[("asset", "DOT.S")])[0].1, "DOT.S");
+    }
+
+    #[tokio::test]
+    async fn test_wallet_transfer_params() {
+        // Rejected before any request is sent
+        let credentials = Credentials::new("key".to_string(), base64::encode("secret"));
+        let kraken = Kraken::new(credentials, Urls::new("http://127.0.0.1:1"));
+
+        let transfer = kraken.wallet_transfer("XBT", Wallet::Spot, Wallet::Spot, 1.0).await;
+        assert!(matches!(transfer, Err(Errors::InvalidParams(_))));
+        for amount in &[0.0, -1.0, f64::NAN] {
+            let transfer = kraken
+                .wallet_transfer("XBT", Wallet::Spot, Wallet::Futures, *amount)
+                .await;
+            assert!(matches!(transfer, Err(Errors::InvalidParams(_))));
+        }
+    }
+
+    #[test]
+    fn test_trade_volume_response() {
+        let response: KrakenResponse = serde_json::from_str(
+            r#"{"error": [], "result": {
+                "currency": "ZUSD", "volume": "200709587.4223",
+                "fees": {"XXBTZUSD": {"fee": "0.1000", "minfee": "0.1000", "maxfee": "0.2600",
+                    "nextfee": null, "nextvolume": null, "tiervolume": "10000000.0000"}},
+                "fees_maker": {"XXBTZUSD": {"fee": "0.0000", "minfee": "0.0000", "maxfee": "0.1600",
+                    "nextfee": null, "nextvolume": null, "tiervolume": "10000000.0000"}}
+            }}"#,
+        )
+        .unwrap();
+        match response.result.unwrap() {
+            Responses::TradeVolume(response) => {
+                assert_eq!(response.currency, "ZUSD");
+                assert_eq!(response.volume, 200709587.4223);
+                assert_eq!(response.fees["XXBTZUSD"].fee, 0.1);
+                assert_eq!(response.fees["XXBTZUSD"].nextfee, None);
+                assert_eq!(response.fees_maker["XXBTZUSD"].maxfee, 0.16);
+            }
+            response => panic!("Unexpected response {:?}", response),
+        }
+    }
+
+    #[test]
+    fn test_order_responses() {
+        let response: KrakenResponse = serde_json::from_str(
+            r#"{"error": [], "result": {
+                "descr": {"order": "buy 1.45000000 XBTUSD @ limit 8000.0", "close": ""},
+                "txid": ["OUF4EM-FRGI2-MQMWZD"]
+            }}"#,
+        )
+        .unwrap();
+        match response.result.unwrap() {
+            Responses::AddOrder(response) => {
+                let order = AddedOrder::from(response);
+                assert_eq!(order.description, "buy 1.45000000 XBTUSD @ limit 8000.0");
+                assert_eq!(order.close, None);
+                assert_eq!(order.txid, vec!["OUF4EM-FRGI2-MQMWZD"]);
+            }
+            response => panic!("Unexpected response {:?}", response),
+        }
+
+        let response: KrakenResponse =
+            serde_json::from_str(r#"{"error": [], "result": {"count": 1, "pending": false}}"#).unwrap();
+        assert!(matches!(response.result.unwrap(), Responses::Canceled { count: 1 }));
+
+        let mut order = NewOrder::new("XBTUSD", Side::Buy, OrderType::Limit, 1.45);
+        order.price = Some(8000.0);
+        order.oflags = OrderFlags(vec![OrderFlag::Post, OrderFlag::Fciq]);
+        order.validate = true;
+        assert_eq!(
+            order.params().unwrap(),
+            vec![
+                ("pair", "XBTUSD".to_string()),
+                ("type", "buy".to_string()),
+                ("ordertype", "limit".to_string()),
+                ("volume", "1.45".to_string()),
+                ("price", "8000".to_string()),
+                ("oflags", "post,fciq".to_string()),
+                ("validate", "true".to_string()),
+            ]
+        );
+        assert!(NewOrder::new("XBTUSD", Side::Sell, OrderType::Market, 0.0)
+            .params()
+            .is_err());
+    }
 }
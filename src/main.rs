@@ -1,5 +1,3 @@
-pub mod kraken_api;
-
 // TODO api_key and secret should be retrieved from env
 fn main() {
     println!("Hello, world!");
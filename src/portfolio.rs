@@ -0,0 +1,215 @@
+// Value of the account balances in a single currency
+use super::{
+    conversion::{Conversion, ConversionGraph, PriceSource},
+    registry::AssetRegistry,
+    BalanceAsset, BalanceKind, Errors, Kraken, Ticker,
+};
+use std::{cmp::Ordering, collections::HashMap};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Holding {
+    // Name in account_balance, such as XXBT or XBT.M
+    pub name: String,
+    // Kraken's name of the asset it's valued as, without the staking suffix
+    pub asset: String,
+    pub kind: BalanceKind,
+    pub amount: f64,
+    // Value in the quote currency, none without a conversion to it
+    pub value: Option<f64>,
+    // Value at today's opening prices
+    pub open_value: Option<f64>,
+    // Fraction of the total value
+    pub weight: Option<f64>,
+    // Conversion used for the value
+    pub conversion: Option<Conversion>,
+}
+
+impl Holding {
+    // Change of the value since today's opening prices, in the quote currency
+    pub fn change(&self) -> Option<f64> {
+        Some(self.value? - self.open_value?)
+    }
+
+    pub fn change_percent(&self) -> Option<f64> {
+        let open_value = self.open_value.filter(|open_value| *open_value != 0.0)?;
+        Some(self.change()? / open_value * 100.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Portfolio {
+    // Kraken's name of the currency of the values
+    pub quote: String,
+    // Sorted by value, highest first, the holdings without a value last. Then by name
+    pub holdings: Vec<Holding>,
+    // Sums of the holdings that have a value
+    pub total: f64,
+    pub open_total: f64,
+}
+
+impl Portfolio {
+    // Values the balances with the tickers, directly or through other pairs. The prices of the source are
+    // compared with today's opening prices for the change. Zero balances are left out
+    pub fn new(
+        registry: &AssetRegistry,
+        balances: &HashMap<String, f64>,
+        tickers: &HashMap<String, Ticker>,
+        quote: &str,
+        source: PriceSource,
+    ) -> Result<Self, Errors> {
+        let quote = registry.resolve_asset(quote)?;
+        let graph = ConversionGraph::new(registry, tickers, source);
+        let open_graph = ConversionGraph::new(registry, tickers, PriceSource::Open);
+
+        let mut holdings = vec![];
+        for (name, amount) in balances {
+            if *amount == 0.0 {
+                continue;
+            }
+            let balance_asset = BalanceAsset::from(name.as_str());
+            // Staked assets are valued as the asset itself. Some, such as ETH2.S, are listed as assets too
+            let asset = registry
+                .resolve_asset(&balance_asset.asset)
+                .or_else(|_| registry.resolve_asset(name))
+                .unwrap_or(&balance_asset.asset);
+            let conversion = graph.best_path(asset, quote).ok();
+            let open_conversion = open_graph.best_path(asset, quote).ok();
+
+            holdings.push(Holding {
+                name: name.clone(),
+                asset: asset.to_string(),
+                kind: balance_asset.kind,
+                amount: *amount,
+                value: conversion.as_ref().map(|conversion| conversion.convert(*amount)),
+                open_value: open_conversion.map(|conversion| conversion.convert(*amount)),
+                weight: None,
+                conversion,
+            });
+        }
+
+        let total: f64 = holdings.iter().filter_map(|holding| holding.value).sum();
+        let open_total = holdings.iter().filter_map(|holding| holding.open_value).sum();
+        for holding in &mut holdings {
+            holding.weight = holding.value.filter(|_| total != 0.0).map(|value| value / total);
+        }
+        holdings.sort_by(|a, b| {
+            let ordering = match (a.value, b.value) {
+                (Some(a), Some(b)) => b.partial_cmp(&a).unwrap_or(Ordering::Equal),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            };
+            ordering.then_with(|| a.name.cmp(&b.name))
+        });
+
+        Ok(Self {
+            quote: quote.to_string(),
+            holdings,
+            total,
+            open_total,
+        })
+    }
+
+    // Fetches the balances and the tickers of all the pairs
+    pub async fn fetch(
+        kraken: &Kraken,
+        registry: &AssetRegistry,
+        quote: &str,
+        source: PriceSource,
+    ) -> Result<Self, Errors> {
+        let balances = kraken.account_balance(&[]).await?;
+        let tickers = kraken.ticker(&[]).await?;
+
+        Self::new(registry, &balances, &tickers, quote, source)
+    }
+
+    // Holding of the name in account_balance
+    pub fn holding(&self, name: &str) -> Option<&Holding> {
+        self.holdings.iter().find(|holding| holding.name == name)
+    }
+
+    // Holdings without a conversion to the quote currency, left out of the totals
+    pub fn unvalued(&self) -> impl Iterator<Item = &Holding> {
+        self.holdings.iter().filter(|holding| holding.value.is_none())
+    }
+
+    pub fn change(&self) -> f64 {
+        self.total - self.open_total
+    }
+
+    pub fn change_percent(&self) -> Option<f64> {
+        if self.open_total == 0.0 {
+            None
+        } else {
+            Some(self.change() / self.open_total * 100.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::tests::{create_test_registry, create_tickers};
+
+    #[test]
+    fn test_portfolio() {
+        let registry = create_test_registry();
+        // XBT/USD opened the day lower
+        let tickers = create_tickers(&[
+            ("XXBTZUSD", 50000.0, 50000.0, 50000.0, 40000.0),
+            ("DOTEUR", 20.0, 20.0, 20.0, 20.0),
+            ("ZEURZUSD", 1.25, 1.25, 1.25, 1.25),
+        ]);
+        let balances: HashMap<String, f64> = vec![
+            ("XXBT", 0.1),
+            ("XBT.M", 0.05),
+            ("DOT.S", 100.0),
+            ("ZUSD", 2500.0),
+            ("ZEUR", 0.0),
+            ("ADA", 10.0),
+        ]
+        .into_iter()
+        .map(|(name, amount)| (name.to_string(), amount))
+        .collect();
+
+        let portfolio = Portfolio::new(&registry, &balances, &tickers, "USD", PriceSource::Mid).unwrap();
+        assert_eq!(portfolio.quote, "ZUSD");
+        let names: Vec<&str> = portfolio.holdings.iter().map(|holding| holding.name.as_str()).collect();
+        assert_eq!(names, vec!["XXBT", "DOT.S", "XBT.M", "ZUSD", "ADA"]);
+        assert_eq!(portfolio.total, 12500.0);
+        assert_eq!(portfolio.open_total, 11000.0);
+        assert_eq!(portfolio.change(), 1500.0);
+        assert!((portfolio.change_percent().unwrap() - 13.636363636363).abs() < 1e-9);
+
+        let holding = portfolio.holding("XXBT").unwrap();
+        assert_eq!(holding.value, Some(5000.0));
+        assert_eq!(holding.weight, Some(0.4));
+        assert_eq!(holding.change(), Some(1000.0));
+        assert_eq!(holding.change_percent(), Some(25.0));
+
+        let holding = portfolio.holding("XBT.M").unwrap();
+        assert_eq!(holding.asset, "XXBT");
+        assert_eq!(holding.kind, BalanceKind::OptIn);
+        assert_eq!(holding.value, Some(2500.0));
+
+        // Valued through EUR
+        let holding = portfolio.holding("DOT.S").unwrap();
+        assert_eq!(holding.asset, "DOT");
+        assert_eq!(holding.kind, BalanceKind::Staked);
+        assert_eq!(holding.value, Some(2500.0));
+        assert_eq!(holding.weight, Some(0.2));
+        assert_eq!(holding.change(), Some(0.0));
+        assert_eq!(holding.conversion.as_ref().unwrap().pairs(), vec!["DOTEUR", "ZEURZUSD"]);
+
+        let holding = portfolio.holding("ZUSD").unwrap();
+        assert_eq!(holding.value, Some(2500.0));
+        assert!(holding.conversion.as_ref().unwrap().path.is_empty());
+
+        let unvalued: Vec<&str> = portfolio.unvalued().map(|holding| holding.name.as_str()).collect();
+        assert_eq!(unvalued, vec!["ADA"]);
+        assert_eq!(portfolio.holding("ADA").unwrap().weight, None);
+        assert!(portfolio.holding("ZEUR").is_none());
+
+        assert!(Portfolio::new(&registry, &balances, &tickers, "GBP", PriceSource::Mid).is_err());
+    }
+}
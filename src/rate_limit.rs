@@ -0,0 +1,95 @@
+// Client side limit of the calls to the REST API, modeled after Kraken's call counter
+use std::time::Duration;
+use tokio::time::{delay_for, Instant};
+
+// Error Kraken returns when the counter went over the limit
+pub const RATE_LIMIT_ERROR: &str = "EAPI:Rate limit exceeded";
+
+// Each call adds its cost to a counter that decays over time. Calls wait until their cost fits under the
+// maximum of the counter
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    max: f64,
+    // Points the counter decreases by each second
+    decay: f64,
+    counter: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(max: f64, decay: f64) -> Self {
+        Self {
+            max,
+            decay,
+            counter: 0.0,
+            updated: Instant::now(),
+        }
+    }
+
+    // Public endpoints allow about one call per second
+    pub fn public() -> Self {
+        Self::new(1.0, 1.0)
+    }
+
+    // Counter of the private endpoints for the Starter verification tier
+    pub fn starter() -> Self {
+        Self::new(15.0, 0.33)
+    }
+
+    // Counter of the private endpoints for the Intermediate and Pro verification tiers
+    pub fn intermediate() -> Self {
+        Self::new(20.0, 0.5)
+    }
+
+    // Current value of the counter, after the decay
+    pub fn counter(&mut self) -> f64 {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.counter = (self.counter - elapsed * self.decay).max(0.0);
+        self.updated = now;
+
+        self.counter
+    }
+
+    // Time until a call of the cost can be made
+    pub fn wait_time(&mut self, cost: f64) -> Duration {
+        let excess = self.counter() + cost - self.max;
+        if excess <= 0.0 || self.decay <= 0.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64(excess / self.decay)
+        }
+    }
+
+    // Waits until the call fits under the limit and adds its cost to the counter
+    pub async fn acquire(&mut self, cost: f64) {
+        let wait = self.wait_time(cost);
+        if wait > Duration::from_secs(0) {
+            delay_for(wait).await;
+        }
+        self.counter();
+        self.counter += cost;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rate_limiter() {
+        let mut limiter = RateLimiter::new(2.0, 20.0);
+        assert_eq!(limiter.wait_time(1.0), Duration::from_secs(0));
+
+        let start = Instant::now();
+        limiter.acquire(1.0).await;
+        limiter.acquire(1.0).await;
+        assert!(start.elapsed() < Duration::from_millis(40));
+        assert!(limiter.wait_time(1.0) > Duration::from_millis(0));
+
+        // A third call waits for the counter to decay by a point, 50ms
+        limiter.acquire(1.0).await;
+        assert!(start.elapsed() >= Duration::from_millis(40));
+        assert!(limiter.counter() <= 2.0);
+    }
+}
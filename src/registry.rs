@@ -0,0 +1,406 @@
+// Assets and pairs of Kraken, to resolve the different spellings of their names
+use super::{validation::OrderValidator, Asset, AssetPairInfo, AssetPairs, Errors, Kraken, NewOrder};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::Path, time::Duration};
+use tokio::fs;
+
+// Common names of assets that Kraken lists under another altname
+const ALIASES: [(&str, &str); 2] = [("BTC", "XBT"), ("DOGE", "XDG")];
+
+// Separators used in friendly pair names, such as XBT/USD or BTC-USD
+const PAIR_SEPARATORS: [char; 2] = ['/', '-'];
+
+// Suffix of the dark pool pairs, which share their base and quote with the regular pairs
+const DARK_POOL_SUFFIX: &str = ".d";
+
+#[derive(Debug, Clone)]
+pub struct AssetRegistry {
+    // By Kraken's name, e.g. XXBT
+    assets: HashMap<String, Asset>,
+    // By Kraken's name, e.g. XXBTZUSD
+    pairs: HashMap<String, AssetPairInfo>,
+    // Uppercased spellings to Kraken's name
+    asset_names: HashMap<String, String>,
+    pair_names: HashMap<String, String>,
+    // Kraken's pair names by the Kraken's names of their base and quote
+    pairs_by_assets: HashMap<(String, String), String>,
+    updated: DateTime<Utc>,
+}
+
+// Contents of the cache file
+#[derive(Deserialize, Serialize)]
+struct Cache {
+    updated: DateTime<Utc>,
+    assets: HashMap<String, Asset>,
+    pairs: HashMap<String, AssetPairInfo>,
+}
+
+impl AssetRegistry {
+    pub fn new(assets: HashMap<String, Asset>, pairs: HashMap<String, AssetPairInfo>) -> Self {
+        Self::with_updated(assets, pairs, Utc::now())
+    }
+
+    // Fetches all the assets and pairs
+    pub async fn fetch(kraken: &Kraken) -> Result<Self, Errors> {
+        let assets = kraken.assets(&[]).await?;
+        let pairs = match kraken.asset_pairs(&[]).await? {
+            AssetPairs::Info(pairs) => pairs,
+            _ => return Err(Errors::InvalidFormat),
+        };
+
+        Ok(Self::new(assets, pairs))
+    }
+
+    pub async fn refresh(&mut self, kraken: &Kraken) -> Result<(), Errors> {
+        *self = Self::fetch(kraken).await?;
+
+        Ok(())
+    }
+
+    pub fn updated(&self) -> DateTime<Utc> {
+        self.updated
+    }
+
+    pub fn is_expired(&self, max_age: Duration) -> bool {
+        let age = Utc::now().signed_duration_since(self.updated);
+        age.to_std().map_or(false, |age| age > max_age)
+    }
+
+    // Loads the registry saved by save
+    pub async fn load(path: &Path) -> Result<Self, Errors> {
+        let data = fs::read(path).await?;
+        let cache: Cache = serde_json::from_slice(&data).map_err(|error| Errors::Parse(error.to_string()))?;
+
+        Ok(Self::with_updated(cache.assets, cache.pairs, cache.updated))
+    }
+
+    // Loads the registry from the cache file, fetching and saving it again when it's missing, invalid or
+    // older than max_age
+    pub async fn load_or_fetch(path: &Path, max_age: Duration, kraken: &Kraken) -> Result<Self, Errors> {
+        match Self::load(path).await {
+            Ok(registry) if !registry.is_expired(max_age) => Ok(registry),
+            _ => {
+                let registry = Self::fetch(kraken).await?;
+                registry.save(path).await?;
+                Ok(registry)
+            }
+        }
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<(), Errors> {
+        let cache = Cache {
+            updated: self.updated,
+            assets: self.assets.clone(),
+            pairs: self.pairs.clone(),
+        };
+        let data = serde_json::to_vec(&cache).map_err(|error| Errors::Parse(error.to_string()))?;
+        fs::write(path, data).await?;
+
+        Ok(())
+    }
+
+    pub fn assets(&self) -> &HashMap<String, Asset> {
+        &self.assets
+    }
+
+    pub fn pairs(&self) -> &HashMap<String, AssetPairInfo> {
+        &self.pairs
+    }
+
+    // Kraken's name of the asset, e.g. XXBT for xbt, XBT, BTC or XXBT
+    pub fn resolve_asset(&self, name: &str) -> Result<&str, Errors> {
+        self.asset_names
+            .get(&name.trim().to_uppercase())
+            .map(String::as_str)
+            .ok_or_else(|| Errors::InvalidParams(format!("Unknown asset {}", name)))
+    }
+
+    // Kraken's name of the pair, e.g. XXBTZUSD for XBTUSD, XBT/USD, btc-usd or BTCUSD
+    pub fn resolve_pair(&self, name: &str) -> Result<&str, Errors> {
+        let upper = name.trim().to_uppercase();
+        if let Some(pair) = self.pair_names.get(&upper) {
+            return Ok(pair);
+        }
+
+        let pair = match upper.find(&PAIR_SEPARATORS[..]) {
+            Some(index) => self.find_pair(&upper[..index], &upper[index + 1..]),
+            // Without a separator every split is tried, since names have different lengths
+            None => upper
+                .char_indices()
+                .skip(1)
+                .find_map(|(index, _)| self.find_pair(&upper[..index], &upper[index..])),
+        };
+
+        pair.ok_or_else(|| Errors::InvalidParams(format!("Unknown pair {}", name)))
+    }
+
+    // Kraken's name of the pair trading base against quote, in any of their spellings
+    pub fn pair_of(&self, base: &str, quote: &str) -> Result<&str, Errors> {
+        self.find_pair(base, quote)
+            .ok_or_else(|| Errors::InvalidParams(format!("No pair of {} and {}", base, quote)))
+    }
+
+    pub fn asset(&self, name: &str) -> Result<&Asset, Errors> {
+        let name = self.resolve_asset(name)?;
+        Ok(&self.assets[name])
+    }
+
+    pub fn pair(&self, name: &str) -> Result<&AssetPairInfo, Errors> {
+        let name = self.resolve_pair(name)?;
+        Ok(&self.pairs[name])
+    }
+
+    // Altname of the asset, e.g. XBT for XXBT
+    pub fn asset_altname(&self, name: &str) -> Result<&str, Errors> {
+        Ok(&self.asset(name)?.altname)
+    }
+
+    // Name used by the WebSocket API, e.g. XBT/USD
+    pub fn pair_wsname(&self, name: &str) -> Result<&str, Errors> {
+        let pair = self.pair(name)?;
+        pair.wsname
+            .as_deref()
+            .ok_or_else(|| Errors::InvalidParams(format!("Pair {} isn't available over WebSocket", name)))
+    }
+
+    // Decimals of the asset's amounts
+    pub fn decimals(&self, asset: &str) -> Result<u64, Errors> {
+        Ok(self.asset(asset)?.decimals)
+    }
+
+    // Decimals of the pair's prices
+    pub fn price_decimals(&self, pair: &str) -> Result<u64, Errors> {
+        Ok(self.pair(pair)?.pair_decimals)
+    }
+
+    // Decimals of the pair's volumes
+    pub fn lot_decimals(&self, pair: &str) -> Result<u64, Errors> {
+        Ok(self.pair(pair)?.lot_decimals)
+    }
+
+    // Validates the order against the info of its pair, which can be in any spelling
+    pub fn validate_order(&self, order: &NewOrder) -> Result<NewOrder, Errors> {
+        OrderValidator::new(self.pair(&order.pair)?).validate(order)
+    }
+
+    fn with_updated(
+        assets: HashMap<String, Asset>,
+        pairs: HashMap<String, AssetPairInfo>,
+        updated: DateTime<Utc>,
+    ) -> Self {
+        let mut asset_names = HashMap::new();
+        for (name, asset) in &assets {
+            asset_names.insert(name.to_uppercase(), name.clone());
+            asset_names.insert(asset.altname.to_uppercase(), name.clone());
+        }
+        for (alias, altname) in ALIASES.iter() {
+            if let Some(name) = asset_names.get(*altname).cloned() {
+                asset_names.entry(alias.to_string()).or_insert(name);
+            }
+        }
+
+        let mut pair_names = HashMap::new();
+        let mut pairs_by_assets = HashMap::new();
+        for (name, pair) in &pairs {
+            pair_names.insert(name.to_uppercase(), name.clone());
+            pair_names.insert(pair.altname.to_uppercase(), name.clone());
+            if let Some(wsname) = &pair.wsname {
+                pair_names.insert(wsname.to_uppercase(), name.clone());
+            }
+            if !name.ends_with(DARK_POOL_SUFFIX) {
+                pairs_by_assets.insert((pair.base.clone(), pair.quote.clone()), name.clone());
+            }
+        }
+
+        Self {
+            assets,
+            pairs,
+            asset_names,
+            pair_names,
+            pairs_by_assets,
+            updated,
+        }
+    }
+
+    fn find_pair(&self, base: &str, quote: &str) -> Option<&str> {
+        let base = self.resolve_asset(base).ok()?;
+        let quote = self.resolve_asset(quote).ok()?;
+
+        self.pairs_by_assets
+            .get(&(base.to_string(), quote.to_string()))
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{OrderType, Side, Ticker, Trade};
+    use chrono::{DateTime, Utc};
+    use std::env;
+
+    // Pair of currencies with 1 price decimal, 8 lot decimals and three fee tiers, shared by the tests of the
+    // modules using pairs
+    pub(crate) fn create_pair(altname: &str, wsname: Option<&str>, base: &str, quote: &str) -> AssetPairInfo {
+        let pair = serde_json::json!({
+            "altname": altname, "wsname": wsname, "aclass_base": "currency", "base": base,
+            "aclass_quote": "currency", "quote": quote, "lot": "unit", "pair_decimals": 1, "lot_decimals": 8,
+            "lot_multiplier": 1, "leverage_buy": [2, 3, 4, 5], "leverage_sell": [2, 3],
+            "fees": [[0, 0.26], [50000, 0.24], [100000, 0.22]],
+            "fees_maker": [[0, 0.16], [50000, 0.14], [100000, 0.12]],
+            "fee_volume_currency": "ZUSD", "margin_call": 80, "margin_stop": 40, "ordermin": "0.0001"
+        });
+        // The numbers of the pairs are deserialized from borrowed strings
+        serde_json::from_str(&pair.to_string()).unwrap()
+    }
+
+    // Registry shared by the tests of the modules using assets and pairs. XXBT has 10 decimals and ZUSD 4, the
+    // other assets 8
+    pub(crate) fn create_test_registry() -> AssetRegistry {
+        let assets = [
+            ("XXBT", "XBT", 10),
+            ("XETH", "ETH", 8),
+            ("XXDG", "XDG", 8),
+            ("ZUSD", "USD", 4),
+            ("ZEUR", "EUR", 8),
+            ("USDT", "USDT", 8),
+            ("DOT", "DOT", 8),
+            ("ADA", "ADA", 8),
+        ]
+        .iter()
+        .map(|(name, altname, decimals)| {
+            let asset = serde_json::json!({
+                "aclass": "currency", "altname": altname, "decimals": decimals, "display_decimals": 5
+            });
+            (name.to_string(), serde_json::from_value(asset).unwrap())
+        })
+        .collect();
+        let pairs = vec![
+            ("XXBTZUSD", create_pair("XBTUSD", Some("XBT/USD"), "XXBT", "ZUSD")),
+            ("XXBTZUSD.d", create_pair("XBTUSD.d", None, "XXBT", "ZUSD")),
+            ("XXBTZEUR", create_pair("XBTEUR", None, "XXBT", "ZEUR")),
+            ("XETHXXBT", create_pair("ETHXBT", None, "XETH", "XXBT")),
+            ("XETHZUSD", create_pair("ETHUSD", None, "XETH", "ZUSD")),
+            ("XDGUSD", create_pair("XDGUSD", Some("XDG/USD"), "XXDG", "ZUSD")),
+            ("XDGEUR", create_pair("XDGEUR", None, "XXDG", "ZEUR")),
+            ("ZEURZUSD", create_pair("EURUSD", None, "ZEUR", "ZUSD")),
+            ("USDTZUSD", create_pair("USDTZUSD", Some("USDT/USD"), "USDT", "ZUSD")),
+            ("DOTUSD", create_pair("DOTUSD", None, "DOT", "ZUSD")),
+            ("DOTEUR", create_pair("DOTEUR", None, "DOT", "ZEUR")),
+        ]
+        .into_iter()
+        .map(|(name, pair)| (name.to_string(), pair))
+        .collect();
+
+        AssetRegistry::new(assets, pairs)
+    }
+
+    // Tickers of the pairs, given as (pair, ask, bid, last trade, today's opening price)
+    pub(crate) fn create_tickers(tickers: &[(&str, f64, f64, f64, f64)]) -> HashMap<String, Ticker> {
+        let tickers: serde_json::Map<String, serde_json::Value> = tickers
+            .iter()
+            .map(|(pair, ask, bid, last, open)| {
+                let (ask, bid, last) = (ask.to_string(), bid.to_string(), last.to_string());
+                let ticker = serde_json::json!({
+                    "a": [ask, "1", "1.000"], "b": [bid, "1", "1.000"], "c": [last, "0.1"], "v": ["1", "1"],
+                    "p": [last, last], "t": [1, 1], "l": [last, last], "h": [last, last], "o": open.to_string()
+                });
+                (pair.to_string(), ticker)
+            })
+            .collect();
+
+        serde_json::from_str(&serde_json::Value::from(tickers).to_string()).unwrap()
+    }
+
+    pub(crate) fn create_trade(pair: &str, side: &str, time: i64, price: f64, vol: f64, fee: f64) -> Trade {
+        let trade = serde_json::json!({
+            "ordertxid": "OQCLML-BW3P3-BUCMWZ", "pair": pair, "time": time, "type": side,
+            "ordertype": "limit", "price": price.to_string(), "cost": (price * vol).to_string(),
+            "fee": fee.to_string(), "vol": vol.to_string(), "margin": "0.00000", "misc": ""
+        });
+
+        serde_json::from_str(&trade.to_string()).unwrap()
+    }
+
+    pub(crate) fn time(timestamp: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(timestamp, 0).unwrap()
+    }
+
+    #[test]
+    fn test_resolve_asset() {
+        let registry = create_test_registry();
+
+        for name in &["XXBT", "XBT", "BTC", "btc", " xbt "] {
+            assert_eq!(registry.resolve_asset(name).unwrap(), "XXBT");
+        }
+        assert_eq!(registry.resolve_asset("DOGE").unwrap(), "XXDG");
+        assert_eq!(registry.resolve_asset("USD").unwrap(), "ZUSD");
+        assert_eq!(registry.asset_altname("XXBT").unwrap(), "XBT");
+        assert_eq!(registry.decimals("BTC").unwrap(), 10);
+        assert_eq!(registry.decimals("ZUSD").unwrap(), 4);
+        assert!(matches!(registry.resolve_asset("SOL"), Err(Errors::InvalidParams(_))));
+    }
+
+    #[test]
+    fn test_resolve_pair() {
+        let registry = create_test_registry();
+
+        for name in &[
+            "XXBTZUSD",
+            "XBTUSD",
+            "XBT/USD",
+            "xbt/usd",
+            "BTC/USD",
+            "BTC-USD",
+            "BTCUSD",
+            "XXBT/ZUSD",
+        ] {
+            assert_eq!(registry.resolve_pair(name).unwrap(), "XXBTZUSD", "{}", name);
+        }
+        assert_eq!(registry.resolve_pair("XBTUSD.d").unwrap(), "XXBTZUSD.d");
+        assert_eq!(registry.resolve_pair("DOGEUSD").unwrap(), "XDGUSD");
+        assert_eq!(registry.resolve_pair("USDTUSD").unwrap(), "USDTZUSD");
+        assert_eq!(registry.pair_of("btc", "usd").unwrap(), "XXBTZUSD");
+        assert!(registry.resolve_pair("USDXBT").is_err());
+        assert!(registry.pair_of("USD", "XBT").is_err());
+
+        assert_eq!(registry.pair_wsname("BTCUSD").unwrap(), "XBT/USD");
+        assert!(registry.pair_wsname("XBTUSD.d").is_err());
+        assert_eq!(registry.price_decimals("XBT/USD").unwrap(), 1);
+        assert_eq!(registry.lot_decimals("XBT/USD").unwrap(), 8);
+        assert_eq!(registry.pair("XBT/USD").unwrap().ordermin, Some(0.0001));
+    }
+
+    #[test]
+    fn test_validate_order() {
+        let registry = create_test_registry();
+
+        let mut order = NewOrder::new("btc/usd", Side::Buy, OrderType::Limit, 0.5);
+        order.price = Some(30000.04);
+        assert_eq!(registry.validate_order(&order).unwrap().price, Some(30000.0));
+
+        order.volume = 0.00001;
+        assert!(matches!(registry.validate_order(&order), Err(Errors::InvalidOrder(_))));
+
+        order.pair = "SOLUSD".to_string();
+        assert!(matches!(registry.validate_order(&order), Err(Errors::InvalidParams(_))));
+    }
+
+    #[tokio::test]
+    async fn test_cache() {
+        let registry = create_test_registry();
+        assert!(!registry.is_expired(Duration::from_secs(60)));
+
+        let path = env::temp_dir().join(format!("kraken_registry_{}.json", std::process::id()));
+        registry.save(&path).await.unwrap();
+        let loaded = AssetRegistry::load(&path).await.unwrap();
+        fs::remove_file(&path).await.unwrap();
+
+        assert_eq!(loaded.updated(), registry.updated());
+        assert_eq!(loaded.assets().len(), 8);
+        assert_eq!(loaded.pairs().len(), 11);
+        assert_eq!(loaded.resolve_pair("BTC/USD").unwrap(), "XXBTZUSD");
+        assert_eq!(loaded.pair("XBTUSD").unwrap().ordermin, Some(0.0001));
+    }
+}
@@ -0,0 +1,436 @@
+// Report of the disposals of the accounting for taxes
+use super::{
+    accounting::{Accounting, CostMethod, Disposal},
+    conversion::ConversionGraph,
+    csv::csv_field,
+    registry::AssetRegistry,
+    Candle, Errors, Kraken, LedgerEntry, Trade,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::{collections::HashMap, path::Path};
+use tokio::fs;
+
+// Holdings of more than a year are usually long term
+pub const LONG_TERM_DAYS: i64 = 365;
+
+// Daily candles, the longest history Kraken returns for the OHLC endpoint
+const OHLC_INTERVAL: &str = "1440";
+
+const CSV_HEADER: &str = "asset,refid,lot_refid,acquired,disposed,volume,proceeds,cost,gain,term";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HoldingTerm {
+    Short,
+    Long,
+}
+
+impl HoldingTerm {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Short => "short",
+            Self::Long => "long",
+        }
+    }
+}
+
+// Values of assets in a fiat currency from OHLC candles. Assets without a pair with the fiat currency are valued
+// through chains of pairs, on a ConversionGraph of the candle prices
+pub struct OhlcRates {
+    // Kraken's name of the fiat currency
+    fiat: String,
+    // Length of the candles
+    interval: Duration,
+    // Candles by Kraken's pair name, oldest first
+    candles: HashMap<String, Vec<Candle>>,
+}
+
+impl OhlcRates {
+    // Rates from daily candles
+    pub fn new(fiat: &str) -> Self {
+        Self {
+            fiat: fiat.to_string(),
+            interval: Duration::days(1),
+            candles: HashMap::new(),
+        }
+    }
+
+    // Fetches the daily candles of the pairs valuing the assets in the fiat currency through the fewest pairs.
+    // Kraken only returns the last 720 candles, so older rates are missing. Assets without such pairs are left
+    // out
+    pub async fn fetch(kraken: &Kraken, registry: &AssetRegistry, assets: &[&str], fiat: &str) -> Result<Self, Errors> {
+        let fiat = registry.resolve_asset(fiat)?;
+        let graph = pairs_graph(registry);
+        let mut rates = Self::new(fiat);
+        for asset in assets {
+            let asset = registry.resolve_asset(asset)?;
+            let conversion = match graph.best_path(asset, fiat) {
+                Ok(conversion) => conversion,
+                Err(_) => continue,
+            };
+            for pair in conversion.pairs() {
+                if rates.candles.contains_key(pair) {
+                    continue;
+                }
+                let ohlc = kraken.ohlc(&[("pair", pair), ("interval", OHLC_INTERVAL)]).await?;
+                if let Some(candles) = ohlc.pairs.into_iter().next().map(|(_, candles)| candles) {
+                    rates.add(pair, candles);
+                }
+            }
+        }
+
+        Ok(rates)
+    }
+
+    // Adds the candles of the pair, by Kraken's name
+    pub fn add(&mut self, pair: &str, candles: Vec<Candle>) {
+        let entry = self.candles.entry(pair.to_string()).or_default();
+        entry.extend(candles);
+        entry.sort_by_key(|candle| candle.time);
+        entry.dedup_by_key(|candle| candle.time);
+    }
+
+    // Value of the asset in the fiat currency at the time, through the best path of the pairs with a price
+    // then. None before the first candle and after the last one has ended
+    pub fn rate(&self, registry: &AssetRegistry, asset: &str, time: DateTime<Utc>) -> Option<f64> {
+        let prices = self
+            .candles
+            .iter()
+            .filter_map(|(pair, candles)| Some((pair.clone(), self.price(candles, time)?)))
+            .collect();
+        let graph = ConversionGraph::from_prices(registry, &prices);
+
+        graph
+            .best_path(asset, &self.fiat)
+            .ok()
+            .map(|conversion| conversion.rate)
+    }
+
+    // Price of the pair at the time, the close of the candle containing it, or of the last candle before a
+    // gap. None once the last candle has ended
+    fn price(&self, candles: &[Candle], time: DateTime<Utc>) -> Option<f64> {
+        let index = candles.partition_point(|candle| candle.time <= time);
+        let candle = &candles[index.checked_sub(1)?];
+        if index == candles.len() && time >= candle.time + self.interval {
+            return None;
+        }
+
+        Some(candle.close).filter(|close| *close > 0.0)
+    }
+}
+
+// Graph of the pairs of the registry where every path has the same rate, so the best one goes through the fewest
+// pairs. Dark pools share their assets with the regular pairs, so they are left out
+fn pairs_graph(registry: &AssetRegistry) -> ConversionGraph<'_> {
+    let prices = registry
+        .pairs()
+        .iter()
+        .filter(|(name, pair)| registry.pair_of(&pair.base, &pair.quote).ok() == Some(name.as_str()))
+        .map(|(name, _)| (name.clone(), 1.0))
+        .collect();
+
+    ConversionGraph::from_prices(registry, &prices)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TaxLotRecord {
+    pub asset: String,
+    // Trade or ledger entry of the disposal
+    pub refid: String,
+    // Trade or ledger entry of the acquisition, none when unknown
+    pub lot_refid: Option<String>,
+    pub acquired: Option<DateTime<Utc>>,
+    pub disposed: DateTime<Utc>,
+    pub volume: f64,
+    pub proceeds: f64,
+    pub cost: f64,
+    pub gain: f64,
+    // None without an acquisition date
+    pub term: Option<HoldingTerm>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TaxReport {
+    // Kraken's name of the currency of the amounts
+    pub currency: String,
+    // In the order of the disposals
+    pub records: Vec<TaxLotRecord>,
+}
+
+impl TaxReport {
+    // Holdings of more than long_term_days are long term
+    pub fn new(currency: &str, disposals: &[Disposal], long_term_days: i64) -> Self {
+        let records = disposals
+            .iter()
+            .map(|disposal| TaxLotRecord {
+                asset: disposal.asset.clone(),
+                refid: disposal.refid.clone(),
+                lot_refid: disposal.lot_refid.clone(),
+                acquired: disposal.acquired,
+                disposed: disposal.disposed,
+                volume: disposal.volume,
+                proceeds: disposal.proceeds,
+                cost: disposal.cost,
+                gain: disposal.gain(),
+                term: disposal.acquired.map(|acquired| {
+                    if disposal.disposed - acquired > Duration::days(long_term_days) {
+                        HoldingTerm::Long
+                    } else {
+                        HoldingTerm::Short
+                    }
+                }),
+            })
+            .collect();
+
+        Self {
+            currency: currency.to_string(),
+            records,
+        }
+    }
+
+    // Runs the accounting in the fiat currency of the rates. Trades quoted in it keep their price, others are
+    // converted with the OHLC rates, at the close of the daily candle of the trade
+    pub fn from_history(
+        registry: &AssetRegistry,
+        trades: &HashMap<String, Trade>,
+        ledgers: &HashMap<String, LedgerEntry>,
+        method: CostMethod,
+        rates: &OhlcRates,
+    ) -> Result<Self, Errors> {
+        let accounting = Accounting::from_history(method, &rates.fiat, registry, trades, ledgers, |asset, time| {
+            rates.rate(registry, asset, time)
+        })?;
+
+        Ok(Self::new(accounting.currency(), accounting.disposals(), LONG_TERM_DAYS))
+    }
+
+    // Sum of the gains of the term, or of the records without one
+    pub fn gain(&self, term: Option<HoldingTerm>) -> f64 {
+        self.records
+            .iter()
+            .filter(|record| record.term == term)
+            .map(|record| record.gain)
+            .sum()
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("{}\n", CSV_HEADER);
+        for record in &self.records {
+            let fields = [
+                csv_field(&record.asset),
+                csv_field(&record.refid),
+                csv_field(record.lot_refid.as_deref().unwrap_or("")),
+                record
+                    .acquired
+                    .map(|acquired| acquired.to_rfc3339())
+                    .unwrap_or_default(),
+                record.disposed.to_rfc3339(),
+                record.volume.to_string(),
+                record.proceeds.to_string(),
+                record.cost.to_string(),
+                record.gain.to_string(),
+                record.term.map(|term| term.as_str().to_string()).unwrap_or_default(),
+            ];
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+
+        csv
+    }
+
+    pub fn to_json(&self) -> Result<String, Errors> {
+        serde_json::to_string_pretty(self).map_err(|error| Errors::Parse(error.to_string()))
+    }
+
+    pub async fn save_csv(&self, path: &Path) -> Result<(), Errors> {
+        fs::write(path, self.to_csv()).await?;
+
+        Ok(())
+    }
+
+    pub async fn save_json(&self, path: &Path) -> Result<(), Errors> {
+        fs::write(path, self.to_json()?).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::tests::{create_test_registry, create_trade, time};
+
+    const DAY: i64 = 86400;
+
+    fn create_candles(closes: &[(i64, f64)]) -> Vec<Candle> {
+        closes
+            .iter()
+            .map(|(timestamp, close)| Candle {
+                time: time(*timestamp),
+                open: *close,
+                high: *close,
+                low: *close,
+                close: *close,
+                vwap: *close,
+                volume: 1.0,
+                count: 1,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ohlc_rates() {
+        let registry = create_test_registry();
+        let mut rates = OhlcRates::new("ZEUR");
+        let mut candles = create_candles(&[(DAY, 100.0), (2 * DAY, 200.0), (4 * DAY, 400.0)]);
+        // Opens differ from the closes
+        candles[1].open = 150.0;
+        rates.add("XXBTZEUR", candles);
+        rates.add("XXBTZEUR", create_candles(&[(0, 50.0)]));
+        let rate = |asset: &str, timestamp: i64| rates.rate(&registry, asset, time(timestamp));
+
+        assert_eq!(rate("ZEUR", 0), Some(1.0));
+        assert_eq!(rate("XXBT", -1), None);
+        assert_eq!(rate("XXBT", 0), Some(50.0));
+        assert_eq!(rate("XXBT", DAY + 3600), Some(100.0));
+        // The close of the candle, not its open
+        assert_eq!(rate("XXBT", 2 * DAY + 3600), Some(200.0));
+        // A missing candle is bridged with the close before it
+        assert_eq!(rate("XXBT", 3 * DAY + 3600), Some(200.0));
+        assert_eq!(rate("XXBT", 5 * DAY - 1), Some(400.0));
+        assert_eq!(rate("XXBT", 5 * DAY), None);
+        assert_eq!(rate("XETH", DAY), None);
+
+        // Inverse pairs and chains of pairs
+        rates.add("ZEURZUSD", create_candles(&[(0, 1.25)]));
+        rates.add("XETHXXBT", create_candles(&[(0, 0.1)]));
+        let rate = |asset: &str, timestamp: i64| rates.rate(&registry, asset, time(timestamp));
+        assert_eq!(rate("ZUSD", 0), Some(0.8));
+        assert_eq!(rate("XETH", 0), Some(5.0));
+        assert_eq!(rate("XETH", DAY), None);
+    }
+
+    #[test]
+    fn test_pairs_graph() {
+        let registry = create_test_registry();
+        let graph = pairs_graph(&registry);
+        let pairs = |from: &str, to: &str| {
+            graph
+                .best_path(from, to)
+                .ok()
+                .map(|conversion| conversion.pairs().join(","))
+        };
+
+        assert_eq!(pairs("XXBT", "ZEUR"), Some("XXBTZEUR".to_string()));
+        assert_eq!(pairs("ZEUR", "ZUSD"), Some("ZEURZUSD".to_string()));
+        // Not through the dark pool
+        assert_eq!(pairs("XXBT", "ZUSD"), Some("XXBTZUSD".to_string()));
+        assert_eq!(pairs("USDT", "ZEUR"), Some("USDTZUSD,ZEURZUSD".to_string()));
+        assert_eq!(pairs("ZEUR", "ZEUR"), Some(String::new()));
+        assert_eq!(pairs("ADA", "ZEUR"), None);
+    }
+
+    #[test]
+    fn test_report() {
+        let registry = create_test_registry();
+        let mut rates = OhlcRates::new("ZEUR");
+        rates.add(
+            "XXBTZEUR",
+            create_candles(&[(0, 10000.0), (400 * DAY, 20000.0), (500 * DAY, 30000.0)]),
+        );
+        let trades: HashMap<String, Trade> = vec![
+            ("T1", create_trade("XXBTZEUR", "buy", 0, 10000.0, 1.0, 0.0)),
+            // Valued with the close of the XBT candle, 20000
+            ("T2", create_trade("XETHXXBT", "buy", 400 * DAY, 0.1, 2.0, 0.0)),
+            // Disposes of the ETH bought 100 days before, at a price of 30000 for XBT
+            ("T3", create_trade("XETHXXBT", "sell", 500 * DAY, 0.15, 1.0, 0.0)),
+            // Traded in the fiat currency, so the trade price is used
+            ("T4", create_trade("XXBTZEUR", "sell", 500 * DAY, 31000.0, 0.5, 0.0)),
+        ]
+        .into_iter()
+        .map(|(txid, trade)| (txid.to_string(), trade))
+        .collect();
+
+        let report = TaxReport::from_history(&registry, &trades, &HashMap::new(), CostMethod::Fifo, &rates).unwrap();
+        assert_eq!(report.currency, "ZEUR");
+        let records: Vec<(&str, &str, f64, f64, Option<HoldingTerm>)> = report
+            .records
+            .iter()
+            .map(|record| {
+                (
+                    record.asset.as_str(),
+                    record.refid.as_str(),
+                    record.proceeds,
+                    record.cost,
+                    record.term,
+                )
+            })
+            .collect();
+        assert_eq!(
+            records,
+            vec![
+                ("XXBT", "T2", 4000.0, 2000.0, Some(HoldingTerm::Long)),
+                ("XETH", "T3", 4500.0, 2000.0, Some(HoldingTerm::Short)),
+                ("XXBT", "T4", 15500.0, 5000.0, Some(HoldingTerm::Long)),
+            ]
+        );
+        assert_eq!(report.gain(Some(HoldingTerm::Long)), 2000.0 + 10500.0);
+        assert_eq!(report.gain(Some(HoldingTerm::Short)), 2500.0);
+        assert_eq!(report.gain(None), 0.0);
+
+        // Without an acquisition date there is no term
+        let report = TaxReport::new("ZEUR", &report_disposals(), 365);
+        assert_eq!(report.records[0].term, None);
+
+        let rates = OhlcRates::new("ZEUR");
+        assert!(TaxReport::from_history(&registry, &trades, &HashMap::new(), CostMethod::Fifo, &rates).is_err());
+    }
+
+    fn report_disposals() -> Vec<Disposal> {
+        vec![Disposal {
+            asset: "XXBT".to_string(),
+            refid: "T1, partial".to_string(),
+            lot_refid: None,
+            acquired: None,
+            disposed: time(0),
+            volume: 1.0,
+            cost: 0.0,
+            proceeds: 1.5,
+        }]
+    }
+
+    #[test]
+    fn test_export() {
+        let mut report = TaxReport::new("ZEUR", &report_disposals(), 365);
+        report.records.push(TaxLotRecord {
+            asset: "XETH".to_string(),
+            refid: "T2".to_string(),
+            lot_refid: Some("T0".to_string()),
+            acquired: Some(time(0)),
+            disposed: time(DAY),
+            volume: 2.0,
+            proceeds: 300.0,
+            cost: 200.0,
+            gain: 100.0,
+            term: Some(HoldingTerm::Short),
+        });
+
+        assert_eq!(
+            report.to_csv(),
+            format!(
+                "{}\n{}\n{}\n",
+                CSV_HEADER,
+                "XXBT,\"T1, partial\",,,1970-01-01T00:00:00+00:00,1,1.5,0,1.5,",
+                "XETH,T2,T0,1970-01-01T00:00:00+00:00,1970-01-02T00:00:00+00:00,2,300,200,100,short"
+            )
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["currency"], "ZEUR");
+        assert_eq!(json["records"][0]["acquired"], serde_json::Value::Null);
+        assert_eq!(json["records"][1]["acquired"], "1970-01-01T00:00:00Z");
+        assert_eq!(json["records"][1]["term"], "short");
+        assert_eq!(json["records"][1]["gain"], 100.0);
+    }
+}
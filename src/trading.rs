@@ -0,0 +1,93 @@
+use super::{registry::AssetRegistry, ws, AddedOrder, Errors, Kraken, NewOrder};
+
+// Transport used to place and cancel orders
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transport {
+    Rest,
+    // Lower latency, needs a ws::Client connected to ws::PRIVATE_URL with a token
+    WebSocket,
+}
+
+enum Backend<'a> {
+    Rest(&'a Kraken),
+    WebSocket(&'a ws::Client),
+}
+
+// Places and cancels orders through either transport, so strategies can switch between them with a setting.
+// REST orders are resolved by the registry of the Kraken client. The registry of the trader translates the pairs
+// to their WebSocket name, without it WebSocket orders need that name already
+pub struct Trader<'a> {
+    backend: Backend<'a>,
+    registry: Option<&'a AssetRegistry>,
+}
+
+impl<'a> Trader<'a> {
+    pub fn new(
+        transport: Transport,
+        kraken: &'a Kraken,
+        client: Option<&'a ws::Client>,
+        registry: Option<&'a AssetRegistry>,
+    ) -> Result<Self, Errors> {
+        let backend = match (transport, client) {
+            (Transport::Rest, _) => Backend::Rest(kraken),
+            (Transport::WebSocket, Some(client)) => Backend::WebSocket(client),
+            (Transport::WebSocket, None) => {
+                return Err(Errors::InvalidParams(
+                    "A WebSocket client is needed for the WebSocket transport".to_string(),
+                ))
+            }
+        };
+
+        Ok(Self { backend, registry })
+    }
+
+    pub fn transport(&self) -> Transport {
+        match self.backend {
+            Backend::Rest(_) => Transport::Rest,
+            Backend::WebSocket(_) => Transport::WebSocket,
+        }
+    }
+
+    // The order with the pair name of the transport: Kraken's name for REST, the WebSocket name otherwise. The
+    // pair is kept as it is without a registry
+    pub fn translate(&self, order: &NewOrder) -> Result<NewOrder, Errors> {
+        let registry = match self.backend {
+            Backend::Rest(kraken) => kraken.registry(),
+            Backend::WebSocket(_) => self.registry,
+        };
+        let pair = match (registry, &self.backend) {
+            (None, _) => return Ok(order.clone()),
+            (Some(registry), Backend::Rest(_)) => registry.resolve_pair(&order.pair)?,
+            (Some(registry), Backend::WebSocket(_)) => registry.pair_wsname(&order.pair)?,
+        };
+
+        let mut order = order.clone();
+        order.pair = pair.to_string();
+        Ok(order)
+    }
+
+    pub async fn add_order(&self, order: &NewOrder) -> Result<AddedOrder, Errors> {
+        let order = self.translate(order)?;
+
+        match self.backend {
+            Backend::Rest(kraken) => kraken.add_order(&order).await,
+            Backend::WebSocket(client) => client.add_order(&order).await,
+        }
+    }
+
+    // Errors if the order couldn't be canceled
+    pub async fn cancel_order(&self, txid: &str) -> Result<(), Errors> {
+        match self.backend {
+            Backend::Rest(kraken) => kraken.cancel_order(txid).await.map(|_| ()),
+            Backend::WebSocket(client) => client.cancel_order(txid).await,
+        }
+    }
+
+    // Returns the amount of canceled orders
+    pub async fn cancel_all(&self) -> Result<u64, Errors> {
+        match self.backend {
+            Backend::Rest(kraken) => kraken.cancel_all().await,
+            Backend::WebSocket(client) => client.cancel_all().await,
+        }
+    }
+}
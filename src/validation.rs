@@ -0,0 +1,213 @@
+// Checks of orders against the pair info, to catch what Kraken would reject before sending them
+use super::{AssetPairInfo, Errors, NewOrder, OrderFlag, OrderType, Side};
+use std::fmt;
+
+// Reason of an order to be invalid for its pair
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderViolation {
+    // Not positive once rounded to the lot decimals
+    InvalidVolume(f64),
+    BelowMinimum { volume: f64, ordermin: f64 },
+    // Name of the param, price or price2
+    MissingPrice(&'static str),
+    // Not positive once rounded to the pair decimals
+    InvalidPrice { param: &'static str, price: f64 },
+    LeverageNotAllowed { leverage: u64, allowed: Vec<u64> },
+}
+
+impl fmt::Display for OrderViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidVolume(volume) => write!(f, "Invalid volume: {}", volume),
+            Self::BelowMinimum { volume, ordermin } => {
+                write!(f, "Volume {} is below the minimum of {}", volume, ordermin)
+            }
+            Self::MissingPrice(param) => write!(f, "Missing {}", param),
+            Self::InvalidPrice { param, price } => write!(f, "Invalid {}: {}", param, price),
+            Self::LeverageNotAllowed { leverage, allowed } => {
+                write!(f, "Leverage {} isn't allowed, expected one of {:?}", leverage, allowed)
+            }
+        }
+    }
+}
+
+pub struct OrderValidator<'a> {
+    pair: &'a AssetPairInfo,
+}
+
+impl<'a> OrderValidator<'a> {
+    pub fn new(pair: &'a AssetPairInfo) -> Self {
+        Self { pair }
+    }
+
+    // Returns the order with the prices rounded to the nearest allowed value and the volume rounded down, or
+    // all the violations found. A volume in the quote currency (viqc) is left as is, since the lot decimals
+    // and the minimum apply to the base currency
+    pub fn validate(&self, order: &NewOrder) -> Result<NewOrder, Errors> {
+        let mut order = order.clone();
+        let mut violations = Vec::new();
+
+        if !order.oflags.contains(&OrderFlag::Viqc) {
+            order.volume = round_down(order.volume, self.pair.lot_decimals);
+            if !order.volume.is_finite() || order.volume <= 0.0 {
+                violations.push(OrderViolation::InvalidVolume(order.volume));
+            } else if let Some(ordermin) = self.pair.ordermin.filter(|ordermin| order.volume < *ordermin) {
+                violations.push(OrderViolation::BelowMinimum {
+                    volume: order.volume,
+                    ordermin,
+                });
+            }
+        }
+
+        let (needs_price, needs_price2) = needed_prices(&order.ordertype);
+        for (param, price, needed) in [
+            ("price", &mut order.price, needs_price),
+            ("price2", &mut order.price2, needs_price2),
+        ] {
+            match price {
+                Some(value) => {
+                    *value = round(*value, self.pair.pair_decimals);
+                    if !value.is_finite() || *value <= 0.0 {
+                        violations.push(OrderViolation::InvalidPrice { param, price: *value });
+                    }
+                }
+                None if needed => violations.push(OrderViolation::MissingPrice(param)),
+                None => {}
+            }
+        }
+
+        if let Some(leverage) = order.leverage {
+            let allowed: &[u64] = match order.side {
+                Side::Buy => &self.pair.leverage_buy,
+                Side::Sell => &self.pair.leverage_sell,
+                Side::Unknown(_) => &[],
+            };
+            if !allowed.contains(&leverage) {
+                violations.push(OrderViolation::LeverageNotAllowed {
+                    leverage,
+                    allowed: allowed.to_vec(),
+                });
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(order)
+        } else {
+            Err(Errors::InvalidOrder(violations))
+        }
+    }
+}
+
+// Whether the order type needs price and price2. Unknown types aren't checked
+fn needed_prices(ordertype: &OrderType) -> (bool, bool) {
+    match ordertype {
+        OrderType::Market | OrderType::SettlePosition | OrderType::Unknown(_) => (false, false),
+        OrderType::Limit | OrderType::StopLoss | OrderType::TakeProfit | OrderType::TrailingStop => (true, false),
+        OrderType::StopLossProfit
+        | OrderType::StopLossProfitLimit
+        | OrderType::StopLossLimit
+        | OrderType::TakeProfitLimit
+        | OrderType::TrailingStopLimit
+        | OrderType::StopLossAndLimit => (true, true),
+    }
+}
+
+// Scaled values this close to a step, relatively, are taken as on the step, to ignore the error of the float
+// product
+const ROUNDING_TOLERANCE: f64 = 1e-12;
+
+fn round(value: f64, decimals: u64) -> f64 {
+    let factor = 10f64.powi(decimals as i32);
+    (value * factor).round() / factor
+}
+
+fn round_down(value: f64, decimals: u64) -> f64 {
+    let factor = 10f64.powi(decimals as i32);
+    let scaled = value * factor;
+    let nearest = scaled.round();
+    if (scaled - nearest).abs() <= scaled.abs() * ROUNDING_TOLERANCE {
+        nearest / factor
+    } else {
+        scaled.floor() / factor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::tests::create_pair;
+    use crate::OrderFlags;
+
+    #[test]
+    fn test_rounding() {
+        let pair = create_pair("XBTUSD", Some("XBT/USD"), "XXBT", "ZUSD");
+        let validator = OrderValidator::new(&pair);
+
+        let mut order = NewOrder::new("XBTUSD", Side::Buy, OrderType::StopLossLimit, 0.123456789);
+        order.price = Some(35000.06);
+        order.price2 = Some(34999.94);
+        let order = validator.validate(&order).unwrap();
+        assert_eq!(order.volume, 0.12345678);
+        assert_eq!(order.price, Some(35000.1));
+        assert_eq!(order.price2, Some(34999.9));
+
+        // Not lowered by the error of the float product
+        let order = NewOrder::new("XBTUSD", Side::Buy, OrderType::Market, 0.29);
+        assert_eq!(validator.validate(&order).unwrap().volume, 0.29);
+        assert_eq!(round_down(1.005, 2), 1.0);
+        assert_eq!(round_down(0.07, 2), 0.07);
+
+        // A volume in quote currency isn't rounded to the lot decimals
+        let mut order = NewOrder::new("XBTUSD", Side::Buy, OrderType::Market, 0.000012345);
+        order.oflags = OrderFlags(vec![OrderFlag::Viqc]);
+        assert_eq!(validator.validate(&order).unwrap().volume, 0.000012345);
+    }
+
+    #[test]
+    fn test_violations() {
+        let pair = create_pair("XBTUSD", Some("XBT/USD"), "XXBT", "ZUSD");
+        let validator = OrderValidator::new(&pair);
+
+        let mut order = NewOrder::new("XBTUSD", Side::Sell, OrderType::StopLossLimit, 0.00005);
+        order.price = Some(0.04);
+        order.leverage = Some(5);
+        match validator.validate(&order) {
+            Err(Errors::InvalidOrder(violations)) => assert_eq!(
+                violations,
+                vec![
+                    OrderViolation::BelowMinimum {
+                        volume: 0.00005,
+                        ordermin: 0.0001
+                    },
+                    OrderViolation::InvalidPrice {
+                        param: "price",
+                        price: 0.0
+                    },
+                    OrderViolation::MissingPrice("price2"),
+                    OrderViolation::LeverageNotAllowed {
+                        leverage: 5,
+                        allowed: vec![2, 3]
+                    },
+                ]
+            ),
+            result => panic!("Unexpected result {:?}", result),
+        }
+
+        let order = NewOrder::new("XBTUSD", Side::Buy, OrderType::Limit, 0.000000001);
+        match validator.validate(&order) {
+            Err(Errors::InvalidOrder(violations)) => assert_eq!(
+                violations,
+                vec![
+                    OrderViolation::InvalidVolume(0.0),
+                    OrderViolation::MissingPrice("price")
+                ]
+            ),
+            result => panic!("Unexpected result {:?}", result),
+        }
+
+        let mut order = NewOrder::new("XBTUSD", Side::Buy, OrderType::Limit, 1.0);
+        order.price = Some(30000.0);
+        order.leverage = Some(5);
+        assert!(validator.validate(&order).is_ok());
+    }
+}
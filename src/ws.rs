@@ -0,0 +1,1349 @@
+// https://docs.kraken.com/websockets/
+use super::{
+    timestamp_to_datetime, AddedOrder, Candle, Errors, NewOrder, Order, OrderBook, OrderBookLevel, OrderStatus,
+    PublicTrade, Ticker, Trade,
+};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use futures::{
+    stream::Stream,
+    task::{Context, Poll},
+    SinkExt, StreamExt,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::{delay_for, delay_until, interval_at, timeout, Instant},
+};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+pub const PUBLIC_URL: &str = "wss://ws.kraken.com";
+pub const PRIVATE_URL: &str = "wss://ws-auth.kraken.com";
+
+pub struct Config {
+    pub url: String,
+    // Time to wait before reconnecting after the connection was lost
+    pub reconnect_delay: Duration,
+    // The connection is considered lost if no message arrives in this time
+    pub heartbeat_timeout: Duration,
+    // Interval of the pings sent to keep the connection alive
+    pub ping_interval: Duration,
+    // Token from Kraken::websockets_token, needed for the private channels
+    pub token: Option<String>,
+    // Time to wait for the response to an order request
+    pub request_timeout: Duration,
+    // Events buffered for the consumer. Once it's full, the connection isn't read until the consumer catches
+    // up, which delays the responses to requests and can make Kraken close the connection
+    pub event_capacity: usize,
+}
+
+impl Config {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            reconnect_delay: Duration::from_secs(5),
+            heartbeat_timeout: Duration::from_secs(15),
+            ping_interval: Duration::from_secs(5),
+            token: None,
+            request_timeout: Duration::from_secs(10),
+            event_capacity: 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Channel {
+    Ticker,
+    // Interval in minutes
+    Ohlc { interval: u64 },
+    Trade,
+    Spread,
+    // Depth of 10, 25, 100, 500 or 1000 levels
+    Book { depth: u64 },
+    // Private channels, available on PRIVATE_URL only. They aren't bound to pairs
+    OwnTrades,
+    OpenOrders,
+}
+
+impl Channel {
+    pub fn is_private(&self) -> bool {
+        matches!(self, Self::OwnTrades | Self::OpenOrders)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Ticker => "ticker",
+            Self::Ohlc { .. } => "ohlc",
+            Self::Trade => "trade",
+            Self::Spread => "spread",
+            Self::Book { .. } => "book",
+            Self::OwnTrades => "ownTrades",
+            Self::OpenOrders => "openOrders",
+        }
+    }
+
+    fn subscription(&self, token: Option<&str>) -> Value {
+        let mut subscription = json!({ "name": self.name() });
+        match self {
+            Self::Ohlc { interval } => subscription["interval"] = json!(interval),
+            Self::Book { depth } => subscription["depth"] = json!(depth),
+            _ => {}
+        }
+        if let Some(token) = token.filter(|_| self.is_private()) {
+            subscription["token"] = json!(token);
+        }
+
+        subscription
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subscription {
+    pub channel: Channel,
+    // WebSocket pair names, such as XBT/USD
+    pub pairs: Vec<String>,
+}
+
+impl Subscription {
+    fn message(&self, event: &str, reqid: u64, token: Option<&str>) -> Value {
+        let mut message = json!({
+            "event": event,
+            "reqid": reqid,
+            "subscription": self.channel.subscription(token),
+        });
+        if !self.pairs.is_empty() {
+            message["pair"] = json!(self.pairs);
+        }
+
+        message
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SystemStatus {
+    #[serde(rename = "connectionID")]
+    #[serde(default)]
+    pub connection_id: Option<u64>,
+    // online, maintenance, cancel_only, limit_only or post_only
+    pub status: String,
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubscriptionStatus {
+    #[serde(rename = "channelName")]
+    #[serde(default)]
+    pub channel_name: Option<String>,
+    #[serde(default)]
+    pub pair: Option<String>,
+    // subscribed, unsubscribed or error
+    pub status: String,
+    #[serde(default)]
+    pub reqid: Option<u64>,
+    #[serde(rename = "errorMessage")]
+    #[serde(default)]
+    pub error_message: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spread {
+    pub bid: f64,
+    pub ask: f64,
+    pub time: DateTime<Utc>,
+    pub bid_volume: f64,
+    pub ask_volume: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookEntry {
+    pub price: f64,
+    pub volume: f64,
+    pub time: DateTime<Utc>,
+    // The level was resent because of a change of its position in the book, not of its volume
+    pub republish: bool,
+    // Price and volume as sent by Kraken, needed to calculate the book checksum
+    pub raw_price: String,
+    pub raw_volume: String,
+}
+
+impl BookEntry {
+    pub fn level(&self) -> OrderBookLevel {
+        OrderBookLevel {
+            price: self.price,
+            volume: self.volume,
+            timestamp: self.time.timestamp() as u64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookSnapshot {
+    pub pair: String,
+    pub depth: u64,
+    pub asks: Vec<BookEntry>,
+    pub bids: Vec<BookEntry>,
+}
+
+impl BookSnapshot {
+    pub fn to_order_book(&self) -> OrderBook {
+        OrderBook {
+            asks: self.asks.iter().map(BookEntry::level).collect(),
+            bids: self.bids.iter().map(BookEntry::level).collect(),
+        }
+    }
+}
+
+// Entries with a volume of 0 remove their price level from the book
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookUpdate {
+    pub pair: String,
+    pub depth: u64,
+    pub asks: Vec<BookEntry>,
+    pub bids: Vec<BookEntry>,
+    // CRC32 checksum of the top 10 levels of the book after the update
+    pub checksum: Option<u32>,
+}
+
+// Changes of an order sent by the openOrders channel. Only the fields that changed are set
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderChange {
+    pub txid: String,
+    pub status: Option<OrderStatus>,
+    pub vol_exec: Option<f64>,
+    pub cost: Option<f64>,
+    pub fee: Option<f64>,
+    // Average price
+    pub price: Option<f64>,
+    pub stopprice: Option<f64>,
+    pub limitprice: Option<f64>,
+    // Reason of the cancellation
+    pub reason: Option<String>,
+}
+
+impl OrderChange {
+    pub fn apply(&self, order: &mut Order) {
+        if let Some(status) = &self.status {
+            order.status = status.clone();
+        }
+        let fields = [
+            (self.vol_exec, &mut order.vol_exec),
+            (self.cost, &mut order.cost),
+            (self.fee, &mut order.fee),
+            (self.price, &mut order.price),
+            (self.stopprice, &mut order.stopprice),
+            (self.limitprice, &mut order.limitprice),
+        ];
+        for (value, field) in fields {
+            if let Some(value) = value {
+                *field = value;
+            }
+        }
+        if self.reason.is_some() {
+            order.reason = self.reason.clone();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum OrderUpdate {
+    // Orders are sent in full when subscribing and when they are placed
+    New { txid: String, order: Box<Order> },
+    Change(OrderChange),
+}
+
+impl OrderUpdate {
+    pub fn txid(&self) -> &str {
+        match self {
+            Self::New { txid, .. } => txid,
+            Self::Change(change) => &change.txid,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Event {
+    SystemStatus(SystemStatus),
+    SubscriptionStatus(SubscriptionStatus),
+    Heartbeat,
+    Pong {
+        reqid: Option<u64>,
+    },
+    Ticker {
+        pair: String,
+        ticker: Ticker,
+    },
+    // candle.time is the beginning of the interval, end is when the interval closes
+    Ohlc {
+        pair: String,
+        interval: u64,
+        candle: Candle,
+        end: DateTime<Utc>,
+    },
+    Trades {
+        pair: String,
+        trades: Vec<PublicTrade>,
+    },
+    Spread {
+        pair: String,
+        spread: Spread,
+    },
+    BookSnapshot(BookSnapshot),
+    BookUpdate(BookUpdate),
+    // Fills of the account's orders, keyed by trade id
+    OwnTrades {
+        trades: Vec<(String, Trade)>,
+        sequence: u64,
+    },
+    OpenOrders {
+        orders: Vec<OrderUpdate>,
+        sequence: u64,
+    },
+    // The connection was reestablished and the subscriptions were renewed
+    Reconnected,
+    // Any message the client doesn't know about
+    Other(Value),
+}
+
+impl Event {
+    // Messages of the private channels are numbered, starting from 1 for every subscription
+    pub fn sequence(&self) -> Option<(&'static str, u64)> {
+        match self {
+            Self::OwnTrades { sequence, .. } => Some(("ownTrades", *sequence)),
+            Self::OpenOrders { sequence, .. } => Some(("openOrders", *sequence)),
+            _ => None,
+        }
+    }
+}
+
+fn invalid(value: &Value) -> Errors {
+    Errors::Parse(format!("Invalid WebSocket message: {}", value))
+}
+
+// Decodes from a borrowed value, as the REST models borrow their strings while deserializing
+fn decode<'a, T: Deserialize<'a>>(value: &'a Value) -> Result<T, Errors> {
+    T::deserialize(value).map_err(|error| Errors::Parse(format!("{} in WebSocket message: {}", error, value)))
+}
+
+// The WebSocket feeds send numbers either as strings or as numbers
+fn to_f64(value: &Value) -> Result<f64, Errors> {
+    match value {
+        Value::String(s) => f64::from_str(s).map_err(|_| invalid(value)),
+        Value::Number(number) => number.as_f64().ok_or_else(|| invalid(value)),
+        _ => Err(invalid(value)),
+    }
+}
+
+fn to_f64_vec(value: &Value) -> Result<Vec<f64>, Errors> {
+    value
+        .as_array()
+        .ok_or_else(|| invalid(value))?
+        .iter()
+        .map(to_f64)
+        .collect()
+}
+
+fn to_datetime(value: &Value) -> Result<DateTime<Utc>, Errors> {
+    timestamp_to_datetime(to_f64(value)?).ok_or_else(|| invalid(value))
+}
+
+fn parse_ticker(payload: &Value) -> Result<Ticker, Errors> {
+    let field = |name: &str| payload.get(name).ok_or_else(|| invalid(payload));
+    let trades = field("t")?
+        .as_array()
+        .ok_or_else(|| invalid(payload))?
+        .iter()
+        .map(|count| to_f64(count).map(|count| count as u64))
+        .collect::<Result<Vec<u64>, Errors>>()?;
+    // Unlike the REST ticker, the opening price is sent for both today and the last 24 hours
+    let open = match field("o")? {
+        Value::Array(open) => open.first().ok_or_else(|| invalid(payload)).and_then(to_f64)?,
+        open => to_f64(open)?,
+    };
+
+    Ok(Ticker {
+        a: to_f64_vec(field("a")?)?,
+        b: to_f64_vec(field("b")?)?,
+        c: to_f64_vec(field("c")?)?,
+        v: to_f64_vec(field("v")?)?,
+        p: to_f64_vec(field("p")?)?,
+        t: trades,
+        l: to_f64_vec(field("l")?)?,
+        h: to_f64_vec(field("h")?)?,
+        o: open,
+    })
+}
+
+// (<time>, <etime>, <open>, <high>, <low>, <close>, <vwap>, <volume>, <count>)
+fn parse_ohlc(payload: &Value, interval: u64) -> Result<(Candle, DateTime<Utc>), Errors> {
+    let values = payload.as_array().filter(|values| values.len() >= 9);
+    let values = values.ok_or_else(|| invalid(payload))?;
+    let end = to_datetime(&values[1])?;
+
+    let candle = Candle {
+        time: end - ChronoDuration::minutes(interval as i64),
+        open: to_f64(&values[2])?,
+        high: to_f64(&values[3])?,
+        low: to_f64(&values[4])?,
+        close: to_f64(&values[5])?,
+        vwap: to_f64(&values[6])?,
+        volume: to_f64(&values[7])?,
+        count: to_f64(&values[8])? as u64,
+    };
+
+    Ok((candle, end))
+}
+
+// (<bid>, <ask>, <timestamp>, <bid volume>, <ask volume>)
+fn parse_spread(payload: &Value) -> Result<Spread, Errors> {
+    let values = payload.as_array().filter(|values| values.len() >= 5);
+    let values = values.ok_or_else(|| invalid(payload))?;
+
+    Ok(Spread {
+        bid: to_f64(&values[0])?,
+        ask: to_f64(&values[1])?,
+        time: to_datetime(&values[2])?,
+        bid_volume: to_f64(&values[3])?,
+        ask_volume: to_f64(&values[4])?,
+    })
+}
+
+// Each entry is an array of (<price>, <volume>, <timestamp>) with an optional "r" for republished entries
+fn parse_book_entries(entries: Option<&Value>) -> Result<Vec<BookEntry>, Errors> {
+    let entries = match entries {
+        Some(entries) => entries.as_array().ok_or_else(|| invalid(entries))?,
+        None => return Ok(Vec::new()),
+    };
+
+    entries
+        .iter()
+        .map(|entry| {
+            let values = entry.as_array().filter(|values| values.len() >= 3);
+            let values = values.ok_or_else(|| invalid(entry))?;
+            let raw_price = values[0].as_str().ok_or_else(|| invalid(entry))?;
+            let raw_volume = values[1].as_str().ok_or_else(|| invalid(entry))?;
+
+            Ok(BookEntry {
+                price: to_f64(&values[0])?,
+                volume: to_f64(&values[1])?,
+                time: to_datetime(&values[2])?,
+                republish: values.get(3).and_then(Value::as_str) == Some("r"),
+                raw_price: raw_price.to_string(),
+                raw_volume: raw_volume.to_string(),
+            })
+        })
+        .collect()
+}
+
+fn parse_book(payloads: &[Value], pair: &str, depth: u64) -> Result<Event, Errors> {
+    let snapshot = payloads
+        .first()
+        .filter(|payload| payload.get("as").is_some() || payload.get("bs").is_some());
+    if let Some(snapshot) = snapshot {
+        return Ok(Event::BookSnapshot(BookSnapshot {
+            pair: pair.to_string(),
+            depth,
+            asks: parse_book_entries(snapshot.get("as"))?,
+            bids: parse_book_entries(snapshot.get("bs"))?,
+        }));
+    }
+
+    // Updates of both sides are sent as two separate objects, with the checksum in the last one
+    let mut update = BookUpdate {
+        pair: pair.to_string(),
+        depth,
+        asks: Vec::new(),
+        bids: Vec::new(),
+        checksum: None,
+    };
+    for payload in payloads {
+        update.asks.extend(parse_book_entries(payload.get("a"))?);
+        update.bids.extend(parse_book_entries(payload.get("b"))?);
+        if let Some(checksum) = payload.get("c") {
+            let checksum = checksum.as_str().and_then(|checksum| u32::from_str(checksum).ok());
+            update.checksum = Some(checksum.ok_or_else(|| invalid(payload))?);
+        }
+    }
+
+    Ok(Event::BookUpdate(update))
+}
+
+// The payloads of the private channels are arrays of objects with a single entry each, keyed by id
+fn parse_private_entries(payload: &Value) -> Result<Vec<(String, &Value)>, Errors> {
+    let entries = payload.as_array().ok_or_else(|| invalid(payload))?;
+
+    entries
+        .iter()
+        .flat_map(|entry| match entry.as_object() {
+            Some(entry) => entry.iter().map(|(id, value)| Ok((id.clone(), value))).collect(),
+            None => vec![Err(invalid(entry))],
+        })
+        .collect()
+}
+
+fn parse_order_update(txid: String, value: &Value) -> Result<OrderUpdate, Errors> {
+    if value.get("descr").is_some() {
+        let order = decode(value)?;
+        return Ok(OrderUpdate::New { txid, order });
+    }
+
+    let number = |name: &str| value.get(name).map(to_f64).transpose();
+    let reason = value.get("cancel_reason").or_else(|| value.get("reason"));
+
+    Ok(OrderUpdate::Change(OrderChange {
+        txid,
+        status: value.get("status").and_then(Value::as_str).map(OrderStatus::from),
+        vol_exec: number("vol_exec")?,
+        cost: number("cost")?,
+        fee: number("fee")?,
+        price: number("avg_price")?,
+        stopprice: number("stopprice")?,
+        limitprice: number("limitprice")?,
+        reason: reason.and_then(Value::as_str).map(|reason| reason.to_string()),
+    }))
+}
+
+// Private channel messages are arrays of (<payload>, <channel name>, {"sequence": <sequence>})
+fn parse_private_message(message: &[Value]) -> Result<Option<Event>, Errors> {
+    let sequence = message.get(2).and_then(|sequence| sequence.get("sequence"));
+    let (sequence, channel_name) = match (sequence.and_then(Value::as_u64), message[1].as_str()) {
+        (Some(sequence), Some(channel_name)) => (sequence, channel_name),
+        _ => return Ok(None),
+    };
+    let entries = parse_private_entries(&message[0])?;
+
+    let event = match channel_name {
+        "ownTrades" => Event::OwnTrades {
+            trades: entries
+                .into_iter()
+                .map(|(id, trade)| Ok((id, decode(trade)?)))
+                .collect::<Result<Vec<(String, Trade)>, Errors>>()?,
+            sequence,
+        },
+        "openOrders" => Event::OpenOrders {
+            orders: entries
+                .into_iter()
+                .map(|(txid, order)| parse_order_update(txid, order))
+                .collect::<Result<Vec<OrderUpdate>, Errors>>()?,
+            sequence,
+        },
+        _ => return Ok(None),
+    };
+
+    Ok(Some(event))
+}
+
+// Public channel messages are arrays of (<channel id>, <payload>..., <channel name>, <pair>)
+fn parse_channel_message(message: &[Value]) -> Result<Option<Event>, Errors> {
+    if message.len() == 3 {
+        return parse_private_message(message);
+    }
+    if message.len() < 4 {
+        return Ok(None);
+    }
+
+    let pair = message[message.len() - 1].as_str();
+    let channel_name = message[message.len() - 2].as_str();
+    let (pair, channel_name) = match (pair, channel_name) {
+        (Some(pair), Some(channel_name)) => (pair.to_string(), channel_name),
+        _ => return Ok(None),
+    };
+    let payloads = &message[1..message.len() - 2];
+
+    // Channels with an argument are named like ohlc-5 and book-10
+    let mut name = channel_name.splitn(2, '-');
+    let (name, argument) = (name.next().unwrap_or(""), name.next());
+    let argument = argument.and_then(|argument| u64::from_str(argument).ok());
+
+    let event = match (name, argument) {
+        ("ticker", _) => Event::Ticker {
+            pair,
+            ticker: parse_ticker(&payloads[0])?,
+        },
+        ("ohlc", Some(interval)) => {
+            let (candle, end) = parse_ohlc(&payloads[0], interval)?;
+            Event::Ohlc {
+                pair,
+                interval,
+                candle,
+                end,
+            }
+        }
+        ("trade", _) => Event::Trades {
+            pair,
+            trades: decode(&payloads[0])?,
+        },
+        ("spread", _) => Event::Spread {
+            pair,
+            spread: parse_spread(&payloads[0])?,
+        },
+        ("book", Some(depth)) => parse_book(payloads, &pair, depth)?,
+        _ => return Ok(None),
+    };
+
+    Ok(Some(event))
+}
+
+// Parses a message of the WebSocket API, such as one recorded from the feeds
+pub fn parse_message(text: &str) -> Result<Event, Errors> {
+    let value: Value = serde_json::from_str(text).map_err(|error| Errors::Parse(error.to_string()))?;
+
+    parse_value(value)
+}
+
+fn parse_value(value: Value) -> Result<Event, Errors> {
+    match value {
+        Value::Object(ref object) => {
+            let event = object.get("event").and_then(Value::as_str).unwrap_or("");
+            match event {
+                "systemStatus" => decode(&value).map(Event::SystemStatus),
+                "subscriptionStatus" => decode(&value).map(Event::SubscriptionStatus),
+                "heartbeat" => Ok(Event::Heartbeat),
+                "pong" => Ok(Event::Pong {
+                    reqid: object.get("reqid").and_then(Value::as_u64),
+                }),
+                _ => Ok(Event::Other(value)),
+            }
+        }
+        Value::Array(ref message) => match parse_channel_message(message)? {
+            Some(event) => Ok(event),
+            None => Ok(Event::Other(value)),
+        },
+        _ => Ok(Event::Other(value)),
+    }
+}
+
+// The order description of addOrderStatus includes the conditional close, as in
+//     buy 1.00000000 XBTUSD @ limit 8000.0, close position @ stop loss 7500.0
+fn split_description(description: &str) -> (String, Option<String>) {
+    match description.find(", close ") {
+        Some(index) => (
+            description[..index].to_string(),
+            Some(description[index + 2..].to_string()),
+        ),
+        None => (description.to_string(), None),
+    }
+}
+
+type Responder = oneshot::Sender<Result<Value, Errors>>;
+
+enum Command {
+    Subscribe(Subscription),
+    Unsubscribe(Subscription),
+    Resubscribe(Subscription),
+    Send(Value),
+    // Authenticated request, answered by the message with the same reqid
+    Request {
+        reqid: u64,
+        message: Value,
+        responder: Responder,
+    },
+    SetToken(String),
+    Close,
+}
+
+// Responses to requests go to the callers waiting for them instead of the events. Returns the message if it
+// isn't a response
+fn respond(pending: &mut HashMap<u64, Responder>, message: Value) -> Option<Value> {
+    let reqid = message.get("reqid").and_then(Value::as_u64);
+
+    match reqid.and_then(|reqid| pending.remove(&reqid)) {
+        Some(responder) => {
+            let _ = responder.send(Ok(message));
+            None
+        }
+        None => Some(message),
+    }
+}
+
+// Forgets the subscriptions Kraken rejected, so they aren't renewed on every reconnection. Only the rejected
+// pair is dropped from subscriptions of several pairs
+fn reject_subscription(
+    subscriptions: &mut Vec<Subscription>,
+    requested: &mut HashMap<u64, Subscription>,
+    status: &SubscriptionStatus,
+) {
+    if status.status != "error" {
+        return;
+    }
+    let rejected = match status.reqid.and_then(|reqid| requested.get_mut(&reqid)) {
+        Some(rejected) => rejected,
+        None => return,
+    };
+    let index = subscriptions.iter().position(|subscription| subscription == rejected);
+
+    match &status.pair {
+        Some(pair) if rejected.pairs.len() > 1 => {
+            rejected.pairs.retain(|existing| existing != pair);
+            if let Some(index) = index {
+                subscriptions[index] = rejected.clone();
+            }
+        }
+        _ => {
+            if let Some(index) = index {
+                subscriptions.remove(index);
+            }
+        }
+    }
+}
+
+// Drops the unsubscribed pairs from the stored subscriptions of the channel, and the subscriptions left without
+// pairs. Unsubscribing without pairs drops the whole channel
+fn remove_subscription(subscriptions: &mut Vec<Subscription>, removed: &Subscription) {
+    for subscription in subscriptions.iter_mut() {
+        if subscription.channel == removed.channel {
+            subscription.pairs.retain(|pair| !removed.pairs.contains(pair));
+        }
+    }
+    subscriptions.retain(|subscription| {
+        subscription.channel != removed.channel || !(subscription.pairs.is_empty() || removed.pairs.is_empty())
+    });
+}
+
+// Returns the gap if messages of a private channel were missed
+fn check_sequence(sequences: &mut HashMap<&'static str, u64>, event: &Event) -> Option<Errors> {
+    let (channel, sequence) = event.sequence()?;
+    let expected = sequences.insert(channel, sequence).map(|last| last + 1);
+
+    match expected {
+        Some(expected) if expected != sequence => Some(Errors::SequenceGap {
+            channel: channel.to_string(),
+            expected,
+            received: sequence,
+        }),
+        _ => None,
+    }
+}
+
+// Stream of the events received by a Client. It ends once the client is closed, or once the client and all of
+// its clones are dropped
+pub struct Events {
+    receiver: mpsc::Receiver<Result<Event, Errors>>,
+}
+
+impl Stream for Events {
+    type Item = Result<Event, Errors>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+// Handle to a WebSocket connection running in the background. The connection is reestablished, and the
+// subscriptions renewed, whenever it's lost. Clones control the same connection, which stays open while any of
+// them is alive. close ends it for all of them
+#[derive(Clone)]
+pub struct Client {
+    commands: mpsc::UnboundedSender<Command>,
+    reqid: Arc<AtomicU64>,
+    request_timeout: Duration,
+}
+
+impl Client {
+    // Has to be called from within a tokio runtime
+    pub fn new(config: Config) -> (Self, Events) {
+        let (commands, commands_receiver) = mpsc::unbounded_channel();
+        let (events_sender, receiver) = mpsc::channel(config.event_capacity);
+        let reqid = Arc::new(AtomicU64::new(1));
+        let request_timeout = config.request_timeout;
+
+        tokio::spawn(run(config, commands_receiver, events_sender, reqid.clone()));
+
+        let client = Self {
+            commands,
+            reqid,
+            request_timeout,
+        };
+        (client, Events { receiver })
+    }
+
+    pub fn subscribe(&self, channel: Channel, pairs: &[&str]) -> Result<(), Errors> {
+        let subscription = Subscription {
+            channel,
+            pairs: pairs.iter().map(|pair| pair.to_string()).collect(),
+        };
+
+        self.send_command(Command::Subscribe(subscription))
+    }
+
+    pub fn unsubscribe(&self, channel: Channel, pairs: &[&str]) -> Result<(), Errors> {
+        let subscription = Subscription {
+            channel,
+            pairs: pairs.iter().map(|pair| pair.to_string()).collect(),
+        };
+
+        self.send_command(Command::Unsubscribe(subscription))
+    }
+
+    // Unsubscribes and subscribes again, to get a fresh snapshot of the channel
+    pub fn resubscribe(&self, channel: Channel, pairs: &[&str]) -> Result<(), Errors> {
+        let subscription = Subscription {
+            channel,
+            pairs: pairs.iter().map(|pair| pair.to_string()).collect(),
+        };
+
+        self.send_command(Command::Resubscribe(subscription))
+    }
+
+    // Returns the reqid the pong will be sent with
+    pub fn ping(&self) -> Result<u64, Errors> {
+        let reqid = self.next_reqid();
+        self.send_command(Command::Send(json!({ "event": "ping", "reqid": reqid })))?;
+
+        Ok(reqid)
+    }
+
+    // The token is used for the private subscriptions made from now on, including the renewed ones after a
+    // reconnection. Tokens expire if they aren't used within 15 minutes, so a fresh one is needed to reconnect
+    pub fn set_token(&self, token: &str) -> Result<(), Errors> {
+        self.send_command(Command::SetToken(token.to_string()))
+    }
+
+    // Places an order through the WebSocket, the same way as Kraken::add_order. Needs a token. The pair has
+    // to be the WebSocket name, such as XBT/USD
+    pub async fn add_order(&self, order: &NewOrder) -> Result<AddedOrder, Errors> {
+        let mut message = json!({ "event": "addOrder" });
+        for (key, value) in order.params()? {
+            message[key] = json!(value);
+        }
+
+        let response = self.request(message).await?;
+        let description = response.get("descr").and_then(Value::as_str);
+        let (description, close) = split_description(description.ok_or_else(|| invalid(&response))?);
+        // Only validated orders are answered without a txid
+        let txid = match response.get("txid").and_then(Value::as_str) {
+            Some(txid) => vec![txid.to_string()],
+            None if order.validate => Vec::new(),
+            None => return Err(invalid(&response)),
+        };
+
+        Ok(AddedOrder {
+            description,
+            close,
+            txid,
+        })
+    }
+
+    // Unlike Kraken::cancel_order, the response doesn't tell the amount of canceled orders. Kraken answers
+    // with an error if the order couldn't be canceled
+    pub async fn cancel_order(&self, txid: &str) -> Result<(), Errors> {
+        self.request(json!({ "event": "cancelOrder", "txid": [txid] })).await?;
+
+        Ok(())
+    }
+
+    // Returns the amount of canceled orders, as Kraken::cancel_all does
+    pub async fn cancel_all(&self) -> Result<u64, Errors> {
+        let response = self.request(json!({ "event": "cancelAll" })).await?;
+
+        response
+            .get("count")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| invalid(&response))
+    }
+
+    pub fn close(&self) -> Result<(), Errors> {
+        self.send_command(Command::Close)
+    }
+
+    fn next_reqid(&self) -> u64 {
+        self.reqid.fetch_add(1, Ordering::Relaxed)
+    }
+
+    async fn request(&self, mut message: Value) -> Result<Value, Errors> {
+        let reqid = self.next_reqid();
+        message["reqid"] = json!(reqid);
+
+        let (responder, response) = oneshot::channel();
+        self.send_command(Command::Request {
+            reqid,
+            message,
+            responder,
+        })?;
+        let response = match timeout(self.request_timeout, response).await {
+            Ok(response) => response.map_err(|_| {
+                Errors::Disconnected("The connection was lost before the response arrived".to_string())
+            })??,
+            Err(_) => {
+                return Err(Errors::Timeout(format!(
+                    "No response to request {} in {:?}",
+                    reqid, self.request_timeout
+                )))
+            }
+        };
+
+        match response.get("status").and_then(Value::as_str) {
+            Some("ok") => Ok(response),
+            _ => {
+                let error = response.get("errorMessage").and_then(Value::as_str);
+                Err(Errors::Kraken(error.unwrap_or("Unknown error").to_string()))
+            }
+        }
+    }
+
+    fn send_command(&self, command: Command) -> Result<(), Errors> {
+        self.commands
+            .send(command)
+            .map_err(|_| Errors::Disconnected("The connection was closed".to_string()))
+    }
+}
+
+async fn run(
+    config: Config,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+    mut events: mpsc::Sender<Result<Event, Errors>>,
+    reqid: Arc<AtomicU64>,
+) {
+    let mut subscriptions: Vec<Subscription> = Vec::new();
+    let mut token = config.token.clone();
+    let mut reconnecting = false;
+
+    loop {
+        if reconnecting {
+            delay_for(config.reconnect_delay).await;
+        }
+
+        let socket = match connect_async(config.url.as_str()).await {
+            Ok((socket, _)) => socket,
+            Err(error) => {
+                if events.send(Err(error.into())).await.is_err() {
+                    return;
+                }
+                reconnecting = true;
+                continue;
+            }
+        };
+        let (mut sink, mut stream) = socket.split();
+        // Subscriptions by the reqid of their subscribe message, to find the ones Kraken rejects
+        let mut requested = HashMap::new();
+
+        let mut resubscribed = true;
+        for subscription in &subscriptions {
+            let reqid = reqid.fetch_add(1, Ordering::Relaxed);
+            let message = subscription.message("subscribe", reqid, token.as_deref());
+            requested.insert(reqid, subscription.clone());
+            if sink.send(Message::Text(message.to_string())).await.is_err() {
+                resubscribed = false;
+                break;
+            }
+        }
+        if !resubscribed {
+            reconnecting = true;
+            continue;
+        }
+        if reconnecting && events.send(Ok(Event::Reconnected)).await.is_err() {
+            return;
+        }
+
+        let mut ping = interval_at(Instant::now() + config.ping_interval, config.ping_interval);
+        let mut last_message = Instant::now();
+        let mut sequences = HashMap::new();
+        // The responders of requests without a response are dropped along with the connection
+        let mut pending = HashMap::new();
+
+        let reason = loop {
+            tokio::select! {
+                message = stream.next() => {
+                    last_message = Instant::now();
+                    let event = match message {
+                        Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                            Ok(message) => match respond(&mut pending, message) {
+                                Some(message) => parse_value(message),
+                                None => continue,
+                            },
+                            Err(error) => Err(Errors::Parse(error.to_string())),
+                        },
+                        Some(Ok(Message::Ping(data))) => {
+                            if sink.send(Message::Pong(data)).await.is_err() {
+                                break "Can't send pong".to_string();
+                            }
+                            continue;
+                        }
+                        Some(Ok(Message::Close(_))) | None => break "Connection closed".to_string(),
+                        Some(Ok(_)) => continue,
+                        // Errors of the underlying connection are fatal, the connection has to be reestablished
+                        Some(Err(error)) => break error.to_string(),
+                    };
+                    if let Ok(Event::SubscriptionStatus(status)) = &event {
+                        reject_subscription(&mut subscriptions, &mut requested, status);
+                    }
+                    let gap = event.as_ref().ok().and_then(|event| check_sequence(&mut sequences, event));
+                    if let Some(gap) = gap {
+                        if events.send(Err(gap)).await.is_err() {
+                            return;
+                        }
+                    }
+                    if events.send(event).await.is_err() {
+                        return;
+                    }
+                    // Waiting for the consumer doesn't count towards the heartbeat timeout
+                    last_message = Instant::now();
+                }
+                command = commands.recv() => {
+                    let message = match command {
+                        Some(Command::Subscribe(subscription)) => {
+                            if subscription.channel.is_private() && token.is_none() {
+                                let error = Errors::InvalidParams("A token is needed for private channels".to_string());
+                                if events.send(Err(error)).await.is_err() {
+                                    return;
+                                }
+                                continue;
+                            }
+                            let reqid = reqid.fetch_add(1, Ordering::Relaxed);
+                            let message = subscription.message("subscribe", reqid, token.as_deref());
+                            sequences.remove(subscription.channel.name());
+                            requested.insert(reqid, subscription.clone());
+                            subscriptions.push(subscription);
+                            message
+                        }
+                        Some(Command::Unsubscribe(subscription)) => {
+                            let reqid = reqid.fetch_add(1, Ordering::Relaxed);
+                            let message = subscription.message("unsubscribe", reqid, token.as_deref());
+                            remove_subscription(&mut subscriptions, &subscription);
+                            message
+                        }
+                        Some(Command::Resubscribe(subscription)) => {
+                            let unsubscribe_reqid = reqid.fetch_add(1, Ordering::Relaxed);
+                            let message = subscription.message("unsubscribe", unsubscribe_reqid, token.as_deref());
+                            if sink.send(Message::Text(message.to_string())).await.is_err() {
+                                break "Can't send message".to_string();
+                            }
+                            sequences.remove(subscription.channel.name());
+                            let reqid = reqid.fetch_add(1, Ordering::Relaxed);
+                            let message = subscription.message("subscribe", reqid, token.as_deref());
+                            requested.insert(reqid, subscription);
+                            message
+                        }
+                        Some(Command::Send(message)) => message,
+                        Some(Command::Request {
+                            reqid,
+                            mut message,
+                            responder,
+                        }) => match &token {
+                            Some(token) => {
+                                message["token"] = json!(token);
+                                // Callers stop waiting once their request times out
+                                pending.retain(|_, responder: &mut Responder| !responder.is_closed());
+                                pending.insert(reqid, responder);
+                                message
+                            }
+                            None => {
+                                let error = Errors::InvalidParams("A token is needed for trading".to_string());
+                                let _ = responder.send(Err(error));
+                                continue;
+                            }
+                        },
+                        Some(Command::SetToken(new_token)) => {
+                            token = Some(new_token);
+                            continue;
+                        }
+                        Some(Command::Close) | None => {
+                            let _ = sink.send(Message::Close(None)).await;
+                            return;
+                        }
+                    };
+                    if sink.send(Message::Text(message.to_string())).await.is_err() {
+                        break "Can't send message".to_string();
+                    }
+                }
+                _ = ping.tick() => {
+                    let message = json!({ "event": "ping", "reqid": reqid.fetch_add(1, Ordering::Relaxed) });
+                    if sink.send(Message::Text(message.to_string())).await.is_err() {
+                        break "Can't send ping".to_string();
+                    }
+                }
+                _ = delay_until(last_message + config.heartbeat_timeout) => {
+                    break format!("No message received in {:?}", config.heartbeat_timeout);
+                }
+            }
+        };
+
+        if events.send(Err(Errors::Disconnected(reason))).await.is_err() {
+            return;
+        }
+        reconnecting = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Side;
+
+    #[test]
+    fn test_parse_channel_messages() {
+        let event = parse_message(
+            r#"[340, {
+                "a": ["5525.40000", 1, "1.000"], "b": ["5525.10000", 1, "1.000"], "c": ["5525.10000", "0.00398963"],
+                "v": ["2634.11501494", "3591.17907851"], "p": ["5631.44067", "5653.78939"], "t": [11493, 16267],
+                "l": ["5505.00000", "5505.00000"], "h": ["5783.00000", "5783.00000"], "o": ["5760.70000", "5763.40000"]
+            }, "ticker", "XBT/USD"]"#,
+        )
+        .unwrap();
+        match event {
+            Event::Ticker { pair, ticker } => {
+                assert_eq!(pair, "XBT/USD");
+                assert_eq!(ticker.a, vec![5525.4, 1.0, 1.0]);
+                assert_eq!(ticker.t, vec![11493, 16267]);
+                assert_eq!(ticker.o, 5760.7);
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+
+        let event = parse_message(
+            r#"[42, ["1542057314.748456", "1542057360.435743", "3586.70000", "3586.70000", "3586.60000",
+                "3586.60000", "3586.68894", "0.03373000", 2], "ohlc-5", "XBT/USD"]"#,
+        )
+        .unwrap();
+        match event {
+            Event::Ohlc {
+                interval, candle, end, ..
+            } => {
+                assert_eq!(interval, 5);
+                assert_eq!(candle.close, 3586.6);
+                assert_eq!(candle.count, 2);
+                assert_eq!((end - candle.time).num_minutes(), 5);
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+
+        let event = parse_message(
+            r#"[0, [["5541.20000", "0.15850568", "1534614057.321597", "s", "l", ""]], "trade", "XBT/USD"]"#,
+        )
+        .unwrap();
+        match event {
+            Event::Trades { trades, .. } => assert_eq!(trades[0].price, 5541.2),
+            event => panic!("Unexpected event {:?}", event),
+        }
+
+        let event = parse_message(
+            r#"[0, ["5698.40000", "5700.00000", "1542057299.545897", "1.01234567", "0.98765432"], "spread", "XBT/USD"]"#,
+        )
+        .unwrap();
+        match event {
+            Event::Spread { spread, .. } => assert_eq!(spread.ask, 5700.0),
+            event => panic!("Unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn test_parse_book_messages() {
+        let event = parse_message(
+            r#"[0, {
+                "as": [["5541.30000", "2.50700000", "1534614248.123678"], ["5541.80000", "0.33000000", "1534614098.345543"]],
+                "bs": [["5541.20000", "1.52900000", "1534614248.765567"]]
+            }, "book-10", "XBT/USD"]"#,
+        )
+        .unwrap();
+        match event {
+            Event::BookSnapshot(snapshot) => {
+                assert_eq!(snapshot.depth, 10);
+                assert_eq!(snapshot.asks.len(), 2);
+                assert_eq!(snapshot.asks[0].raw_price, "5541.30000");
+                let order_book = snapshot.to_order_book();
+                assert_eq!(order_book.spread().unwrap(), 5541.3 - 5541.2);
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+
+        let event = parse_message(
+            r#"[1234,
+                {"a": [["5541.30000", "2.50700000", "1534614248.456738"], ["5542.50000", "0.40100000", "1534614248.456738", "r"]]},
+                {"b": [["5541.30000", "0.00000000", "1534614335.345903"]], "c": "974942666"},
+                "book-10", "XBT/USD"]"#,
+        )
+        .unwrap();
+        match event {
+            Event::BookUpdate(update) => {
+                assert_eq!(update.asks.len(), 2);
+                assert!(update.asks[1].republish);
+                assert_eq!(update.bids[0].volume, 0.0);
+                assert_eq!(update.checksum, Some(974942666));
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn test_parse_event_messages() {
+        let event = parse_message(
+            r#"{"connectionID": 8628615390848610000, "event": "systemStatus", "status": "online", "version": "1.0.0"}"#,
+        )
+        .unwrap();
+        assert!(matches!(event, Event::SystemStatus(status) if status.status == "online"));
+
+        let event = parse_message(
+            r#"{"errorMessage": "Currency pair not supported", "event": "subscriptionStatus", "pair": "XBT/EUX",
+                "status": "error", "subscription": {"name": "ticker"}, "reqid": 42}"#,
+        )
+        .unwrap();
+        match event {
+            Event::SubscriptionStatus(status) => {
+                assert_eq!(status.status, "error");
+                assert_eq!(status.reqid, Some(42));
+                assert_eq!(status.error_message.as_deref(), Some("Currency pair not supported"));
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+
+        assert!(matches!(
+            parse_message(r#"{"event": "heartbeat"}"#).unwrap(),
+            Event::Heartbeat
+        ));
+        assert!(matches!(
+            parse_message(r#"{"event": "pong", "reqid": 7}"#).unwrap(),
+            Event::Pong { reqid: Some(7) }
+        ));
+        assert!(matches!(
+            parse_message(r#"[1, {}, "new", "XBT/USD"]"#).unwrap(),
+            Event::Other(_)
+        ));
+    }
+
+    #[test]
+    fn test_parse_private_messages() {
+        let event = parse_message(
+            r#"[[{"TDLH43-DVQXD-2KHVYY": {
+                "cost": "1000000.00000", "fee": "1600.00000", "margin": "0.00000", "ordertxid": "OGTT3Y-C6I3P-XRI6HX",
+                "ordertype": "limit", "pair": "XBT/EUR", "postxid": "TKH2SE-M7IF5-CFI7LT", "price": "100000.00000",
+                "time": "1560516023.070651", "type": "sell", "vol": "10.00000000"
+            }}], "ownTrades", {"sequence": 2}]"#,
+        )
+        .unwrap();
+        match event {
+            Event::OwnTrades { trades, sequence } => {
+                assert_eq!(sequence, 2);
+                assert_eq!(trades[0].0, "TDLH43-DVQXD-2KHVYY");
+                assert_eq!(trades[0].1.ordertxid, "OGTT3Y-C6I3P-XRI6HX");
+                assert_eq!(trades[0].1.kind, Side::Sell);
+                assert_eq!(trades[0].1.time.timestamp(), 1560516023);
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+
+        let event = parse_message(
+            r#"[[{"OGTT3Y-C6I3P-XRI6HX": {
+                "avg_price": "0.00000", "cost": "0.00000", "descr": {
+                    "close": "", "leverage": "none", "order": "sell 10.00000000 XBT/EUR @ limit 34.50000",
+                    "ordertype": "limit", "pair": "XBT/EUR", "price": "34.50000", "price2": "0.00000",
+                    "position": "", "type": "sell"
+                },
+                "expiretm": "0.000000", "fee": "0.00000", "limitprice": "0.00000", "misc": "", "oflags": "fcib",
+                "opentm": "1560516023.070651", "refid": null, "starttm": "0.000000", "status": "open",
+                "stopprice": "0.00000", "userref": 0, "vol": "10.00000000", "vol_exec": "0.00000000"
+            }}, {"OGTT3Y-C6I3P-XRI6HX": {
+                "status": "closed", "vol_exec": "10.00000000", "cost": "345.00000", "fee": "0.55200",
+                "avg_price": "34.50000"
+            }}], "openOrders", {"sequence": 3}]"#,
+        )
+        .unwrap();
+        let mut orders = match event {
+            Event::OpenOrders { orders, sequence } => {
+                assert_eq!(sequence, 3);
+                orders
+            }
+            event => panic!("Unexpected event {:?}", event),
+        };
+        let change = match orders.pop() {
+            Some(OrderUpdate::Change(change)) => change,
+            update => panic!("Unexpected update {:?}", update),
+        };
+        let mut order = match orders.pop() {
+            Some(OrderUpdate::New { txid, order }) => {
+                assert_eq!(txid, change.txid);
+                order
+            }
+            update => panic!("Unexpected update {:?}", update),
+        };
+        assert_eq!(order.status, OrderStatus::Open);
+        assert_eq!(order.expiretm, None);
+        assert_eq!(order.descr.price, 34.5);
+
+        change.apply(&mut order);
+        assert_eq!(order.status, OrderStatus::Closed);
+        assert_eq!(order.vol_exec, 10.0);
+        assert_eq!(order.price, 34.5);
+        assert_eq!(order.vol, 10.0);
+    }
+
+    #[test]
+    fn test_sequence_gaps() {
+        let event = |sequence| Event::OpenOrders {
+            orders: Vec::new(),
+            sequence,
+        };
+        let mut sequences = HashMap::new();
+
+        assert!(check_sequence(&mut sequences, &event(1)).is_none());
+        assert!(check_sequence(&mut sequences, &event(2)).is_none());
+        assert!(check_sequence(&mut sequences, &Event::Heartbeat).is_none());
+        match check_sequence(&mut sequences, &event(5)) {
+            Some(Errors::SequenceGap {
+                channel,
+                expected,
+                received,
+            }) => {
+                assert_eq!(channel, "openOrders");
+                assert_eq!((expected, received), (3, 5));
+            }
+            gap => panic!("Unexpected gap {:?}", gap),
+        }
+        assert!(check_sequence(&mut sequences, &event(6)).is_none());
+    }
+
+    #[test]
+    fn test_reject_subscription() {
+        let subscription = |pairs: &[&str]| Subscription {
+            channel: Channel::Ticker,
+            pairs: pairs.iter().map(|pair| pair.to_string()).collect(),
+        };
+        let status = |status: &str, reqid, pair: &str| SubscriptionStatus {
+            channel_name: None,
+            pair: Some(pair.to_string()),
+            status: status.to_string(),
+            reqid: Some(reqid),
+            error_message: None,
+        };
+        let mut subscriptions = vec![subscription(&["XBT/USD", "XBT/EUX"]), subscription(&["ETH/EUX"])];
+        let mut requested: HashMap<u64, Subscription> = (1..).zip(subscriptions.iter().cloned()).collect();
+
+        reject_subscription(&mut subscriptions, &mut requested, &status("subscribed", 1, "XBT/USD"));
+        assert_eq!(subscriptions.len(), 2);
+
+        reject_subscription(&mut subscriptions, &mut requested, &status("error", 1, "XBT/EUX"));
+        reject_subscription(&mut subscriptions, &mut requested, &status("error", 2, "ETH/EUX"));
+        reject_subscription(&mut subscriptions, &mut requested, &status("error", 3, "XBT/USD"));
+        assert_eq!(subscriptions, vec![subscription(&["XBT/USD"])]);
+    }
+
+    #[test]
+    fn test_private_subscription_message() {
+        let subscription = Subscription {
+            channel: Channel::OpenOrders,
+            pairs: Vec::new(),
+        };
+        let message = subscription.message("subscribe", 4, Some("token"));
+        assert_eq!(
+            message,
+            json!({ "event": "subscribe", "reqid": 4, "subscription": { "name": "openOrders", "token": "token" } })
+        );
+
+        let subscription = Subscription {
+            channel: Channel::Book { depth: 10 },
+            pairs: vec!["XBT/USD".to_string()],
+        };
+        let message = subscription.message("subscribe", 5, Some("token"));
+        assert_eq!(message["subscription"], json!({ "name": "book", "depth": 10 }));
+        assert_eq!(message["pair"], json!(["XBT/USD"]));
+    }
+}
@@ -0,0 +1,90 @@
+extern crate kraken_api;
+
+use kraken_api::{book::LocalOrderBook, ws::parse_message, Errors};
+
+// Synthetic messages in the format of the book-10 channel of XBT/EUR, with the checksums calculated for them
+const SNAPSHOT: &str = r#"[0, {
+    "as": [
+        ["0.05005", "0.00000500", "1582905487.684110"], ["0.05010", "0.00000500", "1582905486.187983"],
+        ["0.05015", "0.00000500", "1582905484.480241"], ["0.05020", "0.00000500", "1582905486.645658"],
+        ["0.05025", "0.00000500", "1582905486.859009"], ["0.05030", "0.00000500", "1582905488.601486"],
+        ["0.05035", "0.00000500", "1582905488.357312"], ["0.05040", "0.00000500", "1582905488.785484"],
+        ["0.05045", "0.00000500", "1582905485.302661"], ["0.05050", "0.00000500", "1582905486.157467"]
+    ],
+    "bs": [
+        ["0.05000", "0.00000500", "1582905487.439814"], ["0.04995", "0.00000500", "1582905485.119396"],
+        ["0.04990", "0.00000500", "1582905486.432052"], ["0.04980", "0.00000500", "1582905480.609351"],
+        ["0.04975", "0.00000500", "1582905476.793880"], ["0.04970", "0.00000500", "1582905486.767461"],
+        ["0.04965", "0.00000500", "1582905481.767528"], ["0.04960", "0.00000500", "1582905487.378907"],
+        ["0.04955", "0.00000500", "1582905483.626664"], ["0.04950", "0.00000500", "1582905488.509872"]
+    ]
+}, "book-10", "XBT/EUR"]"#;
+
+const UPDATES: [&str; 3] = [
+    r#"[0, {"a": [["0.05005", "0.00000400", "1582905489.001000"]], "c": "90024921"}, "book-10", "XBT/EUR"]"#,
+    r#"[0, {"b": [["0.05001", "0.00001000", "1582905489.002000"]], "c": "3061329453"}, "book-10", "XBT/EUR"]"#,
+    r#"[0, {"a": [["0.05005", "0.00000000", "1582905489.003000"],
+        ["0.05055", "0.00000200", "1582905489.003000", "r"]], "c": "2545376303"}, "book-10", "XBT/EUR"]"#,
+];
+
+fn apply(book: &mut LocalOrderBook, message: &str) -> Result<bool, Errors> {
+    book.apply(&parse_message(message).unwrap())
+}
+
+#[test]
+fn recorded_messages() {
+    let mut book = LocalOrderBook::new("XBT/EUR", 10);
+    assert!(!book.is_synced());
+
+    assert!(apply(&mut book, SNAPSHOT).unwrap());
+    assert!(book.is_synced());
+    assert_eq!(book.checksum(), 974947235);
+
+    for update in UPDATES.iter() {
+        assert!(apply(&mut book, update).unwrap());
+    }
+    assert_eq!(book.asks().len(), 10);
+    assert_eq!(book.asks()[0].price, 0.0501);
+    assert_eq!(book.asks()[9].price, 0.05055);
+    // The new best bid pushed the worst one out of the depth
+    assert_eq!(book.bids().len(), 10);
+    assert_eq!(book.bids()[0].price, 0.05001);
+    assert_eq!(book.bids()[9].price, 0.04955);
+
+    let order_book = book.to_order_book();
+    assert_eq!(order_book.best_ask().unwrap().price, 0.0501);
+    assert_eq!(order_book.best_bid().unwrap().price, 0.05001);
+
+    // Messages of other books are ignored
+    let other = SNAPSHOT.replace("XBT/EUR", "ETH/EUR");
+    assert!(!apply(&mut book, &other).unwrap());
+}
+
+#[test]
+fn checksum_mismatch() {
+    let mut book = LocalOrderBook::new("XBT/EUR", 10);
+    apply(&mut book, SNAPSHOT).unwrap();
+    apply(&mut book, UPDATES[0]).unwrap();
+
+    let update = UPDATES[1].replace("3061329453", "3061329454");
+    match apply(&mut book, &update) {
+        Err(Errors::ChecksumMismatch {
+            pair,
+            expected,
+            calculated,
+        }) => {
+            assert_eq!(pair, "XBT/EUR");
+            assert_eq!(expected, 3061329454);
+            assert_eq!(calculated, 3061329453);
+        }
+        result => panic!("Unexpected result {:?}", result),
+    }
+    assert!(!book.is_synced());
+    assert!(book.asks().is_empty());
+
+    // Updates are ignored until the next snapshot
+    assert!(!apply(&mut book, UPDATES[2]).unwrap());
+    assert!(apply(&mut book, SNAPSHOT).unwrap());
+    assert!(book.is_synced());
+    assert!(apply(&mut book, UPDATES[0]).unwrap());
+}
@@ -0,0 +1,167 @@
+extern crate kraken_api;
+
+use kraken_api::{
+    history::{Dataset, Download, Downloader, HistoryStore},
+    rate_limit::RateLimiter,
+    Credentials, Kraken, Urls,
+};
+use serde_json::{json, Value};
+use std::{
+    env,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+const CURSOR_1: &str = "1616663618600000000";
+const CURSOR_2: &str = "1616663620000000000";
+const CURSOR_3: &str = "1616663625000000000";
+
+// Path and query of the requests received by the mocked server
+type Requests = Arc<Mutex<Vec<String>>>;
+
+// Answers the public endpoints like Kraken would for a pair with a few trades. The first call is rejected
+// for going over the rate limit
+fn respond(request: &str, count: usize) -> Value {
+    let trade = |price: &str, time: f64, id: u64| json!([price, "0.1", time, "b", "l", "", id]);
+    let candle = |time: u64, price: &str| json!([time, price, price, price, price, price, "1.5", 3]);
+    let result = match request {
+        _ if count == 0 => return json!({"error": ["EAPI:Rate limit exceeded"]}),
+        "/0/public/Trades?pair=XBTUSD&since=0" => json!({
+            "XXBTZUSD": [trade("50000.0", 1616663617.5, 1), trade("50001.0", 1616663618.6, 2)],
+            "last": CURSOR_1
+        }),
+        "/0/public/Trades?pair=XBTUSD&since=1616663618600000000" => json!({
+            "XXBTZUSD": [trade("50002.0", 1616663620.0, 3)], "last": CURSOR_2
+        }),
+        "/0/public/Trades?pair=XBTUSD&since=1616663620000000000" => json!({
+            "XXBTZUSD": [trade("50003.0", 1616663625.0, 4)], "last": CURSOR_3
+        }),
+        "/0/public/Trades?pair=XBTUSD&since=1616663625000000000" => json!({"XXBTZUSD": [], "last": CURSOR_3}),
+        "/0/public/OHLC?pair=XBTUSD&interval=1" => json!({
+            "XXBTZUSD": [candle(60, "50000.0"), candle(120, "50001.0"), candle(180, "50002.0")], "last": 120
+        }),
+        "/0/public/OHLC?pair=XBTUSD&interval=1&since=120" => json!({
+            "XXBTZUSD": [candle(180, "50002.0")], "last": 120
+        }),
+        _ => return json!({"error": [format!("EGeneral:Unknown request {}", request)]}),
+    };
+
+    json!({"error": [], "result": result})
+}
+
+async fn serve(requests: Requests) -> String {
+    let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut data = vec![];
+            let mut buffer = [0; 1024];
+            while !data.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buffer).await.unwrap();
+                if read == 0 {
+                    break;
+                }
+                data.extend_from_slice(&buffer[..read]);
+            }
+
+            let head = String::from_utf8_lossy(&data).to_string();
+            let request = head.split_whitespace().nth(1).unwrap_or_default().to_string();
+            let count = {
+                let mut requests = requests.lock().unwrap();
+                requests.push(request.clone());
+                requests.len() - 1
+            };
+            let body = respond(&request, count).to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+
+    address
+}
+
+fn create_downloader<'a>(kraken: &'a Kraken, store: HistoryStore) -> Downloader<'a> {
+    let mut downloader = Downloader::new(kraken, store, RateLimiter::new(5.0, 100.0));
+    downloader.retry_delay = Duration::from_millis(10);
+
+    downloader
+}
+
+#[tokio::test]
+async fn download_and_resume() {
+    let requests = Requests::default();
+    let address = serve(requests.clone()).await;
+    let kraken = Kraken::new(
+        Credentials::new("key".to_string(), "secret".to_string()),
+        Urls::new(&format!("http://{}", address)),
+    );
+    let dir = env::temp_dir().join(format!("kraken_history_download_{}", std::process::id()));
+    let mut downloader = create_downloader(&kraken, HistoryStore::new(&dir));
+
+    let downloads = downloader
+        .download(&["XBTUSD"], &[Dataset::Trades, Dataset::Ohlc(1)])
+        .await
+        .unwrap();
+    assert_eq!(
+        downloads,
+        vec![
+            Download {
+                pair: "XBTUSD".to_string(),
+                dataset: Dataset::Trades,
+                requests: 4,
+                rows: 4,
+                cursor: Some(CURSOR_3.to_string()),
+            },
+            Download {
+                pair: "XBTUSD".to_string(),
+                dataset: Dataset::Ohlc(1),
+                requests: 2,
+                rows: 2,
+                cursor: Some("120".to_string()),
+            },
+        ]
+    );
+    // The call rejected by the rate limit was retried
+    assert_eq!(requests.lock().unwrap().len(), 7);
+
+    let trades = downloader.store().trades("XBTUSD").await.unwrap();
+    let ids: Vec<Option<u64>> = trades.iter().map(|trade| trade.trade_id).collect();
+    assert_eq!(ids, vec![Some(1), Some(2), Some(3), Some(4)]);
+    assert_eq!(trades[1].price, 50001.0);
+    // Without the uncommitted candle
+    let candles = downloader.store().candles("XBTUSD", 1).await.unwrap();
+    let times: Vec<i64> = candles.iter().map(|candle| candle.time.timestamp()).collect();
+    assert_eq!(times, vec![60, 120]);
+
+    // After a restart, the downloads go on from the stored cursors. The first request is kept so the calls
+    // aren't rejected again
+    requests.lock().unwrap().truncate(1);
+    let mut downloader = create_downloader(&kraken, HistoryStore::new(&dir));
+    let downloads = downloader
+        .download(&["XBTUSD"], &[Dataset::Trades, Dataset::Ohlc(1)])
+        .await
+        .unwrap();
+    assert_eq!((downloads[0].requests, downloads[0].rows), (1, 0));
+    assert_eq!((downloads[1].requests, downloads[1].rows), (1, 0));
+    assert_eq!(
+        requests.lock().unwrap()[1..].to_vec(),
+        vec![
+            "/0/public/Trades?pair=XBTUSD&since=1616663625000000000",
+            "/0/public/OHLC?pair=XBTUSD&interval=1&since=120",
+        ]
+    );
+    assert_eq!(downloader.store().trades("XBTUSD").await.unwrap().len(), 4);
+
+    fs::remove_dir_all(&dir).await.unwrap();
+}
@@ -1,7 +1,7 @@
 extern crate kraken_api;
 
-use kraken_api::{AssetPairs, Kraken};
-use tokio;
+use futures::StreamExt;
+use kraken_api::{registry::AssetRegistry, AssetPairs, Kraken, NewOrder, OrderType, ReportKind, Side};
 
 mod common;
 
@@ -13,18 +13,18 @@ async fn assets_api() {
 
     // Should return all the assets
     let response = kraken.assets(&[]).await;
-    assert_eq!(response.is_ok(), true, "Response: {:?}", response);
-    assert_eq!(response.unwrap().len() > 0, true);
+    assert!(response.is_ok(), "Response: {:?}", response);
+    assert!(!response.unwrap().is_empty());
 
     // Should return only the requested ALGO and ADA assets
     let response = kraken.assets(&[("asset", "algo,ada")]).await;
 
-    assert_eq!(response.is_ok(), true, "Response: {:?}", response);
+    assert!(response.is_ok(), "Response: {:?}", response);
     let response = response.unwrap();
 
     assert_eq!(response.len(), 2);
-    assert_eq!(response.contains_key("ALGO"), true);
-    assert_eq!(response.contains_key("ADA"), true);
+    assert!(response.contains_key("ALGO"));
+    assert!(response.contains_key("ADA"));
 }
 
 #[tokio::test]
@@ -32,11 +32,11 @@ async fn asset_pairs_api() {
     let kraken = Kraken::new(create_credentials(), create_urls());
 
     let response = kraken.asset_pairs(&[]).await;
-    assert_eq!(response.is_ok(), true, "Response: {:?}", response);
+    assert!(response.is_ok(), "Response: {:?}", response);
 
     match response.unwrap() {
         AssetPairs::Info(pairs) => {
-            assert_eq!(pairs.len() > 0, true,);
+            assert!(!pairs.is_empty());
         }
         _ => {
             panic!("Invalid response from asset_pairs_api with no params");
@@ -44,11 +44,11 @@ async fn asset_pairs_api() {
     }
 
     let response = kraken.asset_pairs(&[("pair", "XXRPZUSD"), ("info", "fees")]).await;
-    assert_eq!(response.is_ok(), true, "Response: {:?}", response);
+    assert!(response.is_ok(), "Response: {:?}", response);
 
     match response.unwrap() {
         AssetPairs::Fees(pairs) => {
-            assert_eq!(pairs.len() == 1, true,);
+            assert!(pairs.len() == 1);
         }
         _ => {
             panic!("Invalid response from asset_pairs_api with pair and fees params");
@@ -58,11 +58,11 @@ async fn asset_pairs_api() {
     let response = kraken
         .asset_pairs(&[("pair", "XXRPZUSD,XETHXXBT.d"), ("info", "margin")])
         .await;
-    assert_eq!(response.is_ok(), true, "Response: {:?}", response);
+    assert!(response.is_ok(), "Response: {:?}", response);
 
     match response.unwrap() {
         AssetPairs::Margin(pairs) => {
-            assert_eq!(pairs.len() == 2, true,);
+            assert!(pairs.len() == 2);
         }
         _ => {
             panic!("Invalid response from asset_pairs_api with pair and margin params");
@@ -70,18 +70,31 @@ async fn asset_pairs_api() {
     }
 }
 
+#[tokio::test]
+async fn asset_registry_api() {
+    let kraken = Kraken::new(create_credentials(), create_urls());
+    let response = AssetRegistry::fetch(&kraken).await;
+    assert!(response.is_ok(), "Response: {:?}", response);
+
+    let registry = response.unwrap();
+    assert_eq!(registry.resolve_asset("BTC").unwrap(), "XXBT");
+    assert_eq!(registry.resolve_pair("BTC/USD").unwrap(), "XXBTZUSD");
+    assert_eq!(registry.resolve_pair("XBTUSD").unwrap(), "XXBTZUSD");
+    assert_eq!(registry.pair_wsname("XXBTZUSD").unwrap(), "XBT/USD");
+}
+
 #[tokio::test]
 async fn ticker_api() {
     let kraken = Kraken::new(create_credentials(), create_urls());
 
     let response = kraken.ticker(&[("pair", "XXRPZUSD,ADAETH")]).await;
-    assert_eq!(response.is_ok(), true, "Response: {:?}", response);
+    assert!(response.is_ok(), "Response: {:?}", response);
 
     let response = response.unwrap();
 
-    assert_eq!(response.len() == 2, true);
-    assert_eq!(response.contains_key("XXRPZUSD"), true);
-    assert_eq!(response.contains_key("ADAETH"), true);
+    assert!(response.len() == 2);
+    assert!(response.contains_key("XXRPZUSD"));
+    assert!(response.contains_key("ADAETH"));
 }
 
 #[tokio::test]
@@ -89,82 +102,221 @@ async fn order_book_api() {
     let kraken = Kraken::new(create_credentials(), create_urls());
 
     let response = kraken.order_book(&[("pair", "XXRPZUSD")]).await;
-    assert_eq!(response.is_ok(), true, "Response: {:?}", response);
+    assert!(response.is_ok(), "Response: {:?}", response);
 
     let response = response.unwrap();
-    assert_eq!(response.len() == 1, true);
-    assert_eq!(response.contains_key("XXRPZUSD"), true);
+    assert!(response.len() == 1);
+    assert!(response.contains_key("XXRPZUSD"));
 
     let order_book = response.get("XXRPZUSD").unwrap();
-    assert_eq!(order_book.asks.len() > 0, true);
-    assert_eq!(order_book.bids.len() > 0, true);
+    assert!(!order_book.asks.is_empty());
+    assert!(!order_book.bids.is_empty());
 
     let response = kraken.order_book(&[("pair", "XXRPZUSD"), ("count", "2")]).await;
-    assert_eq!(response.is_ok(), true, "Response: {:?}", response);
+    assert!(response.is_ok(), "Response: {:?}", response);
 
     let response = response.unwrap();
-    assert_eq!(response.len() == 1, true);
-    assert_eq!(response.contains_key("XXRPZUSD"), true);
+    assert!(response.len() == 1);
+    assert!(response.contains_key("XXRPZUSD"));
 
     let order_book = response.get("XXRPZUSD").unwrap();
-    assert_eq!(order_book.asks.len() == 2, true);
-    assert_eq!(order_book.bids.len() == 2, true);
+    assert!(order_book.asks.len() == 2);
+    assert!(order_book.bids.len() == 2);
+}
+
+#[tokio::test]
+async fn ohlc_api() {
+    let kraken = Kraken::new(create_credentials(), create_urls());
+
+    let response = kraken.ohlc(&[("pair", "XXRPZUSD")]).await;
+    assert!(response.is_ok(), "Response: {:?}", response);
+
+    let response = response.unwrap();
+    assert!(response.pairs.contains_key("XXRPZUSD"));
+    assert!(!response.pairs.get("XXRPZUSD").unwrap().is_empty());
+
+    let response = kraken.ohlc(&[("pair", "XXRPZUSD"), ("interval", "60")]).await;
+    assert!(response.is_ok(), "Response: {:?}", response);
+}
+
+#[tokio::test]
+async fn recent_trades_api() {
+    let kraken = Kraken::new(create_credentials(), create_urls());
+
+    let response = kraken.recent_trades(&[("pair", "XXRPZUSD")]).await;
+    assert!(response.is_ok(), "Response: {:?}", response);
+
+    let response = response.unwrap();
+    assert!(response.pairs.contains_key("XXRPZUSD"));
 }
 
 #[tokio::test]
 async fn account_balance_api() {
     let kraken = Kraken::new(create_credentials(), create_urls());
     let response = kraken.account_balance(&[]).await;
-    assert_eq!(response.is_ok(), true, "Response: {:?}", response);
+    assert!(response.is_ok(), "Response: {:?}", response);
 
     let response = response.unwrap();
-    assert_eq!(response.contains_key("ZUSD"), true);
+    assert!(response.contains_key("ZUSD"));
 }
 
 #[tokio::test]
 async fn trade_balance_api() {
     let kraken = Kraken::new(create_credentials(), create_urls());
     let response = kraken.trade_balance(&[]).await;
-    assert_eq!(response.is_ok(), true, "Response: {:?}", response);
+    assert!(response.is_ok(), "Response: {:?}", response);
 
     let response = kraken.trade_balance(&[("asset", "ZUSD")]).await;
-    assert_eq!(response.is_ok(), true, "Response: {:?}", response);
+    assert!(response.is_ok(), "Response: {:?}", response);
 }
 
 #[tokio::test]
 async fn open_orders_api() {
     let kraken = Kraken::new(create_credentials(), create_urls());
     let response = kraken.open_orders(&[]).await;
-    assert_eq!(response.is_ok(), true, "Response: {:?}", response);
+    assert!(response.is_ok(), "Response: {:?}", response);
 
     let response = kraken.open_orders(&[("trades", "true")]).await;
-    assert_eq!(response.is_ok(), true, "Response: {:?}", response);
+    assert!(response.is_ok(), "Response: {:?}", response);
 
     let response = kraken.open_orders(&[("userref", "0")]).await;
-    assert_eq!(response.is_ok(), true, "Response: {:?}", response);
+    assert!(response.is_ok(), "Response: {:?}", response);
 }
 
 #[tokio::test]
 async fn closed_orders_api() {
     let kraken = Kraken::new(create_credentials(), create_urls());
     let response = kraken.closed_orders(&[]).await;
-    assert_eq!(response.is_ok(), true, "Response: {:?}", response);
+    assert!(response.is_ok(), "Response: {:?}", response);
 
     let response = kraken.closed_orders(&[("trades", "true")]).await;
-    assert_eq!(response.is_ok(), true, "Response: {:?}", response);
+    assert!(response.is_ok(), "Response: {:?}", response);
 
     let response = kraken.closed_orders(&[("userref", "0")]).await;
-    assert_eq!(response.is_ok(), true, "Response: {:?}", response);
+    assert!(response.is_ok(), "Response: {:?}", response);
 
     let response = kraken.closed_orders(&[("start", "0")]).await;
-    assert_eq!(response.is_ok(), true, "Response: {:?}", response);
+    assert!(response.is_ok(), "Response: {:?}", response);
 
     let response = kraken.closed_orders(&[("end", "0")]).await;
-    assert_eq!(response.is_ok(), true, "Response: {:?}", response);
+    assert!(response.is_ok(), "Response: {:?}", response);
 
     let response = kraken.closed_orders(&[("ofs", "1")]).await;
-    assert_eq!(response.is_ok(), true, "Response: {:?}", response);
+    assert!(response.is_ok(), "Response: {:?}", response);
 
     let response = kraken.closed_orders(&[("closetime", "close")]).await;
-    assert_eq!(response.is_ok(), true, "Response: {:?}", response);
+    assert!(response.is_ok(), "Response: {:?}", response);
+
+    let response = response.unwrap();
+    let closed: Vec<_> = kraken.closed_orders_stream(&[]).collect().await;
+    assert!(closed.iter().all(|order| order.is_ok()));
+    assert_eq!(closed.len() as u64, response.count);
+}
+
+#[tokio::test]
+async fn trades_history_api() {
+    let kraken = Kraken::new(create_credentials(), create_urls());
+    let response = kraken.trades_history(&[]).await;
+    assert!(response.is_ok(), "Response: {:?}", response);
+
+    let response = kraken.trades_history(&[("type", "no position")]).await;
+    assert!(response.is_ok(), "Response: {:?}", response);
+}
+
+#[tokio::test]
+async fn ledgers_api() {
+    let kraken = Kraken::new(create_credentials(), create_urls());
+    let response = kraken.ledgers(&[]).await;
+    assert!(response.is_ok(), "Response: {:?}", response);
+
+    let response = kraken.ledgers(&[("asset", "ZUSD")]).await;
+    assert!(response.is_ok(), "Response: {:?}", response);
+}
+
+#[tokio::test]
+async fn deposit_methods_api() {
+    let kraken = Kraken::new(create_credentials(), create_urls());
+    let response = kraken.deposit_methods("XBT").await;
+    assert!(response.is_ok(), "Response: {:?}", response);
+    assert!(!response.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn deposit_addresses_api() {
+    let kraken = Kraken::new(create_credentials(), create_urls());
+    let response = kraken.deposit_addresses("XBT", "Bitcoin", false).await;
+    assert!(response.is_ok(), "Response: {:?}", response);
+}
+
+#[tokio::test]
+async fn deposit_status_api() {
+    let kraken = Kraken::new(create_credentials(), create_urls());
+    let response = kraken.deposit_status("XBT", "Bitcoin").await;
+    assert!(response.is_ok(), "Response: {:?}", response);
+}
+
+#[tokio::test]
+async fn withdraw_status_api() {
+    let kraken = Kraken::new(create_credentials(), create_urls());
+    let response = kraken.withdraw_status("XBT", None).await;
+    assert!(response.is_ok(), "Response: {:?}", response);
+
+    let response = kraken.withdraw_status("XBT", Some("Bitcoin")).await;
+    assert!(response.is_ok(), "Response: {:?}", response);
+}
+
+#[tokio::test]
+async fn export_status_api() {
+    let kraken = Kraken::new(create_credentials(), create_urls());
+    let response = kraken.export_status(ReportKind::Trades).await;
+    assert!(response.is_ok(), "Response: {:?}", response);
+
+    let response = kraken.export_status(ReportKind::Ledgers).await;
+    assert!(response.is_ok(), "Response: {:?}", response);
+}
+
+#[tokio::test]
+async fn staking_api() {
+    let kraken = Kraken::new(create_credentials(), create_urls());
+    let response = kraken.stakeable_assets().await;
+    assert!(response.is_ok(), "Response: {:?}", response);
+
+    let response = kraken.staking_pending().await;
+    assert!(response.is_ok(), "Response: {:?}", response);
+
+    let response = kraken.staking_transactions().await;
+    assert!(response.is_ok(), "Response: {:?}", response);
+}
+
+#[tokio::test]
+async fn websockets_token_api() {
+    let kraken = Kraken::new(create_credentials(), create_urls());
+    let response = kraken.websockets_token().await;
+    assert!(response.is_ok(), "Response: {:?}", response);
+
+    let response = response.unwrap();
+    assert!(!response.token.is_empty());
+    assert!(response.expires > 0);
+}
+
+#[tokio::test]
+async fn trade_volume_api() {
+    let kraken = Kraken::new(create_credentials(), create_urls());
+    let response = kraken.trade_volume(&["XXBTZUSD"]).await;
+    assert!(response.is_ok(), "Response: {:?}", response);
+
+    let response = response.unwrap();
+    assert!(response.volume >= 0.0);
+    assert!(response.fees.contains_key("XXBTZUSD"));
+}
+
+#[tokio::test]
+async fn add_order_api() {
+    let kraken = Kraken::new(create_credentials(), create_urls());
+    let mut order = NewOrder::new("XBTUSD", Side::Buy, OrderType::Limit, 0.01);
+    order.price = Some(1000.0);
+    order.validate = true;
+    let response = kraken.add_order(&order).await;
+    assert!(response.is_ok(), "Response: {:?}", response);
+    assert!(response.unwrap().txid.is_empty());
 }
@@ -0,0 +1,398 @@
+extern crate kraken_api;
+
+use futures::{SinkExt, StreamExt};
+use kraken_api::{
+    book::LocalOrderBook,
+    registry::AssetRegistry,
+    trading::{Trader, Transport},
+    ws::{Channel, Client, Config, Event, Events},
+    Credentials, Errors, Kraken, NewOrder, OrderType, Side, Urls,
+};
+use serde_json::Value;
+use std::time::Duration;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+use tokio_tungstenite::{accept_async, tungstenite::Message, WebSocketStream};
+
+async fn bind() -> (TcpListener, String) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    (listener, address)
+}
+
+async fn accept(listener: &mut TcpListener) -> WebSocketStream<TcpStream> {
+    let (stream, _) = timeout(Duration::from_secs(5), listener.accept())
+        .await
+        .unwrap()
+        .unwrap();
+    let mut socket = accept_async(stream).await.unwrap();
+    let status = r#"{"connectionID": 1, "event": "systemStatus", "status": "online", "version": "1.0.0"}"#;
+    socket.send(Message::Text(status.to_string())).await.unwrap();
+
+    socket
+}
+
+async fn receive_json(socket: &mut WebSocketStream<TcpStream>) -> Value {
+    loop {
+        let message = timeout(Duration::from_secs(5), socket.next()).await.unwrap();
+        if let Some(Ok(Message::Text(text))) = message {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+async fn next_event(events: &mut Events) -> Event {
+    timeout(Duration::from_secs(5), events.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap()
+}
+
+async fn next_event_result(events: &mut Events) -> Result<Event, Errors> {
+    timeout(Duration::from_secs(5), events.next()).await.unwrap().unwrap()
+}
+
+fn create_config(address: &str) -> Config {
+    let mut config = Config::new(&format!("ws://{}", address));
+    config.reconnect_delay = Duration::from_millis(100);
+    config.ping_interval = Duration::from_secs(60);
+
+    config
+}
+
+fn create_registry() -> AssetRegistry {
+    let assets = serde_json::from_str(
+        r#"{
+            "XXBT": {"aclass": "currency", "altname": "XBT", "decimals": 10, "display_decimals": 5},
+            "ZUSD": {"aclass": "currency", "altname": "USD", "decimals": 4, "display_decimals": 2}
+        }"#,
+    )
+    .unwrap();
+    let pairs = serde_json::from_str(
+        r#"{
+            "XXBTZUSD": {
+                "altname": "XBTUSD", "wsname": "XBT/USD", "aclass_base": "currency", "base": "XXBT",
+                "aclass_quote": "currency", "quote": "ZUSD", "lot": "unit", "pair_decimals": 1, "lot_decimals": 8,
+                "lot_multiplier": 1, "leverage_buy": [], "leverage_sell": [], "fees": [[0, 0.26]],
+                "fees_maker": [[0, 0.16]], "fee_volume_currency": "ZUSD", "margin_call": 80, "margin_stop": 40
+            }
+        }"#,
+    )
+    .unwrap();
+
+    AssetRegistry::new(assets, pairs)
+}
+
+#[tokio::test]
+async fn subscribe_and_reconnect() {
+    let (mut listener, address) = bind().await;
+    let (client, mut events) = Client::new(create_config(&address));
+
+    let mut socket = accept(&mut listener).await;
+    assert!(matches!(next_event(&mut events).await, Event::SystemStatus(status) if status.status == "online"));
+
+    client.subscribe(Channel::Ticker, &["XBT/USD"]).unwrap();
+    let subscribe = receive_json(&mut socket).await;
+    assert_eq!(subscribe["event"], "subscribe");
+    assert_eq!(subscribe["pair"][0], "XBT/USD");
+    assert_eq!(subscribe["subscription"]["name"], "ticker");
+
+    let status = format!(
+        r#"{{"channelID": 10, "channelName": "ticker", "event": "subscriptionStatus", "pair": "XBT/USD",
+            "reqid": {}, "status": "subscribed", "subscription": {{"name": "ticker"}}}}"#,
+        subscribe["reqid"]
+    );
+    socket.send(Message::Text(status)).await.unwrap();
+    match next_event(&mut events).await {
+        Event::SubscriptionStatus(status) => {
+            assert_eq!(status.status, "subscribed");
+            assert_eq!(status.reqid, subscribe["reqid"].as_u64());
+        }
+        event => panic!("Unexpected event {:?}", event),
+    }
+
+    let ticker = r#"[10, {
+        "a": ["5525.40000", 1, "1.000"], "b": ["5525.10000", 1, "1.000"], "c": ["5525.10000", "0.00398963"],
+        "v": ["2634.11501494", "3591.17907851"], "p": ["5631.44067", "5653.78939"], "t": [11493, 16267],
+        "l": ["5505.00000", "5505.00000"], "h": ["5783.00000", "5783.00000"], "o": ["5760.70000", "5763.40000"]
+    }, "ticker", "XBT/USD"]"#;
+    socket.send(Message::Text(ticker.to_string())).await.unwrap();
+    match next_event(&mut events).await {
+        Event::Ticker { pair, ticker } => {
+            assert_eq!(pair, "XBT/USD");
+            assert_eq!(ticker.b[0], 5525.1);
+        }
+        event => panic!("Unexpected event {:?}", event),
+    }
+
+    socket
+        .send(Message::Text(r#"{"event": "heartbeat"}"#.to_string()))
+        .await
+        .unwrap();
+    assert!(matches!(next_event(&mut events).await, Event::Heartbeat));
+
+    let reqid = client.ping().unwrap();
+    let ping = receive_json(&mut socket).await;
+    assert_eq!(ping["event"], "ping");
+    assert_eq!(ping["reqid"].as_u64(), Some(reqid));
+    let pong = format!(r#"{{"event": "pong", "reqid": {}}}"#, reqid);
+    socket.send(Message::Text(pong)).await.unwrap();
+    assert!(matches!(next_event(&mut events).await, Event::Pong { reqid: Some(id) } if id == reqid));
+
+    // Dropping the connection makes the client reconnect and renew the subscriptions
+    drop(socket);
+    assert!(next_event_result(&mut events).await.is_err());
+
+    let mut socket = accept(&mut listener).await;
+    let subscribe = receive_json(&mut socket).await;
+    assert_eq!(subscribe["event"], "subscribe");
+    assert_eq!(subscribe["subscription"]["name"], "ticker");
+    assert!(matches!(next_event(&mut events).await, Event::Reconnected));
+
+    // Events received before closing are still delivered, after them the stream ends
+    client.close().unwrap();
+    while let Some(event) = timeout(Duration::from_secs(5), events.next()).await.unwrap() {
+        assert!(matches!(event, Ok(Event::SystemStatus(_))));
+    }
+}
+
+#[tokio::test]
+async fn partial_unsubscribe_and_reconnect() {
+    let (mut listener, address) = bind().await;
+    let (client, mut events) = Client::new(create_config(&address));
+
+    let mut socket = accept(&mut listener).await;
+    assert!(matches!(next_event(&mut events).await, Event::SystemStatus(_)));
+
+    client.subscribe(Channel::Ticker, &["XBT/USD", "ETH/USD"]).unwrap();
+    client.subscribe(Channel::Spread, &["ETH/USD"]).unwrap();
+    client.unsubscribe(Channel::Ticker, &["ETH/USD"]).unwrap();
+    for event in &["subscribe", "subscribe", "unsubscribe"] {
+        assert_eq!(receive_json(&mut socket).await["event"], *event);
+    }
+
+    // Only the pairs still subscribed are renewed, the other channels keep theirs
+    drop(socket);
+    assert!(next_event_result(&mut events).await.is_err());
+
+    let mut socket = accept(&mut listener).await;
+    let ticker = receive_json(&mut socket).await;
+    assert_eq!(ticker["subscription"]["name"], "ticker");
+    assert_eq!(ticker["pair"], serde_json::json!(["XBT/USD"]));
+    let spread = receive_json(&mut socket).await;
+    assert_eq!(spread["subscription"]["name"], "spread");
+    assert_eq!(spread["pair"], serde_json::json!(["ETH/USD"]));
+    assert!(matches!(next_event(&mut events).await, Event::Reconnected));
+    assert!(matches!(next_event(&mut events).await, Event::SystemStatus(_)));
+
+    // Unsubscribing the last pair drops the subscription
+    client.unsubscribe(Channel::Ticker, &["XBT/USD"]).unwrap();
+    assert_eq!(receive_json(&mut socket).await["event"], "unsubscribe");
+    drop(socket);
+    assert!(next_event_result(&mut events).await.is_err());
+
+    let mut socket = accept(&mut listener).await;
+    let spread = receive_json(&mut socket).await;
+    assert_eq!(spread["subscription"]["name"], "spread");
+    assert!(matches!(next_event(&mut events).await, Event::Reconnected));
+    client.close().unwrap();
+}
+
+#[tokio::test]
+async fn heartbeat_timeout() {
+    let (mut listener, address) = bind().await;
+    let mut config = create_config(&address);
+    config.heartbeat_timeout = Duration::from_millis(300);
+    let (_client, mut events) = Client::new(config);
+
+    // The connection is kept open but stays silent after the system status
+    let _socket = accept(&mut listener).await;
+    assert!(matches!(next_event(&mut events).await, Event::SystemStatus(_)));
+    assert!(matches!(
+        next_event_result(&mut events).await,
+        Err(Errors::Disconnected(_))
+    ));
+
+    let _socket = accept(&mut listener).await;
+    assert!(matches!(next_event(&mut events).await, Event::Reconnected));
+}
+
+#[tokio::test]
+async fn private_feeds() {
+    let (mut listener, address) = bind().await;
+    let (client, mut events) = Client::new(create_config(&address));
+
+    let mut socket = accept(&mut listener).await;
+    assert!(matches!(next_event(&mut events).await, Event::SystemStatus(_)));
+
+    client.subscribe(Channel::OpenOrders, &[]).unwrap();
+    assert!(matches!(
+        next_event_result(&mut events).await,
+        Err(Errors::InvalidParams(_))
+    ));
+
+    client.set_token("token").unwrap();
+    client.subscribe(Channel::OpenOrders, &[]).unwrap();
+    let subscribe = receive_json(&mut socket).await;
+    assert_eq!(subscribe["subscription"]["name"], "openOrders");
+    assert_eq!(subscribe["subscription"]["token"], "token");
+    assert!(subscribe.get("pair").is_none());
+
+    for sequence in &[1, 2, 4] {
+        let update = format!(
+            r#"[[{{"OGTT3Y-C6I3P-XRI6HX": {{"status": "canceled", "cancel_reason": "User requested"}}}}],
+                "openOrders", {{"sequence": {}}}]"#,
+            sequence
+        );
+        socket.send(Message::Text(update)).await.unwrap();
+    }
+    for sequence in 1..=2 {
+        assert!(matches!(next_event(&mut events).await, Event::OpenOrders { sequence: s, .. } if s == sequence));
+    }
+    assert!(matches!(
+        next_event_result(&mut events).await,
+        Err(Errors::SequenceGap {
+            expected: 3,
+            received: 4,
+            ..
+        })
+    ));
+    match next_event(&mut events).await {
+        Event::OpenOrders { orders, sequence } => {
+            assert_eq!(sequence, 4);
+            assert_eq!(orders[0].txid(), "OGTT3Y-C6I3P-XRI6HX");
+        }
+        event => panic!("Unexpected event {:?}", event),
+    }
+}
+
+#[tokio::test]
+async fn trading() {
+    let (mut listener, address) = bind().await;
+    let mut config = create_config(&address);
+    config.token = Some("token".to_string());
+    let (client, mut events) = Client::new(config);
+
+    let mut socket = accept(&mut listener).await;
+    assert!(matches!(next_event(&mut events).await, Event::SystemStatus(_)));
+
+    let kraken = Kraken::new(
+        Credentials::new(String::new(), String::new()),
+        Urls::new("http://127.0.0.1:1"),
+    );
+    let registry = create_registry();
+    let trader = Trader::new(Transport::WebSocket, &kraken, Some(&client), Some(&registry)).unwrap();
+    let mut order = NewOrder::new("BTCUSD", Side::Buy, OrderType::Limit, 1.5);
+    order.price = Some(8000.0);
+
+    // REST orders are resolved by the registry of the Kraken client, if it has one
+    let rest_trader = Trader::new(Transport::Rest, &kraken, None, Some(&registry)).unwrap();
+    assert_eq!(rest_trader.translate(&order).unwrap().pair, "BTCUSD");
+    let rest_kraken = Kraken::new(
+        Credentials::new(String::new(), String::new()),
+        Urls::new("http://127.0.0.1:1"),
+    )
+    .with_registry(create_registry());
+    let rest_trader = Trader::new(Transport::Rest, &rest_kraken, None, None).unwrap();
+    assert_eq!(rest_trader.translate(&order).unwrap().pair, "XXBTZUSD");
+    let add = trader.add_order(&order);
+    let cancel = trader.cancel_order("OUF4EM-FRGI2-MQMWZD");
+    let cancel_all = trader.cancel_all();
+
+    // The responses are sent in reverse order, each one has to reach the request with its reqid
+    let server = async {
+        let mut requests = Vec::new();
+        for _ in 0..3 {
+            requests.push(receive_json(&mut socket).await);
+        }
+        assert_eq!(requests[0]["event"], "addOrder");
+        assert_eq!(requests[0]["token"], "token");
+        assert_eq!(requests[0]["pair"], "XBT/USD");
+        assert_eq!(requests[0]["type"], "buy");
+        assert_eq!(requests[0]["price"], "8000");
+        assert_eq!(requests[1]["txid"][0], "OUF4EM-FRGI2-MQMWZD");
+
+        let responses = [
+            format!(
+                r#"{{"event": "cancelAllStatus", "count": 2, "status": "ok", "reqid": {}}}"#,
+                requests[2]["reqid"]
+            ),
+            format!(
+                r#"{{"event": "cancelOrderStatus", "status": "error", "errorMessage": "EOrder:Unknown order",
+                    "reqid": {}}}"#,
+                requests[1]["reqid"]
+            ),
+            format!(
+                r#"{{"event": "addOrderStatus", "status": "ok", "txid": "ONPNXH-KMKMU-F4MR5V",
+                    "descr": "buy 1.50000000 XBTUSD @ limit 8000.0, close position @ stop loss 7500.0",
+                    "reqid": {}}}"#,
+                requests[0]["reqid"]
+            ),
+        ];
+        for response in responses.iter() {
+            socket.send(Message::Text(response.clone())).await.unwrap();
+        }
+    };
+    let (added, canceled, canceled_all, _) = futures::join!(add, cancel, cancel_all, server);
+
+    let added = added.unwrap();
+    assert_eq!(added.txid, vec!["ONPNXH-KMKMU-F4MR5V"]);
+    assert_eq!(added.description, "buy 1.50000000 XBTUSD @ limit 8000.0");
+    assert_eq!(added.close.as_deref(), Some("close position @ stop loss 7500.0"));
+    assert!(matches!(canceled, Err(Errors::Kraken(error)) if error == "EOrder:Unknown order"));
+    assert_eq!(canceled_all.unwrap(), 2);
+
+    // Kraken doesn't answer the request
+    let mut config = create_config(&address);
+    config.token = Some("token".to_string());
+    config.request_timeout = Duration::from_millis(100);
+    let (client, mut events) = Client::new(config);
+    let mut socket = accept(&mut listener).await;
+    assert!(matches!(next_event(&mut events).await, Event::SystemStatus(_)));
+
+    let cancel = client.cancel_order("OUF4EM-FRGI2-MQMWZD");
+    let (canceled, request) = futures::join!(cancel, receive_json(&mut socket));
+    assert_eq!(request["event"], "cancelOrder");
+    assert!(matches!(canceled, Err(Errors::Timeout(_))));
+}
+
+#[tokio::test]
+async fn book_resync() {
+    let (mut listener, address) = bind().await;
+    let (client, mut events) = Client::new(create_config(&address));
+    let mut book = LocalOrderBook::subscribe(&client, "XBT/EUR", 10).unwrap();
+
+    let mut socket = accept(&mut listener).await;
+    assert!(matches!(next_event(&mut events).await, Event::SystemStatus(_)));
+    let subscribe = receive_json(&mut socket).await;
+    assert_eq!(subscribe["subscription"]["name"], "book");
+    assert_eq!(subscribe["subscription"]["depth"], 10);
+
+    let snapshot = r#"[0, {"as": [["0.05005", "0.00000500", "1582905487.684110"]],
+        "bs": [["0.05000", "0.00000500", "1582905487.439814"]]}, "book-10", "XBT/EUR"]"#;
+    socket.send(Message::Text(snapshot.to_string())).await.unwrap();
+    assert!(book.apply(&next_event(&mut events).await).unwrap());
+
+    let update = r#"[0, {"a": [["0.05005", "0.00000400", "1582905489.001000"]], "c": "1"}, "book-10", "XBT/EUR"]"#;
+    socket.send(Message::Text(update.to_string())).await.unwrap();
+    assert!(matches!(
+        book.apply(&next_event(&mut events).await),
+        Err(Errors::ChecksumMismatch { .. })
+    ));
+
+    // A fresh snapshot is requested
+    let unsubscribe = receive_json(&mut socket).await;
+    assert_eq!(unsubscribe["event"], "unsubscribe");
+    assert_eq!(unsubscribe["pair"][0], "XBT/EUR");
+    let subscribe = receive_json(&mut socket).await;
+    assert_eq!(subscribe["event"], "subscribe");
+    assert_eq!(subscribe["subscription"]["depth"], 10);
+
+    socket.send(Message::Text(snapshot.to_string())).await.unwrap();
+    assert!(book.apply(&next_event(&mut events).await).unwrap());
+    assert!(book.is_synced());
+}